@@ -90,6 +90,51 @@ pub struct Cli {
     /// Setup Rust documentation RAG
     #[clap(long)]
     pub setup_rust_docs: bool,
+    /// Run all lesson examples and quiz solutions, report as json or junit
+    #[clap(long, value_name = "FORMAT")]
+    pub check_lessons: Option<Option<String>>,
+    /// Draft translations of lessons and quizzes into a locale for review
+    #[clap(long, value_name = "LOCALE")]
+    pub translate_lessons: Option<String>,
+    /// Print a learner's progress, quiz answers, solutions and tutor sessions as JSON
+    #[clap(long, value_name = "LEARNER")]
+    pub export_progress: Option<String>,
+    /// Merge a progress bundle made by --export-progress, newest copies win
+    #[clap(long, value_name = "FILE")]
+    pub import_progress: Option<String>,
+    /// Learner for --import-progress, defaults to the one in the bundle
+    #[clap(long, value_name = "LEARNER")]
+    pub progress_learner: Option<String>,
+    /// Check the signature of a completion record saved from a certificate
+    #[clap(long, value_name = "FILE")]
+    pub verify_certificate: Option<String>,
+    /// Create or update a server account, prompting for its password
+    #[clap(long, value_name = "USERNAME")]
+    pub add_account: Option<String>,
+    /// Role for --add-account: learner, instructor or admin
+    #[clap(long, value_name = "ROLE")]
+    pub account_role: Option<String>,
+    /// Cohort for --add-account, used to group learners in the instructor dashboard
+    #[clap(long, value_name = "COHORT")]
+    pub account_cohort: Option<String>,
+    /// Remove a server account and revoke its tokens
+    #[clap(long, value_name = "USERNAME")]
+    pub remove_account: Option<String>,
+    /// List all server accounts and tokens
+    #[clap(long)]
+    pub list_accounts: bool,
+    /// Issue a bearer token for a server account
+    #[clap(long, value_name = "USERNAME")]
+    pub issue_token: Option<String>,
+    /// Revoke a bearer token by its id
+    #[clap(long, value_name = "TOKEN_ID")]
+    pub revoke_token: Option<String>,
+    /// Report token usage and cost grouped by day, model or session
+    #[clap(long, value_name = "BY")]
+    pub usage: Option<Option<String>>,
+    /// Print the --usage report as JSON
+    #[clap(long)]
+    pub usage_json: bool,
     /// Input text
     #[clap(trailing_var_arg = true)]
     text: Vec<String>,
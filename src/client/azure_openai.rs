@@ -53,7 +53,8 @@ fn prepare_chat_completions(
         self_.model.real_name()
     );
 
-    let body = openai_build_chat_completions_body(data, &self_.model);
+    let mut body = openai_build_chat_completions_body(data, &self_.model);
+    openai_include_stream_usage(&mut body);
 
     let mut request_data = RequestData::new(url, body);
 
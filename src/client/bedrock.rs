@@ -275,6 +275,12 @@ async fn chat_completions_streaming(
                                 ))?;
                             }
                         }
+                        "metadata" => {
+                            handler.set_usage(
+                                data["usage"]["inputTokens"].as_u64(),
+                                data["usage"]["outputTokens"].as_u64(),
+                            );
+                        }
                         _ => {}
                     }
                 }
@@ -304,7 +310,7 @@ async fn embeddings(builder: RequestBuilder) -> Result<EmbeddingsOutput> {
 
     let res_body: EmbeddingsResBody =
         serde_json::from_value(data).context("Invalid embeddings data")?;
-    Ok(res_body.embeddings)
+    Ok(EmbeddingsOutput::new(res_body.embeddings))
 }
 
 #[derive(Deserialize)]
@@ -518,6 +524,7 @@ fn extract_chat_completions(data: &Value) -> Result<ChatCompletionsOutput> {
         id: None,
         input_tokens: data["usage"]["inputTokens"].as_u64(),
         output_tokens: data["usage"]["outputTokens"].as_u64(),
+        ..Default::default()
     };
     Ok(output)
 }
@@ -0,0 +1,324 @@
+use super::*;
+
+use crate::config::{write_file_atomic, Config, Role};
+use crate::utils::sha256;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs::{self, read_to_string},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+/// Hits and misses since the process started, shown in `.info`.
+static STATS: LazyLock<Mutex<CacheStats>> = LazyLock::new(Default::default);
+
+/// Size of each cache directory as far as this process knows, so writes only list the
+/// directory once it may be over the cap.
+static SIZES: LazyLock<Mutex<HashMap<PathBuf, u64>>> = LazyLock::new(Default::default);
+
+/// Responses of chat completions, embeddings and rerank calls, stored on disk under a hash of
+/// the model id and the request. Enabled with `cache: true` in config.yaml.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: i64,
+    max_size: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CacheStats {
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry<T> {
+    at: i64,
+    value: T,
+}
+
+/// A chat response, as replayed to a later identical request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedChat {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl ResponseCache {
+    /// The cache for a call, unless it is disabled, this is a dry run or the role opts out.
+    pub fn init(config: &Config, role: Option<&Role>) -> Option<Self> {
+        if !config.cache || config.dry_run || role.and_then(|v| v.cache()) == Some(false) {
+            return None;
+        }
+        Some(Self::new(
+            &Config::cache_dir(),
+            config.cache_ttl as i64,
+            config.cache_max_size * 1024 * 1024,
+        ))
+    }
+
+    /// The cache and key for a chat call. Sampled answers differ from one call to the next,
+    /// so they're only cached at `temperature: 0` or when the role opts in with `cache: true`.
+    pub fn init_chat(
+        config: &Config,
+        role: &Role,
+        model: &Model,
+        data: &ChatCompletionsData,
+    ) -> Option<(Self, String)> {
+        if data.temperature != Some(0.0) && role.cache() != Some(true) {
+            return None;
+        }
+        Some((Self::init(config, Some(role))?, Self::chat_key(model, data)))
+    }
+
+    pub fn new(dir: &Path, ttl: i64, max_size: u64) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            ttl,
+            max_size,
+        }
+    }
+
+    pub fn chat_key(model: &Model, data: &ChatCompletionsData) -> String {
+        // Whitespace around a prompt doesn't change the answer
+        let messages: Vec<_> = data
+            .messages
+            .iter()
+            .map(|message| {
+                let mut message = message.clone();
+                if let MessageContent::Text(text) = &mut message.content {
+                    *text = text.trim().to_string();
+                }
+                message
+            })
+            .collect();
+        Self::key(
+            "chat",
+            model,
+            &json!({
+                "messages": messages,
+                "temperature": data.temperature,
+                "top_p": data.top_p,
+                "functions": data.functions,
+                "max_tokens": model.max_tokens_param(),
+            }),
+        )
+    }
+
+    pub fn embedding_key(model: &Model, text: &str, query: bool) -> String {
+        Self::key(
+            "embeddings",
+            model,
+            &json!({ "text": text, "query": query }),
+        )
+    }
+
+    pub fn rerank_key(model: &Model, data: &RerankData) -> String {
+        Self::key(
+            "rerank",
+            model,
+            &json!({
+                "query": data.query,
+                "documents": data.documents,
+                "top_n": data.top_n,
+            }),
+        )
+    }
+
+    fn key(kind: &str, model: &Model, data: &Value) -> String {
+        sha256(&json!({ "kind": kind, "model": model.id(), "data": data }).to_string())
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.read(key);
+        let mut stats = STATS.lock();
+        match value.is_some() {
+            true => stats.hits += 1,
+            false => stats.misses += 1,
+        }
+        value
+    }
+
+    fn read<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let path = self.entry_path(key);
+        let content = read_to_string(&path).ok()?;
+        match serde_json::from_str::<CacheEntry<T>>(&content) {
+            Ok(entry) if Utc::now().timestamp() - entry.at < self.ttl => Some(entry.value),
+            _ => {
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Store a response, then evict the oldest entries beyond the size cap. Failures only
+    /// warn, as the response has already been received.
+    pub fn put<T: Serialize>(&self, key: &str, value: T) {
+        if let Err(err) = self.write(key, value) {
+            warn!("Failed to cache response, {err}");
+        }
+    }
+
+    fn write<T: Serialize>(&self, key: &str, value: T) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create '{}'", self.dir.display()))?;
+        let entry = CacheEntry {
+            at: Utc::now().timestamp(),
+            value,
+        };
+        let path = self.entry_path(key);
+        let content = serde_json::to_string(&entry)?;
+        let replaced = fs::metadata(&path).map(|v| v.len()).unwrap_or_default();
+        write_file_atomic(&path, &content)?;
+
+        let mut sizes = SIZES.lock();
+        let size = match sizes.get_mut(&self.dir) {
+            Some(size) => {
+                *size = size.saturating_sub(replaced) + content.len() as u64;
+                *size
+            }
+            None => self.entries()?.iter().map(|(_, v)| v.len()).sum(),
+        };
+        let size = if size > self.max_size {
+            self.prune()?
+        } else {
+            size
+        };
+        sizes.insert(self.dir.clone(), size);
+        Ok(())
+    }
+
+    /// Evict the oldest entries beyond the size cap, and return the size that is left.
+    fn prune(&self) -> Result<u64> {
+        let mut entries = self.entries()?;
+        let mut size: u64 = entries.iter().map(|(_, metadata)| metadata.len()).sum();
+        entries.sort_by_key(|(_, metadata)| metadata.modified().ok());
+        for (path, metadata) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            fs::remove_file(&path)?;
+            size = size.saturating_sub(metadata.len());
+        }
+        Ok(size)
+    }
+
+    fn entries(&self) -> Result<Vec<(PathBuf, fs::Metadata)>> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Ok(vec![]);
+        };
+        let mut entries = vec![];
+        for entry in dir.flatten() {
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                entries.push((entry.path(), metadata));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// The line shown for `cache` in `.info`.
+    pub fn info(config: &Config) -> String {
+        if !config.cache {
+            return "false".into();
+        }
+        let CacheStats { hits, misses } = *STATS.lock();
+        let cache = Self::new(&Config::cache_dir(), 0, 0);
+        let entries = cache.entries().unwrap_or_default();
+        let size: u64 = entries.iter().map(|(_, metadata)| metadata.len()).sum();
+        format!(
+            "true ({hits} hits, {misses} misses, {} entries, {:.1} MB)",
+            entries.len(),
+            size as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_cache() {
+        let dir = std::env::temp_dir().join(format!("aichat-cache-{}", std::process::id()));
+        let model = Model::new("test", "chat");
+        let data = |text: &str, temperature| ChatCompletionsData {
+            messages: vec![Message::new(
+                MessageRole::User,
+                MessageContent::Text(text.into()),
+            )],
+            temperature,
+            top_p: None,
+            functions: None,
+            stream: false,
+        };
+        let key = ResponseCache::chat_key(&model, &data("hello", Some(0.0)));
+        assert_eq!(
+            key,
+            ResponseCache::chat_key(&model, &data(" hello\n", Some(0.0)))
+        );
+        assert_ne!(
+            key,
+            ResponseCache::chat_key(&model, &data("hello", Some(0.5)))
+        );
+        assert_ne!(
+            key,
+            ResponseCache::chat_key(&Model::new("test", "other"), &data("hello", Some(0.0)))
+        );
+
+        // Sampled answers are only cached when the role asks for it
+        let config = Config {
+            cache: true,
+            ..Default::default()
+        };
+        let role = Role::new("test", "");
+        let opted_in = Role::new("test", "---\ncache: true\n---\n");
+        assert!(
+            ResponseCache::init_chat(&config, &role, &model, &data("hello", Some(0.0))).is_some()
+        );
+        assert!(
+            ResponseCache::init_chat(&config, &role, &model, &data("hello", Some(0.5))).is_none()
+        );
+        assert!(ResponseCache::init_chat(&config, &role, &model, &data("hello", None)).is_none());
+        assert!(
+            ResponseCache::init_chat(&config, &opted_in, &model, &data("hello", None)).is_some()
+        );
+
+        let cache = ResponseCache::new(&dir, 60, 1024 * 1024);
+        assert!(cache.get::<CachedChat>(&key).is_none());
+        cache.put(
+            &key,
+            CachedChat {
+                text: "Hi".into(),
+                tool_calls: vec![],
+            },
+        );
+        assert_eq!(cache.get::<CachedChat>(&key).unwrap().text, "Hi");
+
+        // Expired entries are dropped
+        let expired = ResponseCache::new(&dir, 0, 1024 * 1024);
+        assert!(expired.get::<CachedChat>(&key).is_none());
+        assert!(!dir.join(format!("{key}.json")).exists());
+
+        // The size cap evicts the oldest entries
+        let small = ResponseCache::new(&dir, 60, 100);
+        small.put("first", "a".repeat(60));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        small.put("second", "b".repeat(60));
+        assert!(small.get::<String>("first").is_none());
+        assert!(small.get::<String>("second").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
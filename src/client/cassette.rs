@@ -0,0 +1,443 @@
+use super::*;
+
+use crate::config::write_file_atomic;
+
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use http::{Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Incoming, service::service_fn};
+use hyper_util::rt::TokioIo;
+use indexmap::IndexMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs::read_to_string,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// The header a redirected request carries its real URL in.
+const URL_HEADER: &str = "x-aichat-cassette-url";
+
+const REDACTED: &str = "REDACTED";
+
+/// Body fields holding credentials, such as those of an OAuth token exchange. Headers and query
+/// parameters are matched by `is_secret` instead, which would also catch `max_tokens`.
+const SECRET_FIELDS: [&str; 6] = [
+    "client_id",
+    "client_secret",
+    "refresh_token",
+    "access_token",
+    "id_token",
+    "api_key",
+];
+
+/// Provider requests recorded to, or replayed from, a cassette file, so the clients and the
+/// server can be tested without credentials or network access. Every request built by
+/// `Client::request_builder` is sent to a local server instead, which forwards and records
+/// it, or answers it from the cassette.
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    addr: SocketAddr,
+    state: Arc<CassetteState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug)]
+struct CassetteState {
+    path: PathBuf,
+    mode: CassetteMode,
+    interactions: Mutex<Vec<Interaction>>,
+    /// Which interactions have been replayed, each answers a single request
+    replayed: Mutex<Vec<bool>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: IndexMap<String, String>,
+    #[serde(default)]
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: IndexMap<String, String>,
+    /// The whole body, an event stream included
+    pub body: String,
+}
+
+impl FromStr for CassetteMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            _ => bail!("Invalid cassette mode '{s}', expected 'record' or 'replay'"),
+        }
+    }
+}
+
+impl Cassette {
+    /// Start the local server. Recording adds to the cassette if it exists, replaying needs it.
+    pub fn start(path: &Path, mode: CassetteMode) -> Result<Self> {
+        let interactions: Vec<Interaction> = match read_to_string(path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("Invalid cassette at '{}'", path.display()))?,
+            Err(_) if mode == CassetteMode::Record => vec![],
+            Err(err) => {
+                bail!("Failed to load cassette at '{}', {err}", path.display())
+            }
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let cassette = Self {
+            addr,
+            state: Arc::new(CassetteState {
+                path: path.to_path_buf(),
+                mode,
+                replayed: Mutex::new(vec![false; interactions.len()]),
+                interactions: Mutex::new(interactions),
+            }),
+        };
+        let server = cassette.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((cnx, _)) = listener.accept().await else {
+                    continue;
+                };
+                let server = server.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| server.clone().handle(req));
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(cnx), service)
+                        .await;
+                });
+            }
+        });
+        Ok(cassette)
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.state.mode == CassetteMode::Replay
+    }
+
+    /// Send the request to the cassette server, keeping its real URL in a header.
+    pub fn redirect(&self, request_data: &mut RequestData) {
+        let url = std::mem::replace(&mut request_data.url, format!("http://{}/", self.addr));
+        request_data.header(URL_HEADER, url);
+    }
+
+    async fn handle(
+        self,
+        req: hyper::Request<Incoming>,
+    ) -> std::result::Result<Response<Full<Bytes>>, hyper::Error> {
+        let method = req.method().to_string();
+        let mut headers = IndexMap::new();
+        let mut url = String::new();
+        for (key, value) in req.headers() {
+            let value = value.to_str().unwrap_or_default().to_string();
+            match key.as_str() {
+                URL_HEADER => url = value,
+                "host" | "content-length" | "connection" => {}
+                key => {
+                    headers.insert(key.to_string(), value);
+                }
+            }
+        }
+        let body = req.into_body().collect().await?.to_bytes();
+        let request = RecordedRequest {
+            method,
+            url,
+            headers,
+            body: serde_json::from_slice(&body)
+                .unwrap_or_else(|_| String::from_utf8_lossy(&body).into()),
+        };
+        let response = match self.state.mode {
+            CassetteMode::Record => self.record(request, body).await,
+            CassetteMode::Replay => self.replay(&request),
+        };
+        let response = response.unwrap_or_else(|err| RecordedResponse {
+            status: StatusCode::BAD_GATEWAY.as_u16(),
+            headers: [("content-type".into(), "application/json".into())].into(),
+            body: json!({ "error": { "message": err.to_string(), "type": "cassette_error" } })
+                .to_string(),
+        });
+        let mut res = Response::new(Full::new(Bytes::from(response.body)));
+        *res.status_mut() = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
+        for (key, value) in response.headers {
+            if let (Ok(key), Ok(value)) = (key.parse::<http::HeaderName>(), value.parse()) {
+                res.headers_mut().insert(key, value);
+            }
+        }
+        Ok(res)
+    }
+
+    async fn record(&self, request: RecordedRequest, body: Bytes) -> Result<RecordedResponse> {
+        let method = request.method.parse()?;
+        let mut builder = reqwest::Client::new().request(method, &request.url);
+        for (key, value) in &request.headers {
+            builder = builder.header(key, value);
+        }
+        let res = builder.body(body).send().await?;
+        let status = res.status().as_u16();
+        let headers = res
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| [("content-type".to_string(), v.to_string())].into())
+            .unwrap_or_default();
+        let response = RecordedResponse {
+            status,
+            headers,
+            body: res.text().await?,
+        };
+        let interaction = Interaction {
+            request: sanitize_request(request),
+            response: sanitize_response(response.clone()),
+        };
+        let mut interactions = self.state.interactions.lock();
+        interactions.push(interaction);
+        let content = serde_yaml::to_string(&*interactions)?;
+        write_file_atomic(&self.state.path, &content).with_context(|| {
+            format!(
+                "Failed to write cassette to '{}'",
+                self.state.path.display()
+            )
+        })?;
+        Ok(response)
+    }
+
+    /// Answer with the first interaction not replayed yet whose request matches.
+    fn replay(&self, request: &RecordedRequest) -> Result<RecordedResponse> {
+        let url = sanitize_url(&request.url);
+        let mut body = request.body.clone();
+        sanitize_body(&mut body);
+        let interactions = self.state.interactions.lock();
+        let mut replayed = self.state.replayed.lock();
+        let index = interactions
+            .iter()
+            .enumerate()
+            .position(|(i, v)| {
+                !replayed[i]
+                    && v.request.method == request.method
+                    && v.request.url == url
+                    && v.request.body == body
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "No recorded response for {} {url} in '{}'",
+                    request.method,
+                    self.state.path.display()
+                )
+            })?;
+        replayed[index] = true;
+        Ok(interactions[index].response.clone())
+    }
+}
+
+/// Credentials in headers, query parameters and bodies are never written to a cassette.
+fn sanitize_request(mut request: RecordedRequest) -> RecordedRequest {
+    request.url = sanitize_url(&request.url);
+    for (key, value) in request.headers.iter_mut() {
+        if is_secret(key) {
+            *value = REDACTED.into();
+        }
+    }
+    sanitize_body(&mut request.body);
+    request
+}
+
+/// Responses may hand out credentials too, an access token for one. Event streams are kept as
+/// they are.
+fn sanitize_response(mut response: RecordedResponse) -> RecordedResponse {
+    if let Ok(mut body) = serde_json::from_str::<Value>(&response.body) {
+        sanitize_body(&mut body);
+        response.body = body.to_string();
+    }
+    response
+}
+
+fn sanitize_body(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    *value = REDACTED.into();
+                } else {
+                    sanitize_body(value);
+                }
+            }
+        }
+        Value::Array(list) => list.iter_mut().for_each(sanitize_body),
+        _ => {}
+    }
+}
+
+fn sanitize_url(url: &str) -> String {
+    let Ok(mut url) = reqwest::Url::parse(url) else {
+        return url.to_string();
+    };
+    if url.query().is_none() {
+        return url.to_string();
+    }
+    let pairs: Vec<_> = url
+        .query_pairs()
+        .map(|(key, value)| {
+            let value = if is_secret(&key) {
+                REDACTED.into()
+            } else {
+                value.to_string()
+            };
+            (key.to_string(), value)
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url.to_string()
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["auth", "key", "token", "secret", "signature", "cookie"]
+        .iter()
+        .any(|v| name.contains(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{access_token::get_access_token, vertexai::prepare_gcloud_access_token};
+    use crate::config::Config;
+
+    use parking_lot::RwLock;
+
+    const CASSETTE: &str = r#"
+- request:
+    method: POST
+    url: https://api.openai.com/v1/chat/completions
+    headers:
+      authorization: REDACTED
+    body: {"model":"gpt-4o-mini","messages":[{"role":"user","content":"hi"}]}
+  response:
+    status: 200
+    headers:
+      content-type: application/json
+    body: '{"id":"x","choices":[{"message":{"content":"Hi there"}}],"usage":{"prompt_tokens":9,"completion_tokens":2}}'
+- request:
+    method: POST
+    url: https://api.openai.com/v1/chat/completions
+    body: {"model":"gpt-4o-mini","messages":[{"role":"user","content":"hi"}],"stream":true,"stream_options":{"include_usage":true}}
+  response:
+    status: 200
+    headers:
+      content-type: text/event-stream
+    body: |+
+      data: {"choices":[{"delta":{"content":"Hi"}}]}
+
+      data: {"choices":[{"delta":{"content":" there"}}]}
+
+      data: [DONE]
+
+- request:
+    method: POST
+    url: https://oauth2.googleapis.com/token
+    body: {"client_id":"REDACTED","client_secret":"REDACTED","refresh_token":"REDACTED","grant_type":"refresh_token"}
+  response:
+    status: 200
+    headers:
+      content-type: application/json
+    body: '{"access_token":"REDACTED","expires_in":3599}'
+"#;
+
+    #[tokio::test]
+    async fn test_cassette_replay() {
+        let path =
+            std::env::temp_dir().join(format!("aichat-cassette-{}.yaml", std::process::id()));
+        std::fs::write(&path, CASSETTE).unwrap();
+        let config = Config {
+            clients: vec![serde_yaml::from_str("type: openai\napi_key: sk-test").unwrap()],
+            cassette: Some(Cassette::start(&path, CassetteMode::Replay).unwrap()),
+            ..Default::default()
+        };
+        let config = Arc::new(RwLock::new(config));
+        let client = init_client(&config, Some(Model::new("openai", "gpt-4o-mini"))).unwrap();
+        let data = |stream| ChatCompletionsData {
+            messages: vec![Message::new(
+                MessageRole::User,
+                MessageContent::Text("hi".into()),
+            )],
+            temperature: None,
+            top_p: None,
+            functions: None,
+            stream,
+        };
+        let http_client = client.build_client().unwrap();
+
+        let output = client
+            .chat_completions_inner(&http_client, data(false))
+            .await
+            .unwrap();
+        assert_eq!(output.text, "Hi there");
+        assert_eq!(output.input_tokens, Some(9));
+
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut handler = SseHandler::new(tx, crate::utils::create_abort_signal());
+        client
+            .chat_completions_streaming_inner(&http_client, &mut handler, data(true))
+            .await
+            .unwrap();
+        assert_eq!(handler.output().0, "Hi there");
+
+        // Each interaction answers once
+        let err = client
+            .chat_completions_inner(&http_client, data(false))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No recorded response"));
+
+        // The Vertex AI token exchange is replayed without credentials
+        let cassette = config.read().cassette.clone();
+        prepare_gcloud_access_token(&http_client, "vertexai-test", &None, cassette.as_ref())
+            .await
+            .unwrap();
+        assert_eq!(get_access_token("vertexai-test").unwrap(), REDACTED);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            sanitize_url("https://example.com/v1/models/x:generate?alt=sse&key=abc"),
+            "https://example.com/v1/models/x:generate?alt=sse&key=REDACTED"
+        );
+        let response = sanitize_response(RecordedResponse {
+            status: 200,
+            headers: Default::default(),
+            body: r#"{"access_token":"ya29.x","expires_in":3599,"usage":{"max_tokens":8}}"#.into(),
+        });
+        assert_eq!(
+            response.body,
+            r#"{"access_token":"REDACTED","expires_in":3599,"usage":{"max_tokens":8}}"#
+        );
+    }
+}
@@ -88,6 +88,18 @@ pub async fn claude_chat_completions_streaming(
         debug!("stream-data: {data}");
         if let Some(typ) = data["type"].as_str() {
             match typ {
+                "message_start" => {
+                    handler.set_usage(
+                        data["message"]["usage"]["input_tokens"].as_u64(),
+                        data["message"]["usage"]["output_tokens"].as_u64(),
+                    );
+                }
+                "message_delta" => {
+                    handler.set_usage(
+                        data["usage"]["input_tokens"].as_u64(),
+                        data["usage"]["output_tokens"].as_u64(),
+                    );
+                }
                 "content_block_start" => {
                     if let (Some("tool_use"), Some(name), Some(id)) = (
                         data["content_block"]["type"].as_str(),
@@ -350,6 +362,7 @@ pub fn claude_extract_chat_completions(data: &Value) -> Result<ChatCompletionsOu
         id: data["id"].as_str().map(|v| v.to_string()),
         input_tokens: data["usage"]["input_tokens"].as_u64(),
         output_tokens: data["usage"]["output_tokens"].as_u64(),
+        ..Default::default()
     };
     Ok(output)
 }
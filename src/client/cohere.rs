@@ -179,6 +179,12 @@ async fn chat_completions_streaming(
                     function_arguments.clear();
                     function_id.clear();
                 }
+                "message-end" => {
+                    handler.set_usage(
+                        data["delta"]["usage"]["billed_units"]["input_tokens"].as_u64(),
+                        data["delta"]["usage"]["billed_units"]["output_tokens"].as_u64(),
+                    );
+                }
                 _ => {}
             }
         }
@@ -197,12 +203,26 @@ async fn embeddings(builder: RequestBuilder, _model: &Model) -> Result<Embedding
     }
     let res_body: EmbeddingsResBody =
         serde_json::from_value(data).context("Invalid embeddings data")?;
-    Ok(res_body.embeddings.float)
+    Ok(EmbeddingsOutput {
+        embeddings: res_body.embeddings.float,
+        input_tokens: res_body.meta.and_then(|v| v.billed_units?.input_tokens),
+    })
 }
 
 #[derive(Deserialize)]
 struct EmbeddingsResBody {
     embeddings: EmbeddingsResBodyEmbeddings,
+    meta: Option<EmbeddingsResBodyMeta>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResBodyMeta {
+    billed_units: Option<EmbeddingsResBodyBilledUnits>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResBodyBilledUnits {
+    input_tokens: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -250,6 +270,7 @@ fn extract_chat_completions(data: &Value) -> Result<ChatCompletionsOutput> {
         id: data["id"].as_str().map(|v| v.to_string()),
         input_tokens: data["usage"]["billed_units"]["input_tokens"].as_u64(),
         output_tokens: data["usage"]["billed_units"]["output_tokens"].as_u64(),
+        ..Default::default()
     };
     Ok(output)
 }
@@ -1,20 +1,20 @@
 use super::*;
 
 use crate::{
-    config::{Config, GlobalConfig, Input},
+    config::{record_usage, Config, GlobalConfig, Input, UsageEntry, UsageKind},
     function::{eval_tool_calls, FunctionDeclaration, ToolCall, ToolResult},
     render::render_stream,
     utils::*,
 };
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use fancy_regex::Regex;
 use indexmap::IndexMap;
 use inquire::{
     list_option::ListOption, required, validator::Validation, MultiSelect, Select, Text,
 };
 use reqwest::{Client as ReqwestClient, RequestBuilder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::sync::LazyLock;
 use std::time::Duration;
@@ -48,6 +48,11 @@ pub trait Client: Sync + Send {
 
     fn model_mut(&mut self) -> &mut Model;
 
+    /// The model that answered the last call, which differs from `model` for a model group.
+    fn answered_model(&self) -> Model {
+        self.model().clone()
+    }
+
     fn build_client(&self) -> Result<ReqwestClient> {
         let mut builder = ReqwestClient::builder();
         let extra = self.extra_config();
@@ -72,9 +77,35 @@ pub trait Client: Sync + Send {
         }
         let client = self.build_client()?;
         let data = input.prepare_completion_data(self.model(), false)?;
-        self.chat_completions_inner(&client, data)
+        let cache = ResponseCache::init_chat(
+            &self.global_config().read(),
+            input.role(),
+            self.model(),
+            &data,
+        );
+        if let Some(cached) = cache.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            let CachedChat { text, tool_calls } = cached;
+            return Ok(ChatCompletionsOutput {
+                text,
+                tool_calls,
+                cached: true,
+                ..Default::default()
+            });
+        }
+        let output = self
+            .chat_completions_inner(&client, data)
             .await
-            .with_context(|| "Failed to call chat-completions api")
+            .with_context(|| "Failed to call chat-completions api")?;
+        if let Some((cache, key)) = &cache {
+            cache.put(
+                key,
+                CachedChat {
+                    text: output.text.clone(),
+                    tool_calls: output.tool_calls.clone(),
+                },
+            );
+        }
+        Ok(output)
     }
 
     async fn chat_completions_streaming(
@@ -93,7 +124,33 @@ pub trait Client: Sync + Send {
                 }
                 let client = self.build_client()?;
                 let data = input.prepare_completion_data(self.model(), true)?;
-                self.chat_completions_streaming_inner(&client, handler, data).await
+                let cache = ResponseCache::init_chat(
+                    &self.global_config().read(),
+                    input.role(),
+                    self.model(),
+                    &data,
+                );
+                if let Some(cached) = cache.as_ref().and_then(|(cache, key)| cache.get(key)) {
+                    let CachedChat { text, tool_calls } = cached;
+                    handler.set_cached();
+                    handler.text(&text)?;
+                    for call in tool_calls {
+                        handler.tool_call(call)?;
+                    }
+                    return Ok(());
+                }
+                self.chat_completions_streaming_inner(&client, handler, data).await?;
+                if let Some((cache, key)) = cache.filter(|_| !handler.abort().aborted()) {
+                    let (text, tool_calls) = handler.output();
+                    cache.put(
+                        &key,
+                        CachedChat {
+                            text: text.to_string(),
+                            tool_calls: tool_calls.to_vec(),
+                        },
+                    );
+                }
+                Ok::<_, anyhow::Error>(())
             } => {
                 handler.done();
                 ret.with_context(|| "Failed to call chat-completions api")
@@ -105,18 +162,67 @@ pub trait Client: Sync + Send {
         }
     }
 
+    /// Embeddings are cached per text, so only the texts not seen before are sent.
     async fn embeddings(&self, data: &EmbeddingsData) -> Result<Vec<Vec<f32>>> {
-        let client = self.build_client()?;
-        self.embeddings_inner(&client, data)
-            .await
-            .context("Failed to call embeddings api")
+        let cache = ResponseCache::init(&self.global_config().read(), None);
+        let keys: Vec<_> = match &cache {
+            Some(_) => data
+                .texts
+                .iter()
+                .map(|text| ResponseCache::embedding_key(self.model(), text, data.query))
+                .collect(),
+            None => vec![],
+        };
+        let mut output: Vec<Option<Vec<f32>>> = match &cache {
+            Some(cache) => keys.iter().map(|key| cache.get(key)).collect(),
+            None => vec![None; data.texts.len()],
+        };
+        let missing: Vec<_> = (0..output.len()).filter(|i| output[*i].is_none()).collect();
+        if !missing.is_empty() {
+            let texts = missing.iter().map(|i| data.texts[*i].clone()).collect();
+            let data = EmbeddingsData::new(texts, data.query);
+            let client = self.build_client()?;
+            let EmbeddingsOutput {
+                embeddings,
+                input_tokens,
+            } = self
+                .embeddings_inner(&client, &data)
+                .await
+                .context("Failed to call embeddings api")?;
+            match input_tokens {
+                Some(input_tokens) => record_embeddings_usage(self, input_tokens),
+                None => record_estimated_usage(self, UsageKind::Embeddings, &data.texts),
+            }
+            for (i, embedding) in missing.into_iter().zip(embeddings) {
+                if let Some(cache) = &cache {
+                    cache.put(&keys[i], &embedding);
+                }
+                output[i] = Some(embedding);
+            }
+        }
+        output
+            .into_iter()
+            .map(|v| v.ok_or_else(|| anyhow!("Missing embeddings in the response")))
+            .collect()
     }
 
     async fn rerank(&self, data: &RerankData) -> Result<RerankOutput> {
+        let cache = ResponseCache::init(&self.global_config().read(), None)
+            .map(|cache| (cache, ResponseCache::rerank_key(self.model(), data)));
+        if let Some(output) = cache.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            return Ok(output);
+        }
         let client = self.build_client()?;
-        self.rerank_inner(&client, data)
+        let output = self
+            .rerank_inner(&client, data)
             .await
-            .context("Failed to call rerank api")
+            .context("Failed to call rerank api")?;
+        let texts = std::iter::once(&data.query).chain(&data.documents);
+        record_estimated_usage(self, UsageKind::Rerank, texts);
+        if let Some((cache, key)) = &cache {
+            cache.put(key, &output);
+        }
+        Ok(output)
     }
 
     async fn chat_completions_inner(
@@ -154,6 +260,9 @@ pub trait Client: Sync + Send {
         mut request_data: RequestData,
     ) -> RequestBuilder {
         self.patch_request_data(&mut request_data);
+        if let Some(cassette) = &self.global_config().read().cassette {
+            cassette.redirect(&mut request_data);
+        }
         request_data.into_builder(client)
     }
 
@@ -277,7 +386,7 @@ impl RequestData {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChatCompletionsData {
     pub messages: Vec<Message>,
     pub temperature: Option<f64>,
@@ -293,6 +402,8 @@ pub struct ChatCompletionsOutput {
     pub id: Option<String>,
     pub input_tokens: Option<u64>,
     pub output_tokens: Option<u64>,
+    /// Replayed from the response cache rather than returned by the provider
+    pub cached: bool,
 }
 
 impl ChatCompletionsOutput {
@@ -302,6 +413,50 @@ impl ChatCompletionsOutput {
             ..Default::default()
         }
     }
+
+    /// The token usage, estimating the counts the provider didn't report. The prompt's
+    /// estimate comes from `Model::total_tokens`, the completion's from its text.
+    pub fn usage(&self, estimated_input_tokens: u64) -> TokenUsage {
+        let estimated_output_tokens = || {
+            let arguments: usize = self
+                .tool_calls
+                .iter()
+                .map(|v| estimate_token_length(&v.arguments.to_string()))
+                .sum();
+            (estimate_token_length(&self.text) + arguments) as u64
+        };
+        TokenUsage {
+            input_tokens: self.input_tokens.unwrap_or(estimated_input_tokens),
+            output_tokens: self.output_tokens.unwrap_or_else(estimated_output_tokens),
+            estimated: self.input_tokens.is_none() || self.output_tokens.is_none(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Some count is an estimate, because the provider didn't report it
+    pub estimated: bool,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// The cost in dollars, from the model's prices per million tokens. `None` when the
+    /// model has no prices.
+    pub fn cost(&self, model: &Model) -> Option<f64> {
+        let data = model.data();
+        if data.input_price.is_none() && data.output_price.is_none() {
+            return None;
+        }
+        let input = data.input_price.unwrap_or_default() * self.input_tokens as f64;
+        let output = data.output_price.unwrap_or_default() * self.output_tokens as f64;
+        Some((input + output) / 1_000_000.0)
+    }
 }
 
 #[derive(Debug)]
@@ -316,7 +471,21 @@ impl EmbeddingsData {
     }
 }
 
-pub type EmbeddingsOutput = Vec<Vec<f32>>;
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingsOutput {
+    pub embeddings: Vec<Vec<f32>>,
+    /// As reported by the provider, not all of them do
+    pub input_tokens: Option<u64>,
+}
+
+impl EmbeddingsOutput {
+    pub fn new(embeddings: Vec<Vec<f32>>) -> Self {
+        Self {
+            embeddings,
+            input_tokens: None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct RerankData {
@@ -337,7 +506,7 @@ impl RerankData {
 
 pub type RerankOutput = Vec<RerankResult>;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RerankResult {
     pub index: usize,
     pub relevance_score: f64,
@@ -419,6 +588,7 @@ pub async fn call_chat_completions(
 
     match ret {
         Ok(ret) => {
+            record_chat_usage(input, client, &ret);
             let ChatCompletionsOutput {
                 mut text,
                 tool_calls,
@@ -457,13 +627,25 @@ pub async fn call_chat_completions_streaming(
 
     render_ret?;
 
+    let (input_tokens, output_tokens) = handler.usage();
+    let cached = handler.cached();
     let (text, tool_calls) = handler.take();
     match send_ret {
         Ok(_) => {
-            if !text.is_empty() && !text.ends_with('\n') {
+            let output = ChatCompletionsOutput {
+                text,
+                tool_calls,
+                input_tokens,
+                output_tokens,
+                cached,
+                ..Default::default()
+            };
+            record_chat_usage(input, client, &output);
+            if !output.text.is_empty() && !output.text.ends_with('\n') {
                 println!();
             }
-            Ok((text, eval_tool_calls(client.global_config(), tool_calls)?))
+            let tool_results = eval_tool_calls(client.global_config(), output.tool_calls)?;
+            Ok((output.text, tool_results))
         }
         Err(err) => {
             if !text.is_empty() {
@@ -474,6 +656,54 @@ pub async fn call_chat_completions_streaming(
     }
 }
 
+fn record_chat_usage(input: &Input, client: &dyn Client, output: &ChatCompletionsOutput) {
+    if client.global_config().read().dry_run || output.cached {
+        return;
+    }
+    let estimated_input_tokens = input
+        .build_messages()
+        .map(|messages| client.model().total_tokens(&messages) as u64)
+        .unwrap_or_default();
+    let usage = output.usage(estimated_input_tokens);
+    let config = client.global_config().read();
+    record_usage(
+        &config,
+        &UsageEntry::new(&config, UsageKind::Chat, client, &usage),
+    );
+}
+
+fn record_embeddings_usage<C: Client + ?Sized>(client: &C, input_tokens: u64) {
+    let usage = TokenUsage {
+        input_tokens,
+        output_tokens: 0,
+        estimated: false,
+    };
+    let config = client.global_config().read();
+    record_usage(
+        &config,
+        &UsageEntry::new(&config, UsageKind::Embeddings, client, &usage),
+    );
+}
+
+/// Add an embeddings or rerank call to the usage ledger, for the APIs that don't report usage,
+/// with the input tokens estimated from the texts.
+fn record_estimated_usage<'a, C: Client + ?Sized>(
+    client: &C,
+    kind: UsageKind,
+    texts: impl IntoIterator<Item = &'a String>,
+) {
+    let usage = TokenUsage {
+        input_tokens: texts
+            .into_iter()
+            .map(|v| estimate_token_length(v) as u64)
+            .sum(),
+        output_tokens: 0,
+        estimated: true,
+    };
+    let config = client.global_config().read();
+    record_usage(&config, &UsageEntry::new(&config, kind, client, &usage));
+}
+
 pub fn noop_prepare_embeddings<T>(_client: &T, _data: &EmbeddingsData) -> Result<RequestData> {
     bail!("The client doesn't support embeddings api")
 }
@@ -495,41 +725,63 @@ pub fn catch_error(data: &Value, status: u16) -> Result<()> {
         return Ok(());
     }
     debug!("Invalid response, status: {status}, data: {data}");
+    let message = provider_error_message(data)
+        .unwrap_or_else(|| format!("Invalid response data: {data} (status: {status})"));
+    Err(ProviderError { status, message }.into())
+}
+
+/// An error returned by a provider, keeping the HTTP status so callers can tell rate limits
+/// and server errors apart from bad requests.
+#[derive(Debug)]
+pub struct ProviderError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+fn provider_error_message(data: &Value) -> Option<String> {
     if let Some(error) = data["error"].as_object() {
         if let (Some(typ), Some(message)) = (
             json_str_from_map(error, "type"),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (type: {typ})");
+            return Some(format!("{message} (type: {typ})"));
         } else if let (Some(typ), Some(message)) = (
             json_str_from_map(error, "code"),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (code: {typ})");
+            return Some(format!("{message} (code: {typ})"));
         }
     } else if let Some(error) = data["errors"][0].as_object() {
         if let (Some(code), Some(message)) = (
             error.get("code").and_then(|v| v.as_u64()),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (status: {code})")
+            return Some(format!("{message} (status: {code})"));
         }
     } else if let Some(error) = data[0]["error"].as_object() {
         if let (Some(status), Some(message)) = (
             json_str_from_map(error, "status"),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (status: {status})")
+            return Some(format!("{message} (status: {status})"));
         }
     } else if let (Some(detail), Some(status)) = (data["detail"].as_str(), data["status"].as_i64())
     {
-        bail!("{detail} (status: {status})");
+        return Some(format!("{detail} (status: {status})"));
     } else if let Some(error) = data["error"].as_str() {
-        bail!("{error}");
+        return Some(error.to_string());
     } else if let Some(message) = data["message"].as_str() {
-        bail!("{message}");
+        return Some(message.to_string());
     }
-    bail!("Invalid response data: {data} (status: {status})");
+    None
 }
 
 pub fn json_str_from_map<'a>(
@@ -674,3 +926,122 @@ fn prompt_input_string(
     let text = text.prompt()?;
     Ok(text)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_usage() {
+        let (tx, _rx) = unbounded_channel();
+        let mut handler = SseHandler::new(tx, create_abort_signal());
+        handler.set_usage(Some(12), None);
+        handler.set_usage(None, Some(3));
+        let (input_tokens, output_tokens) = handler.usage();
+        let output = ChatCompletionsOutput {
+            text: "Hello there".into(),
+            input_tokens,
+            output_tokens,
+            ..Default::default()
+        };
+        let usage = output.usage(100);
+        assert_eq!((usage.input_tokens, usage.output_tokens), (12, 3));
+        assert!(!usage.estimated);
+
+        let estimated = ChatCompletionsOutput::new("Hello there").usage(100);
+        assert_eq!((estimated.input_tokens, estimated.output_tokens), (100, 3));
+        assert!(estimated.estimated);
+
+        let mut model = Model::new("openai", "gpt-4o-mini");
+        assert_eq!(usage.cost(&model), None);
+        model.data_mut().input_price = Some(2.0);
+        model.data_mut().output_price = Some(8.0);
+        let cost = usage.cost(&model).unwrap();
+        assert!((cost - 0.000048).abs() < 1e-12);
+    }
+
+    struct FakeClient {
+        global_config: GlobalConfig,
+        model: Model,
+    }
+
+    #[async_trait::async_trait]
+    impl Client for FakeClient {
+        fn global_config(&self) -> &GlobalConfig {
+            &self.global_config
+        }
+
+        fn extra_config(&self) -> Option<&ExtraConfig> {
+            None
+        }
+
+        fn patch_config(&self) -> Option<&RequestPatch> {
+            None
+        }
+
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn model(&self) -> &Model {
+            &self.model
+        }
+
+        fn model_mut(&mut self) -> &mut Model {
+            &mut self.model
+        }
+
+        async fn chat_completions_inner(
+            &self,
+            _client: &ReqwestClient,
+            _data: ChatCompletionsData,
+        ) -> Result<ChatCompletionsOutput> {
+            bail!("Not supported")
+        }
+
+        async fn chat_completions_streaming_inner(
+            &self,
+            _client: &ReqwestClient,
+            _handler: &mut SseHandler,
+            _data: ChatCompletionsData,
+        ) -> Result<()> {
+            bail!("Not supported")
+        }
+
+        async fn embeddings_inner(
+            &self,
+            _client: &ReqwestClient,
+            data: &EmbeddingsData,
+        ) -> Result<EmbeddingsOutput> {
+            Ok(EmbeddingsOutput::new(vec![vec![1.0]; data.texts.len()]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_usage() {
+        let path = std::env::temp_dir().join(format!("aichat-usage-{}.jsonl", std::process::id()));
+        let ledger = crate::config::UsageLedger::new(&path);
+        let config = Config {
+            usage_ledger: Some(ledger.clone()),
+            ..Default::default()
+        };
+        let client = FakeClient {
+            global_config: std::sync::Arc::new(parking_lot::RwLock::new(config)),
+            model: Model::new("fake", "embed"),
+        };
+        let texts = vec!["Ownership moves the value".to_string()];
+        let embeddings = client
+            .embeddings(&EmbeddingsData::new(texts, false))
+            .await
+            .unwrap();
+        assert_eq!(embeddings, [vec![1.0]]);
+
+        // RAG goes through `Client::embeddings`, so its calls reach the ledger
+        let entries = ledger.load().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, UsageKind::Embeddings);
+        assert_eq!(entries[0].model, "fake:embed");
+        assert!(entries[0].estimated);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
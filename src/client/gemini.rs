@@ -121,7 +121,7 @@ async fn embeddings(builder: RequestBuilder, _model: &Model) -> Result<Embedding
         .into_iter()
         .map(|embedding| embedding.values)
         .collect();
-    Ok(output)
+    Ok(EmbeddingsOutput::new(output))
 }
 
 #[derive(Deserialize)]
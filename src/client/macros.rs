@@ -74,7 +74,7 @@ macro_rules! register_client {
 
         pub fn init_client(config: &$crate::config::GlobalConfig, model: Option<$crate::client::Model>) -> anyhow::Result<Box<dyn Client>> {
             let model = model.unwrap_or_else(|| config.read().model.clone());
-            None
+            $crate::client::ModelGroupClient::init(config, &model)
             $(.or_else(|| $client::init(config, &model)))+
             .ok_or_else(|| {
                 anyhow::anyhow!("Invalid model '{}'", model.id())
@@ -119,14 +119,17 @@ macro_rules! register_client {
 
         pub fn list_all_models(config: &$crate::config::Config) -> Vec<&'static $crate::client::Model> {
             let models = ALL_MODELS.get_or_init(|| {
-                config
+                let mut models: Vec<_> = config
                     .clients
                     .iter()
                     .flat_map(|v| match v {
                         $(ClientConfig::$config(c) => $client::list_models(c),)+
                         ClientConfig::Unknown => vec![],
                     })
-                    .collect()
+                    .collect();
+                let groups = $crate::client::model_group_models(config, &models);
+                models.extend(groups);
+                models
             });
             models.iter().collect()
         }
@@ -0,0 +1,303 @@
+use super::*;
+
+use crate::utils::sha256;
+
+use anyhow::{Context, Result};
+use fancy_regex::Regex;
+use reqwest::Client as ReqwestClient;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+
+const DEFAULT_DIMENSIONS: usize = 256;
+const DEFAULT_DELAY: u64 = 20;
+
+/// A provider that answers without a backend, for tests, demos and working offline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockConfig {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub models: Vec<ModelData>,
+    /// Answers to chat requests. The first one that fits is used, otherwise the last message
+    /// is echoed back.
+    #[serde(default)]
+    pub responses: Vec<MockResponse>,
+    /// Words per streamed chunk
+    pub chunk_size: Option<usize>,
+    /// Milliseconds between streamed chunks
+    pub delay: Option<u64>,
+    pub dimensions: Option<usize>,
+    pub patch: Option<RequestPatch>,
+    pub extra: Option<ExtraConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockResponse {
+    /// A regex the last user message has to match, any message when omitted
+    #[serde(rename = "match")]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub text: String,
+    /// Only answered to requests with tools, and not to the tool results that follow
+    #[serde(default)]
+    pub tool_calls: Vec<MockToolCall>,
+    /// Fail with this message instead, with `status` as the HTTP status
+    pub error: Option<String>,
+    pub status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockToolCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+impl MockClient {
+    pub const PROMPTS: [PromptAction<'static>; 0] = [];
+
+    fn respond(&self, data: &ChatCompletionsData) -> Result<ChatCompletionsOutput> {
+        let last_message = data.messages.last().map(|v| &v.content);
+        let prompt = data
+            .messages
+            .iter()
+            .rev()
+            .find(|v| v.role.is_user())
+            .map(|v| v.content.to_text())
+            .unwrap_or_default();
+        for response in &self.config.responses {
+            if !response.tool_calls.is_empty()
+                && (data.functions.is_none()
+                    || matches!(last_message, Some(MessageContent::ToolCalls(_))))
+            {
+                continue;
+            }
+            if let Some(pattern) = &response.pattern {
+                let re = Regex::new(pattern)
+                    .with_context(|| format!("Invalid mock response match '{pattern}'"))?;
+                if !re.is_match(&prompt).unwrap_or_default() {
+                    continue;
+                }
+            }
+            if let Some(message) = &response.error {
+                return Err(ProviderError {
+                    status: response.status.unwrap_or(500),
+                    message: message.clone(),
+                }
+                .into());
+            }
+            let tool_calls = response
+                .tool_calls
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    ToolCall::new(
+                        v.name.clone(),
+                        v.arguments.clone(),
+                        Some(format!("call_{i}")),
+                    )
+                })
+                .collect();
+            return Ok(ChatCompletionsOutput {
+                text: response.text.clone(),
+                tool_calls,
+                ..Default::default()
+            });
+        }
+        let text = match last_message {
+            Some(MessageContent::ToolCalls(v)) => v
+                .tool_results
+                .iter()
+                .map(|v| v.output.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => prompt,
+        };
+        Ok(ChatCompletionsOutput::new(&text))
+    }
+}
+
+#[async_trait::async_trait]
+impl Client for MockClient {
+    client_common_fns!();
+
+    async fn chat_completions_inner(
+        &self,
+        _client: &ReqwestClient,
+        data: ChatCompletionsData,
+    ) -> Result<ChatCompletionsOutput> {
+        self.respond(&data)
+    }
+
+    async fn chat_completions_streaming_inner(
+        &self,
+        _client: &ReqwestClient,
+        handler: &mut SseHandler,
+        data: ChatCompletionsData,
+    ) -> Result<()> {
+        let output = self.respond(&data)?;
+        let delay = Duration::from_millis(self.config.delay.unwrap_or(DEFAULT_DELAY));
+        let chunk_size = self.config.chunk_size.unwrap_or(1).max(1);
+        let words: Vec<_> = output.text.split_inclusive(char::is_whitespace).collect();
+        for (i, chunk) in words.chunks(chunk_size).enumerate() {
+            if i > 0 {
+                tokio::time::sleep(delay).await;
+            }
+            handler.text(&chunk.concat())?;
+        }
+        for call in output.tool_calls {
+            handler.tool_call(call)?;
+        }
+        Ok(())
+    }
+
+    async fn embeddings_inner(
+        &self,
+        _client: &ReqwestClient,
+        data: &EmbeddingsData,
+    ) -> Result<EmbeddingsOutput> {
+        let dimensions = self.config.dimensions.unwrap_or(DEFAULT_DIMENSIONS).max(1);
+        let embeddings = data.texts.iter().map(|v| embed(v, dimensions)).collect();
+        Ok(EmbeddingsOutput::new(embeddings))
+    }
+
+    async fn rerank_inner(
+        &self,
+        _client: &ReqwestClient,
+        data: &RerankData,
+    ) -> Result<RerankOutput> {
+        let query = words(&data.query);
+        let mut output: Vec<_> = data
+            .documents
+            .iter()
+            .enumerate()
+            .map(|(index, document)| {
+                let document = words(document);
+                let found = query.iter().filter(|v| document.contains(v)).count();
+                RerankResult {
+                    index,
+                    relevance_score: found as f64 / query.len().max(1) as f64,
+                }
+            })
+            .collect();
+        output.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+        output.truncate(data.top_n);
+        Ok(output)
+    }
+}
+
+/// Hash every word into a dimension, so the same text always gets the same embedding and
+/// texts sharing words end up close.
+fn embed(text: &str, dimensions: usize) -> Vec<f32> {
+    let mut embedding = vec![0.0; dimensions];
+    for word in words(text) {
+        let hash = u64::from_str_radix(&sha256(&word)[..16], 16).unwrap_or_default();
+        let sign = if hash >> 63 == 1 { -1.0 } else { 1.0 };
+        embedding[(hash % dimensions as u64) as usize] += sign;
+    }
+    let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        embedding[0] = 1.0;
+    } else {
+        embedding.iter_mut().for_each(|v| *v /= norm);
+    }
+    embedding
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_mock_client() {
+        let config = Config {
+            clients: vec![serde_yaml::from_str(
+                r#"
+type: mock
+chunk_size: 2
+delay: 0
+responses:
+  - match: (?i)weather
+    tool_calls:
+      - name: get_weather
+        arguments: { city: Paris }
+  - match: (?i)limit
+    error: Too many requests
+    status: 429
+"#,
+            )
+            .unwrap()],
+            ..Default::default()
+        };
+        let config = Arc::new(RwLock::new(config));
+        let client = init_client(&config, Some(Model::new("mock", "chat"))).unwrap();
+        let http_client = client.build_client().unwrap();
+        let data = |text: &str, functions: bool| ChatCompletionsData {
+            messages: vec![Message::new(
+                MessageRole::User,
+                MessageContent::Text(text.into()),
+            )],
+            temperature: None,
+            top_p: None,
+            functions: functions.then(Vec::new),
+            stream: false,
+        };
+
+        let output = client
+            .chat_completions_inner(&http_client, data("What's the weather?", true))
+            .await
+            .unwrap();
+        assert_eq!(output.tool_calls[0].name, "get_weather");
+        assert_eq!(output.tool_calls[0].arguments["city"], "Paris");
+        // Without tools it's echoed
+        let output = client
+            .chat_completions_inner(&http_client, data("What's the weather?", false))
+            .await
+            .unwrap();
+        assert_eq!(output.text, "What's the weather?");
+        let err = client
+            .chat_completions_inner(&http_client, data("Over the limit", false))
+            .await
+            .unwrap_err();
+        assert!(is_retryable_error(&err));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut handler = SseHandler::new(tx, crate::utils::create_abort_signal());
+        client
+            .chat_completions_streaming_inner(&http_client, &mut handler, data("a b c d e", false))
+            .await
+            .unwrap();
+        let mut chunks = vec![];
+        while let Ok(SseEvent::Text(text)) = rx.try_recv() {
+            chunks.push(text);
+        }
+        assert_eq!(chunks, ["a b ", "c d ", "e"]);
+
+        let texts = vec![
+            "Ownership moves the value".to_string(),
+            "ownership moves values".to_string(),
+            "Lifetimes annotate references".to_string(),
+        ];
+        let embeddings = client
+            .embeddings_inner(&http_client, &EmbeddingsData::new(texts.clone(), false))
+            .await
+            .unwrap()
+            .embeddings;
+        let similarity = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+        assert!(
+            similarity(&embeddings[0], &embeddings[1]) > similarity(&embeddings[0], &embeddings[2])
+        );
+        assert_eq!(embeddings[0], embed(&texts[0], DEFAULT_DIMENSIONS));
+    }
+}
@@ -1,15 +1,21 @@
 mod access_token;
+mod cache;
+mod cassette;
 mod common;
 mod message;
 #[macro_use]
 mod macros;
 mod model;
+mod model_group;
 mod stream;
 
 pub use crate::function::ToolCall;
+pub use cache::*;
+pub use cassette::*;
 pub use common::*;
 pub use message::*;
 pub use model::*;
+pub use model_group::*;
 pub use stream::*;
 
 register_client!(
@@ -31,6 +37,7 @@ register_client!(
     ),
     (vertexai, "vertexai", VertexAIConfig, VertexAIClient),
     (bedrock, "bedrock", BedrockConfig, BedrockClient),
+    (mock, "mock", MockConfig, MockClient),
 );
 
 pub const OPENAI_COMPATIBLE_PROVIDERS: [(&str, &str); 18] = [
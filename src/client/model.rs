@@ -45,6 +45,24 @@ impl Model {
             .collect()
     }
 
+    /// The pseudo-model of a model group, which shares the type and capabilities of its
+    /// first member. Prices and limits are left to the member that answers.
+    pub fn from_group(name: &str, first: &Model) -> Self {
+        let mut data = first.data.clone();
+        data.name = String::new();
+        data.real_name = None;
+        data.max_input_tokens = None;
+        data.input_price = None;
+        data.output_price = None;
+        data.patch = None;
+        data.max_output_tokens = None;
+        data.require_max_tokens = false;
+        Self {
+            client_name: name.to_string(),
+            data,
+        }
+    }
+
     pub fn retrieve_model(config: &Config, model_id: &str, model_type: ModelType) -> Result<Self> {
         let models = list_all_models(config);
         let (client_name, model_name) = match model_id.split_once(':') {
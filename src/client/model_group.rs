@@ -0,0 +1,584 @@
+use super::*;
+
+use crate::config::{Config, GlobalConfig};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use parking_lot::Mutex;
+use rand::Rng;
+use reqwest::Client as ReqwestClient;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+
+/// Consecutive failures, per backend model, and until when it is skipped.
+static HEALTH: LazyLock<Mutex<HashMap<String, Health>>> = LazyLock::new(Default::default);
+
+/// Models served under one name, configured under `model_groups` in config.yaml. The name
+/// works wherever a model id does, and a call falls back through the members when one fails.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "ModelGroupConfig")]
+pub struct ModelGroup {
+    pub strategy: ModelGroupStrategy,
+    pub models: Vec<ModelGroupMember>,
+    /// Retries of a member on rate limits, server and connection errors, before falling back
+    pub retries: u32,
+    /// Failed calls in a row after which a member is skipped for `cooldown` seconds
+    pub failure_threshold: u32,
+    pub cooldown: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelGroupStrategy {
+    /// Try the models in order
+    #[default]
+    Fallback,
+    /// Pick the first model at random by weight, then fall back to the heaviest
+    Weighted,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "ModelGroupMemberConfig")]
+pub struct ModelGroupMember {
+    pub id: String,
+    pub weight: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ModelGroupConfig {
+    Models(Vec<ModelGroupMember>),
+    Group {
+        #[serde(default)]
+        strategy: ModelGroupStrategy,
+        models: Vec<ModelGroupMember>,
+        retries: Option<u32>,
+        failure_threshold: Option<u32>,
+        cooldown: Option<i64>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ModelGroupMemberConfig {
+    Id(String),
+    Weighted { id: String, weight: u32 },
+}
+
+impl From<ModelGroupConfig> for ModelGroup {
+    fn from(value: ModelGroupConfig) -> Self {
+        let (strategy, models, retries, failure_threshold, cooldown) = match value {
+            ModelGroupConfig::Models(models) => (Default::default(), models, None, None, None),
+            ModelGroupConfig::Group {
+                strategy,
+                models,
+                retries,
+                failure_threshold,
+                cooldown,
+            } => (strategy, models, retries, failure_threshold, cooldown),
+        };
+        Self {
+            strategy,
+            models,
+            retries: retries.unwrap_or(1),
+            failure_threshold: failure_threshold.unwrap_or(3),
+            cooldown: cooldown.unwrap_or(60),
+        }
+    }
+}
+
+impl From<ModelGroupMemberConfig> for ModelGroupMember {
+    fn from(value: ModelGroupMemberConfig) -> Self {
+        match value {
+            ModelGroupMemberConfig::Id(id) => Self { id, weight: 1 },
+            ModelGroupMemberConfig::Weighted { id, weight } => Self { id, weight },
+        }
+    }
+}
+
+impl ModelGroup {
+    /// The members in the order to try them. Members whose circuit is open go last, so they
+    /// are only tried when every other one has failed.
+    pub fn plan(&self, now: i64) -> Vec<&ModelGroupMember> {
+        let mut members: Vec<_> = self.models.iter().collect();
+        if self.strategy == ModelGroupStrategy::Weighted {
+            members.sort_by_key(|v| std::cmp::Reverse(v.weight));
+            let total: u32 = members.iter().map(|v| v.weight).sum();
+            if total > 0 {
+                let mut pick = rand::rng().random_range(0..total);
+                if let Some(index) = members.iter().position(|v| {
+                    let hit = pick < v.weight;
+                    pick = pick.saturating_sub(v.weight);
+                    hit
+                }) {
+                    let first = members.remove(index);
+                    members.insert(0, first);
+                }
+            }
+        }
+        let health = HEALTH.lock();
+        members.sort_by_key(|v| health.get(&v.id).is_some_and(|v| v.is_open(now)));
+        members
+    }
+
+    pub fn record_success(&self, member: &ModelGroupMember) {
+        HEALTH.lock().remove(&member.id);
+    }
+
+    pub fn record_failure(&self, member: &ModelGroupMember, now: i64) {
+        let mut health = HEALTH.lock();
+        let entry = health.entry(member.id.clone()).or_default();
+        entry.failures += 1;
+        if entry.failures >= self.failure_threshold {
+            warn!(
+                "Skipping model '{}' for {}s after {} failures",
+                member.id, self.cooldown, entry.failures
+            );
+            entry.open_until = Some(now + self.cooldown);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    open_until: Option<i64>,
+}
+
+impl Health {
+    fn is_open(&self, now: i64) -> bool {
+        self.open_until.is_some_and(|v| v > now)
+    }
+}
+
+/// The pseudo-models of the configured groups, listed along with the provider models. A
+/// group takes its model type and capabilities from its first member, but not its prices,
+/// as the cost comes from the member that answers.
+pub fn model_group_models(config: &Config, models: &[Model]) -> Vec<Model> {
+    config
+        .model_groups
+        .iter()
+        .filter_map(|(name, group)| {
+            let first = group.models.first()?;
+            let model = models.iter().find(|v| v.id() == first.id)?;
+            Some(Model::from_group(name, model))
+        })
+        .collect()
+}
+
+pub fn validate_model_groups(config: &Config) -> Result<()> {
+    let client_names = list_client_names(config);
+    for (name, group) in &config.model_groups {
+        if client_names.contains(&name) {
+            bail!("Model group '{name}' has the name of a client");
+        }
+        if group.models.is_empty() {
+            bail!("Model group '{name}' has no models");
+        }
+        let mut model_type = None;
+        for member in &group.models {
+            if config.model_groups.contains_key(&member.id) {
+                bail!("Model group '{name}' contains the group '{}'", member.id);
+            }
+            if group.strategy == ModelGroupStrategy::Weighted && member.weight == 0 {
+                bail!("Model '{}' in group '{name}' has no weight", member.id);
+            }
+            let member_type = list_all_models(config)
+                .into_iter()
+                .find(|v| v.id() == member.id)
+                .map(|v| v.model_type())
+                .ok_or_else(|| anyhow!("Unknown model '{}' in group '{name}'", member.id))?;
+            if model_type.is_some_and(|v| v != member_type) {
+                bail!("Model group '{name}' mixes model types");
+            }
+            model_type = Some(member_type);
+        }
+    }
+    Ok(())
+}
+
+/// Calls the members of a model group in turn until one answers.
+pub struct ModelGroupClient {
+    global_config: GlobalConfig,
+    group: ModelGroup,
+    model: Model,
+    answered_model: Mutex<Option<Model>>,
+}
+
+impl ModelGroupClient {
+    pub fn init(global_config: &GlobalConfig, model: &Model) -> Option<Box<dyn Client>> {
+        if !model.data().name.is_empty() {
+            return None;
+        }
+        let group = global_config
+            .read()
+            .model_groups
+            .get(model.client_name())?
+            .clone();
+        Some(Box::new(Self {
+            global_config: global_config.clone(),
+            group,
+            model: model.clone(),
+            answered_model: Mutex::new(None),
+        }))
+    }
+
+    fn member_client(&self, member: &ModelGroupMember) -> Result<(Box<dyn Client>, ReqwestClient)> {
+        let config = self.global_config.read();
+        let model = Model::retrieve_model(&config, &member.id, self.model.model_type())?;
+        drop(config);
+        let mut client = init_client(&self.global_config, Some(model))?;
+        // Limits set on the group, such as the max tokens of a `/v1` request, apply to every member
+        if let Some(max_output_tokens) = self.model.max_output_tokens() {
+            client.model_mut().set_max_tokens(
+                Some(max_output_tokens),
+                self.model.data().require_max_tokens,
+            );
+        }
+        let http_client = client.build_client()?;
+        Ok((client, http_client))
+    }
+
+    fn answered(&self, member: &ModelGroupMember, client: &dyn Client) {
+        info!(
+            "Model group '{}' answered by '{}'",
+            self.model.id(),
+            member.id
+        );
+        self.group.record_success(member);
+        *self.answered_model.lock() = Some(client.model().clone());
+    }
+
+    /// Log the failure and tell what to do next.
+    fn failed(&self, member: &ModelGroupMember, attempt: u32, err: &anyhow::Error) -> Fallback {
+        warn!(
+            "Model '{}' in group '{}' failed, {err}",
+            member.id,
+            self.model.id()
+        );
+        // A request the provider refuses, such as a 400 for a context overflow, would fail on
+        // every member and says nothing about this one's health
+        if !is_retryable_error(err) {
+            return Fallback::Fail;
+        }
+        if attempt < self.group.retries {
+            return Fallback::Retry;
+        }
+        self.group.record_failure(member, Utc::now().timestamp());
+        Fallback::NextMember
+    }
+
+    /// A member that can't be set up, e.g. after a config change, is skipped.
+    fn skipped(&self, member: &ModelGroupMember, err: &anyhow::Error) {
+        warn!(
+            "Skipping model '{}' in group '{}', {err}",
+            member.id,
+            self.model.id()
+        );
+    }
+
+    async fn route<'a, T, F>(&self, call: F) -> Result<T>
+    where
+        T: Send,
+        F: Fn(Box<dyn Client>, ReqwestClient) -> BoxFuture<'a, (Box<dyn Client>, Result<T>)>
+            + Send
+            + Sync,
+    {
+        let mut last_err = None;
+        for member in self.group.plan(Utc::now().timestamp()) {
+            let mut attempt = 0;
+            loop {
+                let (client, http_client) = match self.member_client(member) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        self.skipped(member, &err);
+                        last_err = Some(err);
+                        break;
+                    }
+                };
+                match call(client, http_client).await {
+                    (client, Ok(output)) => {
+                        self.answered(member, client.as_ref());
+                        return Ok(output);
+                    }
+                    (_, Err(err)) => match self.failed(member, attempt, &err) {
+                        Fallback::Retry => {}
+                        Fallback::NextMember => {
+                            last_err = Some(err);
+                            break;
+                        }
+                        Fallback::Fail => return Err(err),
+                    },
+                }
+                retry_backoff(attempt).await;
+                attempt += 1;
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow!("Model group '{}' is empty", self.model.id())))
+    }
+}
+
+#[async_trait::async_trait]
+impl Client for ModelGroupClient {
+    fn global_config(&self) -> &GlobalConfig {
+        &self.global_config
+    }
+
+    fn extra_config(&self) -> Option<&ExtraConfig> {
+        None
+    }
+
+    fn patch_config(&self) -> Option<&RequestPatch> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        self.model.client_name()
+    }
+
+    fn model(&self) -> &Model {
+        &self.model
+    }
+
+    fn model_mut(&mut self) -> &mut Model {
+        &mut self.model
+    }
+
+    fn answered_model(&self) -> Model {
+        self.answered_model
+            .lock()
+            .clone()
+            .unwrap_or_else(|| self.model.clone())
+    }
+
+    async fn chat_completions_inner(
+        &self,
+        _client: &ReqwestClient,
+        data: ChatCompletionsData,
+    ) -> Result<ChatCompletionsOutput> {
+        let data = &data;
+        self.route(|client, http_client| {
+            Box::pin(async move {
+                let ret = client
+                    .chat_completions_inner(&http_client, data.clone())
+                    .await;
+                (client, ret)
+            })
+        })
+        .await
+    }
+
+    /// Only falls back while nothing has been streamed, afterwards the error is the caller's.
+    async fn chat_completions_streaming_inner(
+        &self,
+        _client: &ReqwestClient,
+        handler: &mut SseHandler,
+        data: ChatCompletionsData,
+    ) -> Result<()> {
+        let mut last_err = None;
+        for member in self.group.plan(Utc::now().timestamp()) {
+            let mut attempt = 0;
+            loop {
+                let (client, http_client) = match self.member_client(member) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        self.skipped(member, &err);
+                        last_err = Some(err);
+                        break;
+                    }
+                };
+                let ret = client
+                    .chat_completions_streaming_inner(&http_client, handler, data.clone())
+                    .await;
+                match ret {
+                    Ok(()) => {
+                        self.answered(member, client.as_ref());
+                        return Ok(());
+                    }
+                    Err(err) => {
+                        let next = self.failed(member, attempt, &err);
+                        if handler.has_output() {
+                            return Err(err);
+                        }
+                        match next {
+                            Fallback::Retry => {}
+                            Fallback::NextMember => {
+                                last_err = Some(err);
+                                break;
+                            }
+                            Fallback::Fail => return Err(err),
+                        }
+                    }
+                }
+                retry_backoff(attempt).await;
+                attempt += 1;
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow!("Model group '{}' is empty", self.model.id())))
+    }
+
+    async fn embeddings_inner(
+        &self,
+        _client: &ReqwestClient,
+        data: &EmbeddingsData,
+    ) -> Result<EmbeddingsOutput> {
+        self.route(|client, http_client| {
+            Box::pin(async move {
+                let ret = client.embeddings_inner(&http_client, data).await;
+                (client, ret)
+            })
+        })
+        .await
+    }
+
+    async fn rerank_inner(
+        &self,
+        _client: &ReqwestClient,
+        data: &RerankData,
+    ) -> Result<RerankOutput> {
+        self.route(|client, http_client| {
+            Box::pin(async move {
+                let ret = client.rerank_inner(&http_client, data).await;
+                (client, ret)
+            })
+        })
+        .await
+    }
+}
+
+/// What a group does after a member failed.
+enum Fallback {
+    /// Call the same member again
+    Retry,
+    /// Count the failure against the member and try the next one
+    NextMember,
+    /// Return the error, the other members would fail the same way
+    Fail,
+}
+
+async fn retry_backoff(attempt: u32) {
+    tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt.min(5)))).await;
+}
+
+/// Rate limits, server errors and failed connections are worth another try.
+pub fn is_retryable_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|v| {
+        if let Some(err) = v.downcast_ref::<ProviderError>() {
+            err.status == 429 || err.status >= 500
+        } else if let Some(err) = v.downcast_ref::<reqwest::Error>() {
+            err.is_connect() || err.is_timeout()
+        } else {
+            false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_model_group_plan() {
+        let group: ModelGroup = serde_yaml::from_str(
+            "strategy: weighted\nmodels: [a:x, {id: b:y, weight: 3}]\nfailure_threshold: 2",
+        )
+        .unwrap();
+        assert_eq!((group.retries, group.cooldown), (1, 60));
+        let mut firsts = HashMap::new();
+        for _ in 0..400 {
+            *firsts.entry(group.plan(0)[0].id.clone()).or_insert(0) += 1;
+        }
+        assert!(firsts["b:y"] > firsts["a:x"]);
+
+        let group: ModelGroup = serde_yaml::from_str("[test:first, test:second]").unwrap();
+        let ids = |group: &ModelGroup, now| -> Vec<String> {
+            group.plan(now).iter().map(|v| v.id.clone()).collect()
+        };
+        group.record_failure(&group.models[0], 100);
+        group.record_failure(&group.models[0], 100);
+        assert_eq!(ids(&group, 100), ["test:first", "test:second"]);
+        group.record_failure(&group.models[0], 100);
+        assert_eq!(ids(&group, 100), ["test:second", "test:first"]);
+        // Tried again once the cooldown is over
+        assert_eq!(ids(&group, 161), ["test:first", "test:second"]);
+        group.record_success(&group.models[0]);
+        assert_eq!(ids(&group, 100), ["test:first", "test:second"]);
+
+        let err = anyhow::Error::new(ProviderError {
+            status: 429,
+            message: "Rate limited".into(),
+        })
+        .context("Failed to call chat-completions api");
+        assert!(is_retryable_error(&err));
+        assert!(!is_retryable_error(&anyhow!("Invalid request")));
+    }
+
+    #[tokio::test]
+    async fn test_model_group_fallback() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+clients:
+  - type: mock
+    name: fallback-first
+    responses:
+      - match: (?i)too long
+        error: Context length exceeded
+        status: 400
+      - match: (?i)busy
+        error: Overloaded
+        status: 503
+  - type: mock
+    name: fallback-second
+model_groups:
+  pool:
+    models: [fallback-first:chat, fallback-unknown:chat, fallback-second:chat]
+    retries: 0
+    failure_threshold: 1
+"#,
+        )
+        .unwrap();
+        let group = config.model_groups["pool"].clone();
+        let config = Arc::new(RwLock::new(config));
+        let client = init_client(&config, Some(Model::new("pool", ""))).unwrap();
+        let http_client = client.build_client().unwrap();
+        let data = |text: &str| ChatCompletionsData {
+            messages: vec![Message::new(
+                MessageRole::User,
+                MessageContent::Text(text.into()),
+            )],
+            temperature: None,
+            top_p: None,
+            functions: None,
+            stream: false,
+        };
+
+        // A bad request is returned as is, without counting against the member
+        for _ in 0..3 {
+            let err = client
+                .chat_completions_inner(&http_client, data("Way too long"))
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("Context length exceeded"));
+        }
+        assert_eq!(
+            group.plan(Utc::now().timestamp())[0].id,
+            "fallback-first:chat"
+        );
+
+        // A server error falls back, past the member that can't be set up
+        let output = client
+            .chat_completions_inner(&http_client, data("Are you busy?"))
+            .await
+            .unwrap();
+        assert_eq!(output.text, "Are you busy?");
+        assert_eq!(client.answered_model().id(), "fallback-second:chat");
+        assert_ne!(
+            group.plan(Utc::now().timestamp())[0].id,
+            "fallback-first:chat"
+        );
+    }
+}
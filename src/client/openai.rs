@@ -50,7 +50,8 @@ fn prepare_chat_completions(
 
     let url = format!("{}/chat/completions", api_base.trim_end_matches('/'));
 
-    let body = openai_build_chat_completions_body(data, &self_.model);
+    let mut body = openai_build_chat_completions_body(data, &self_.model);
+    openai_include_stream_usage(&mut body);
 
     let mut request_data = RequestData::new(url, body);
 
@@ -126,6 +127,12 @@ pub async fn openai_chat_completions_streaming(
         }
         let data: Value = serde_json::from_str(&message.data)?;
         debug!("stream-data: {data}");
+        if data["usage"].is_object() {
+            handler.set_usage(
+                data["usage"]["prompt_tokens"].as_u64(),
+                data["usage"]["completion_tokens"].as_u64(),
+            );
+        }
         if let Some(text) = data["choices"][0]["delta"]["content"]
             .as_str()
             .filter(|v| !v.is_empty())
@@ -209,13 +216,21 @@ pub async fn openai_embeddings(
     }
     let res_body: EmbeddingsResBody =
         serde_json::from_value(data).context("Invalid embeddings data")?;
-    let output = res_body.data.into_iter().map(|v| v.embedding).collect();
-    Ok(output)
+    Ok(EmbeddingsOutput {
+        embeddings: res_body.data.into_iter().map(|v| v.embedding).collect(),
+        input_tokens: res_body.usage.and_then(|v| v.prompt_tokens),
+    })
 }
 
 #[derive(Deserialize)]
 struct EmbeddingsResBody {
     data: Vec<EmbeddingsResBodyEmbedding>,
+    usage: Option<EmbeddingsResBodyUsage>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResBodyUsage {
+    prompt_tokens: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -223,6 +238,14 @@ struct EmbeddingsResBodyEmbedding {
     embedding: Vec<f32>,
 }
 
+/// Ask for a final chunk with the token usage when streaming. Only the APIs known to accept
+/// `stream_options` get it, though usage is read from any stream that includes it.
+pub fn openai_include_stream_usage(body: &mut Value) {
+    if body["stream"].as_bool() == Some(true) {
+        body["stream_options"] = json!({ "include_usage": true });
+    }
+}
+
 pub fn openai_build_chat_completions_body(data: ChatCompletionsData, model: &Model) -> Value {
     let ChatCompletionsData {
         messages,
@@ -393,6 +416,7 @@ pub fn openai_extract_chat_completions(data: &Value) -> Result<ChatCompletionsOu
         id: data["id"].as_str().map(|v| v.to_string()),
         input_tokens: data["usage"]["prompt_tokens"].as_u64(),
         output_tokens: data["usage"]["completion_tokens"].as_u64(),
+        ..Default::default()
     };
     Ok(output)
 }
@@ -11,6 +11,8 @@ pub struct OpenAICompatibleConfig {
     pub name: Option<String>,
     pub api_base: Option<String>,
     pub api_key: Option<String>,
+    /// Ask for token usage at the end of streams with `stream_options`
+    pub stream_usage: Option<bool>,
     #[serde(default)]
     pub models: Vec<ModelData>,
     pub patch: Option<RequestPatch>,
@@ -22,6 +24,15 @@ impl OpenAICompatibleClient {
     config_get_fn!(api_key, get_api_key);
 
     pub const PROMPTS: [PromptAction<'static>; 0] = [];
+
+    /// Providers known to accept `stream_options`, others may reject the request with it.
+    const STREAM_USAGE_PROVIDERS: [&'static str; 4] = ["deepinfra", "deepseek", "qianwen", "xai"];
+
+    fn stream_usage(&self) -> bool {
+        self.config
+            .stream_usage
+            .unwrap_or_else(|| Self::STREAM_USAGE_PROVIDERS.contains(&self.model.client_name()))
+    }
 }
 
 impl_client_trait!(
@@ -44,7 +55,10 @@ fn prepare_chat_completions(
 
     let url = format!("{api_base}/chat/completions");
 
-    let body = openai_build_chat_completions_body(data, &self_.model);
+    let mut body = openai_build_chat_completions_body(data, &self_.model);
+    if self_.stream_usage() {
+        openai_include_stream_usage(&mut body);
+    }
 
     let mut request_data = RequestData::new(url, body);
 
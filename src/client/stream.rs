@@ -1,4 +1,4 @@
-use super::{catch_error, ToolCall};
+use super::{catch_error, ProviderError, ToolCall};
 use crate::utils::AbortSignal;
 
 use anyhow::{anyhow, bail, Context, Result};
@@ -13,6 +13,9 @@ pub struct SseHandler {
     abort_signal: AbortSignal,
     buffer: String,
     tool_calls: Vec<ToolCall>,
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    cached: bool,
 }
 
 impl SseHandler {
@@ -22,6 +25,9 @@ impl SseHandler {
             abort_signal,
             buffer: String::new(),
             tool_calls: Vec::new(),
+            input_tokens: None,
+            output_tokens: None,
+            cached: false,
         }
     }
 
@@ -61,12 +67,37 @@ impl SseHandler {
         Ok(())
     }
 
+    /// Record the token counts reported in a stream chunk. Providers send them in different
+    /// chunks, so a count the chunk leaves out keeps its earlier value.
+    pub fn set_usage(&mut self, input_tokens: Option<u64>, output_tokens: Option<u64>) {
+        self.input_tokens = input_tokens.or(self.input_tokens);
+        self.output_tokens = output_tokens.or(self.output_tokens);
+    }
+
     pub fn abort(&self) -> AbortSignal {
         self.abort_signal.clone()
     }
 
-    pub fn tool_calls(&self) -> &[ToolCall] {
-        &self.tool_calls
+    pub fn usage(&self) -> (Option<u64>, Option<u64>) {
+        (self.input_tokens, self.output_tokens)
+    }
+
+    /// Mark the response as replayed from the response cache.
+    pub fn set_cached(&mut self) {
+        self.cached = true;
+    }
+
+    pub fn cached(&self) -> bool {
+        self.cached
+    }
+
+    pub fn output(&self) -> (&str, &[ToolCall]) {
+        (&self.buffer, &self.tool_calls)
+    }
+
+    /// Whether anything has reached the receiver, after which the request can't be retried.
+    pub fn has_output(&self) -> bool {
+        !self.buffer.is_empty() || !self.tool_calls.is_empty()
     }
 
     pub fn take(self) -> (String, Vec<ToolCall>) {
@@ -115,10 +146,14 @@ where
                         let data: Value = match text.parse() {
                             Ok(data) => data,
                             Err(_) => {
-                                bail!(
-                                    "Invalid response data: {text} (status: {})",
-                                    status.as_u16()
-                                );
+                                return Err(ProviderError {
+                                    status: status.as_u16(),
+                                    message: format!(
+                                        "Invalid response data: {text} (status: {})",
+                                        status.as_u16()
+                                    ),
+                                }
+                                .into());
                             }
                         };
                         catch_error(&data, status.as_u16())?;
@@ -130,6 +165,9 @@ where
                             header_value.to_str().unwrap_or_default()
                         );
                     }
+                    EventSourceError::Transport(err) => {
+                        return Err(err.into());
+                    }
                     _ => {
                         bail!("{}", err);
                     }
@@ -212,10 +250,8 @@ impl JsonStreamParser {
                     }
                     self.balances.push(ch);
                 }
-                '[' => {
-                    if self.start.is_some() {
-                        self.balances.push(ch);
-                    }
+                '[' if self.start.is_some() => {
+                    self.balances.push(ch);
                 }
                 '}' => {
                     self.balances.pop();
@@ -30,6 +30,17 @@ impl VertexAIClient {
         ("project_id", "Project ID", None),
         ("location", "Location", None),
     ];
+
+    async fn prepare_access_token(&self, client: &ReqwestClient) -> Result<()> {
+        let cassette = self.global_config().read().cassette.clone();
+        prepare_gcloud_access_token(
+            client,
+            self.name(),
+            &self.config.adc_file,
+            cassette.as_ref(),
+        )
+        .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -41,7 +52,7 @@ impl Client for VertexAIClient {
         client: &ReqwestClient,
         data: ChatCompletionsData,
     ) -> Result<ChatCompletionsOutput> {
-        prepare_gcloud_access_token(client, self.name(), &self.config.adc_file).await?;
+        self.prepare_access_token(client).await?;
         let model = self.model();
         let model_category = ModelCategory::from_str(model.real_name())?;
         let request_data = prepare_chat_completions(self, data, &model_category)?;
@@ -59,7 +70,7 @@ impl Client for VertexAIClient {
         handler: &mut SseHandler,
         data: ChatCompletionsData,
     ) -> Result<()> {
-        prepare_gcloud_access_token(client, self.name(), &self.config.adc_file).await?;
+        self.prepare_access_token(client).await?;
         let model = self.model();
         let model_category = ModelCategory::from_str(model.real_name())?;
         let request_data = prepare_chat_completions(self, data, &model_category)?;
@@ -81,8 +92,8 @@ impl Client for VertexAIClient {
         &self,
         client: &ReqwestClient,
         data: &EmbeddingsData,
-    ) -> Result<Vec<Vec<f32>>> {
-        prepare_gcloud_access_token(client, self.name(), &self.config.adc_file).await?;
+    ) -> Result<EmbeddingsOutput> {
+        self.prepare_access_token(client).await?;
         let request_data = prepare_embeddings(self, data)?;
         let builder = self.request_builder(client, request_data);
         embeddings(builder, self.model()).await
@@ -200,6 +211,10 @@ pub async fn gemini_chat_completions_streaming(
         let handle = |value: &str| -> Result<()> {
             let data: Value = serde_json::from_str(value)?;
             debug!("stream-data: {data}");
+            handler.set_usage(
+                data["usageMetadata"]["promptTokenCount"].as_u64(),
+                data["usageMetadata"]["candidatesTokenCount"].as_u64(),
+            );
             if let Some(parts) = data["candidates"][0]["content"]["parts"].as_array() {
                 for (i, part) in parts.iter().enumerate() {
                     if let Some(text) = part["text"].as_str() {
@@ -237,12 +252,20 @@ async fn embeddings(builder: RequestBuilder, _model: &Model) -> Result<Embedding
     }
     let res_body: EmbeddingsResBody =
         serde_json::from_value(data).context("Invalid embeddings data")?;
+    let input_tokens = res_body
+        .predictions
+        .iter()
+        .map(|v| v.embeddings.statistics.as_ref()?.token_count)
+        .sum();
     let output = res_body
         .predictions
         .into_iter()
         .map(|v| v.embeddings.values)
         .collect();
-    Ok(output)
+    Ok(EmbeddingsOutput {
+        embeddings: output,
+        input_tokens,
+    })
 }
 
 #[derive(Deserialize)]
@@ -258,6 +281,12 @@ struct EmbeddingsResBodyPrediction {
 #[derive(Deserialize)]
 struct EmbeddingsResBodyPredictionEmbeddings {
     values: Vec<f32>,
+    statistics: Option<EmbeddingsResBodyPredictionStatistics>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResBodyPredictionStatistics {
+    token_count: Option<u64>,
 }
 
 fn gemini_extract_chat_completions_text(data: &Value) -> Result<ChatCompletionsOutput> {
@@ -294,6 +323,7 @@ fn gemini_extract_chat_completions_text(data: &Value) -> Result<ChatCompletionsO
         id: None,
         input_tokens: data["usageMetadata"]["promptTokenCount"].as_u64(),
         output_tokens: data["usageMetadata"]["candidatesTokenCount"].as_u64(),
+        ..Default::default()
     };
     Ok(output)
 }
@@ -443,9 +473,10 @@ pub async fn prepare_gcloud_access_token(
     client: &reqwest::Client,
     client_name: &str,
     adc_file: &Option<String>,
+    cassette: Option<&Cassette>,
 ) -> Result<()> {
     if !is_valid_access_token(client_name) {
-        let (token, expires_in) = fetch_access_token(client, adc_file)
+        let (token, expires_in) = fetch_access_token(client, adc_file, cassette)
             .await
             .with_context(|| "Failed to fetch access token")?;
         let expires_at = Utc::now()
@@ -459,11 +490,24 @@ pub async fn prepare_gcloud_access_token(
 async fn fetch_access_token(
     client: &reqwest::Client,
     file: &Option<String>,
+    cassette: Option<&Cassette>,
 ) -> Result<(String, i64)> {
-    let credentials = load_adc(file).await?;
-    let value: Value = client
-        .post("https://oauth2.googleapis.com/token")
-        .json(&credentials)
+    // The cassette has the credentials redacted, so replaying needs none
+    let credentials = match cassette {
+        Some(cassette) if cassette.is_replay() => json!({
+            "client_id": "",
+            "client_secret": "",
+            "refresh_token": "",
+            "grant_type": "refresh_token",
+        }),
+        _ => load_adc(file).await?,
+    };
+    let mut request_data = RequestData::new("https://oauth2.googleapis.com/token", credentials);
+    if let Some(cassette) = cassette {
+        cassette.redirect(&mut request_data);
+    }
+    let value: Value = request_data
+        .into_builder(client)
         .send()
         .await?
         .json()
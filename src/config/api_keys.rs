@@ -0,0 +1,248 @@
+use super::*;
+
+use crate::{client::TokenUsage, utils::sha256};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDate};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// A key handed out for the OpenAI-compatible `/v1/*` routes, configured under `api_keys`
+/// in config.yaml, so the server can be shared without sharing the provider credentials.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualApiKey {
+    pub name: String,
+    pub key: String,
+    /// Model ids the key may use, where `openai:*` allows all of a client's models. Empty
+    /// allows every model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_per_day: Option<u64>,
+}
+
+impl VirtualApiKey {
+    pub fn allows_model(&self, model_id: &str) -> bool {
+        self.models.is_empty()
+            || self.models.iter().any(|v| match v.strip_suffix('*') {
+                Some(prefix) => model_id.starts_with(prefix),
+                None => v == model_id,
+            })
+    }
+}
+
+/// What a key has used. Days are counted in UTC.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKeyUsage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cost: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<NaiveDate>,
+    #[serde(default)]
+    pub tokens_today: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<i64>,
+}
+
+/// Seconds between writes of `api_key_usage.yaml` while requests keep coming.
+const USAGE_SAVE_INTERVAL: i64 = 30;
+
+/// Checks virtual API keys and their quotas. The counters are kept in `api_key_usage.yaml`,
+/// so a restart doesn't reset the daily token quota. The per-minute window is in memory.
+#[derive(Debug, Default)]
+pub struct ApiKeyGate {
+    keys: Vec<VirtualApiKey>,
+    path: PathBuf,
+    usage: IndexMap<String, ApiKeyUsage>,
+    recent_requests: HashMap<String, VecDeque<i64>>,
+    dirty: bool,
+    saved_at: i64,
+}
+
+impl ApiKeyGate {
+    pub fn init(config: &Config) -> Result<Self> {
+        Self::load(config.api_keys.clone(), &Config::api_key_usage_file())
+    }
+
+    pub fn load(keys: Vec<VirtualApiKey>, path: &Path) -> Result<Self> {
+        let mut names = HashSet::new();
+        for key in &keys {
+            if key.key.trim().is_empty() {
+                bail!("API key '{}' has an empty key", key.name);
+            }
+            if !names.insert(key.name.as_str()) {
+                bail!("Duplicate API key name '{}'", key.name);
+            }
+        }
+        let usage = if path.exists() {
+            let err = || format!("Failed to load API key usage at '{}'", path.display());
+            let content = read_to_string(path).with_context(err)?;
+            serde_yaml::from_str(&content).with_context(err)?
+        } else {
+            IndexMap::new()
+        };
+        Ok(Self {
+            keys,
+            path: path.to_path_buf(),
+            usage,
+            recent_requests: Default::default(),
+            dirty: false,
+            saved_at: 0,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let content =
+            serde_yaml::to_string(&self.usage).context("Failed to serde API key usage")?;
+        write_file_atomic(&self.path, &content)
+    }
+
+    /// Save the counters if they changed since the last save.
+    pub fn flush(&mut self, now: i64) -> Result<()> {
+        if self.dirty {
+            self.save()?;
+            self.dirty = false;
+            self.saved_at = now;
+        }
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn keys(&self) -> &[VirtualApiKey] {
+        &self.keys
+    }
+
+    pub fn verify(&self, token: &str) -> Option<&VirtualApiKey> {
+        let token_hash = sha256(token);
+        self.keys.iter().find(|v| sha256(&v.key) == token_hash)
+    }
+
+    pub fn usage(&self, name: &str) -> ApiKeyUsage {
+        self.usage.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Count a request against the key's quotas, or fail with the quota that is used up.
+    /// A request is let through while the daily tokens are under the quota, so the last one
+    /// of the day may go over it.
+    pub fn admit(&mut self, key: &VirtualApiKey, now: i64) -> Result<()> {
+        if let Some(limit) = key.tokens_per_day {
+            let mut usage = self.usage(&key.name);
+            roll_day(&mut usage, now);
+            if usage.tokens_today >= limit {
+                bail!("API key '{}' used its {limit} tokens for today", key.name);
+            }
+        }
+        let recent = self.recent_requests.entry(key.name.clone()).or_default();
+        while recent.front().is_some_and(|v| *v <= now - 60) {
+            recent.pop_front();
+        }
+        if let Some(limit) = key.requests_per_minute {
+            if recent.len() >= limit as usize {
+                bail!(
+                    "API key '{}' made {limit} requests in the last minute",
+                    key.name
+                );
+            }
+        }
+        recent.push_back(now);
+        Ok(())
+    }
+
+    /// Add what a completed request used to the key's counters. They are saved at most every
+    /// [`USAGE_SAVE_INTERVAL`] seconds, [`Self::flush`] saves the rest on shutdown.
+    pub fn record(
+        &mut self,
+        name: &str,
+        usage: &TokenUsage,
+        cost: Option<f64>,
+        now: i64,
+    ) -> Result<()> {
+        let entry = self.usage.entry(name.to_string()).or_default();
+        roll_day(entry, now);
+        entry.requests += 1;
+        entry.input_tokens += usage.input_tokens;
+        entry.output_tokens += usage.output_tokens;
+        entry.cost += cost.unwrap_or_default();
+        entry.tokens_today += usage.total_tokens();
+        entry.last_used_at = Some(now);
+        self.dirty = true;
+        if now - self.saved_at >= USAGE_SAVE_INTERVAL {
+            self.flush(now)?;
+        }
+        Ok(())
+    }
+}
+
+fn roll_day(usage: &mut ApiKeyUsage, now: i64) {
+    let today = DateTime::from_timestamp(now, 0).map(|v| v.date_naive());
+    if usage.day != today {
+        usage.day = today;
+        usage.tokens_today = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_quotas() {
+        let path =
+            std::env::temp_dir().join(format!("aichat-api-keys-{}.yaml", std::process::id()));
+        let key = VirtualApiKey {
+            name: "editor".into(),
+            key: "sk-editor".into(),
+            models: vec!["openai:*".into(), "claude:claude-3-5-haiku".into()],
+            requests_per_minute: Some(2),
+            tokens_per_day: Some(100),
+        };
+        assert!(key.allows_model("openai:gpt-4o-mini"));
+        assert!(!key.allows_model("claude:claude-3-opus"));
+
+        let mut gate = ApiKeyGate::load(vec![key.clone()], &path).unwrap();
+        assert_eq!(
+            gate.verify("sk-editor").map(|v| v.name.as_str()),
+            Some("editor")
+        );
+        assert!(gate.verify("sk-other").is_none());
+
+        let now = 1_800_000_000;
+        gate.admit(&key, now).unwrap();
+        gate.admit(&key, now + 1).unwrap();
+        assert!(gate.admit(&key, now + 2).is_err());
+        gate.admit(&key, now + 61).unwrap();
+
+        let usage = TokenUsage {
+            input_tokens: 80,
+            output_tokens: 30,
+            estimated: false,
+        };
+        gate.record("editor", &usage, Some(0.5), now + 61).unwrap();
+        assert!(gate.admit(&key, now + 200).is_err());
+        // Saving waits for the interval or a flush
+        gate.record("editor", &usage, None, now + 62).unwrap();
+        let saved = ApiKeyGate::load(vec![key.clone()], &path).unwrap();
+        assert_eq!(saved.usage("editor").requests, 1);
+        gate.flush(now + 63).unwrap();
+        // The next day starts with a fresh token quota, and counters survive a restart
+        let mut gate = ApiKeyGate::load(vec![key.clone()], &path).unwrap();
+        assert_eq!(gate.usage("editor").input_tokens, 160);
+        gate.admit(&key, now + 86_400).unwrap();
+
+        assert!(ApiKeyGate::load(vec![key.clone(), key], &path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -208,6 +208,11 @@ impl Input {
         Ok(())
     }
 
+    pub fn set_rag_context(&mut self, rag_name: &str, context: &str) {
+        self.patched_text = Some(self.config.read().rag_template(context, &self.text));
+        self.rag_name = Some(rag_name.to_string());
+    }
+
     pub fn rag_name(&self) -> Option<&str> {
         self.rag_name.as_deref()
     }
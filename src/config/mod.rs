@@ -1,18 +1,25 @@
 mod agent;
+mod api_keys;
 mod input;
 mod role;
 mod session;
+mod usage;
 
 pub use self::agent::{complete_agent_variables, list_agents, Agent, AgentVariables};
+pub use self::api_keys::{ApiKeyGate, VirtualApiKey};
 pub use self::input::Input;
 pub use self::role::{
-    Role, RoleLike, CODE_ROLE, CREATE_TITLE_ROLE, EXPLAIN_SHELL_ROLE, SHELL_ROLE,
+    Role, RoleLike, CODE_ROLE, CREATE_TITLE_ROLE, EXPLAIN_SHELL_ROLE, SHELL_ROLE, TUTOR_ROLE,
 };
 use self::session::Session;
+pub use self::usage::{
+    record_usage, UsageEntry, UsageGroupBy, UsageKind, UsageLedger, UsageReport,
+};
 
 use crate::client::{
-    create_client_config, list_client_types, list_models, ClientConfig, MessageContentToolCalls,
-    Model, ModelType, ProviderModels, OPENAI_COMPATIBLE_PROVIDERS,
+    create_client_config, list_client_types, list_models, validate_model_groups, Cassette,
+    CassetteMode, ClientConfig, MessageContentToolCalls, Model, ModelGroup, ModelType,
+    ProviderModels, ResponseCache, OPENAI_COMPATIBLE_PROVIDERS,
 };
 use crate::function::{FunctionDeclaration, Functions, ToolResult};
 use crate::rag::Rag;
@@ -60,6 +67,13 @@ const FUNCTIONS_DIR_NAME: &str = "functions";
 const FUNCTIONS_FILE_NAME: &str = "functions.json";
 const FUNCTIONS_BIN_DIR_NAME: &str = "bin";
 const AGENTS_DIR_NAME: &str = "agents";
+const ACCOUNTS_FILE_NAME: &str = "accounts.yaml";
+const ACTIVITY_FILE_NAME: &str = "activity.jsonl";
+const API_KEY_USAGE_FILE_NAME: &str = "api_key_usage.yaml";
+const CERTIFICATE_KEY_FILE_NAME: &str = "certificate.key";
+const EXAMS_FILE_NAME: &str = "exams.yaml";
+const USAGE_FILE_NAME: &str = "usage.jsonl";
+const CACHE_DIR_NAME: &str = "cache";
 
 const CLIENTS_FIELD: &str = "clients";
 
@@ -136,18 +150,30 @@ pub struct Config {
     #[serde(default)]
     pub document_loaders: HashMap<String, String>,
 
+    pub cache: bool,
+    pub cache_ttl: u64,
+    pub cache_max_size: u64,
+
     pub highlight: bool,
     pub theme: Option<String>,
     pub left_prompt: Option<String>,
     pub right_prompt: Option<String>,
 
     pub serve_addr: Option<String>,
+    pub api_keys: Vec<VirtualApiKey>,
+    pub cors_origins: Vec<String>,
     pub user_agent: Option<String>,
     pub save_shell_history: bool,
     pub sync_models_url: Option<String>,
 
+    pub model_groups: IndexMap<String, ModelGroup>,
     pub clients: Vec<ClientConfig>,
 
+    #[serde(skip)]
+    pub cassette: Option<Cassette>,
+    /// Where model calls are recorded, `usage.jsonl` in the config directory unless set.
+    #[serde(skip)]
+    pub usage_ledger: Option<UsageLedger>,
     #[serde(skip)]
     pub macro_flag: bool,
     #[serde(skip)]
@@ -211,18 +237,27 @@ impl Default for Config {
 
             document_loaders: Default::default(),
 
+            cache: false,
+            cache_ttl: 7 * 24 * 60 * 60,
+            cache_max_size: 100,
+
             highlight: true,
             theme: None,
             left_prompt: None,
             right_prompt: None,
 
             serve_addr: None,
+            api_keys: vec![],
+            cors_origins: vec![],
             user_agent: None,
             save_shell_history: true,
             sync_models_url: None,
 
+            model_groups: Default::default(),
             clients: vec![],
 
+            cassette: None,
+            usage_ledger: None,
             macro_flag: false,
             info_flag: false,
             agent_variables: None,
@@ -274,7 +309,9 @@ impl Config {
 
             config.load_functions()?;
 
+            validate_model_groups(config)?;
             config.setup_model()?;
+            config.setup_cassette()?;
             config.setup_document_loaders();
             config.setup_user_agent();
             Ok(())
@@ -337,6 +374,55 @@ impl Config {
         }
     }
 
+    pub fn accounts_file() -> PathBuf {
+        match env::var(get_env_name("accounts_file")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(ACCOUNTS_FILE_NAME),
+        }
+    }
+
+    pub fn activity_file() -> PathBuf {
+        match env::var(get_env_name("activity_file")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(ACTIVITY_FILE_NAME),
+        }
+    }
+
+    pub fn certificate_key_file() -> PathBuf {
+        match env::var(get_env_name("certificate_key_file")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(CERTIFICATE_KEY_FILE_NAME),
+        }
+    }
+
+    pub fn api_key_usage_file() -> PathBuf {
+        match env::var(get_env_name("api_key_usage_file")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(API_KEY_USAGE_FILE_NAME),
+        }
+    }
+
+    pub fn exams_file() -> PathBuf {
+        match env::var(get_env_name("exams_file")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(EXAMS_FILE_NAME),
+        }
+    }
+
+    pub fn usage_file() -> PathBuf {
+        match env::var(get_env_name("usage_file")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(USAGE_FILE_NAME),
+        }
+    }
+
+    pub fn cache_dir() -> PathBuf {
+        match env::var(get_env_name("cache_dir")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(CACHE_DIR_NAME),
+        }
+    }
+
     pub fn messages_file(&self) -> PathBuf {
         match &self.agent {
             None => match env::var(get_env_name("messages_file")) {
@@ -598,6 +684,7 @@ impl Config {
             ),
             ("rag_top_k", rag_top_k.to_string()),
             ("dry_run", self.dry_run.to_string()),
+            ("cache", ResponseCache::info(self)),
             ("function_calling", self.function_calling.to_string()),
             ("stream", self.stream.to_string()),
             ("save", self.save.to_string()),
@@ -614,6 +701,7 @@ impl Config {
             ("macros_dir", display_path(&Self::macros_dir())),
             ("functions_dir", display_path(&Self::functions_dir())),
             ("messages_file", display_path(&self.messages_file())),
+            ("cache_dir", display_path(&Self::cache_dir())),
         ];
         if let Ok((_, Some(log_path))) = Self::log_config(self.working_mode.is_serve()) {
             items.push(("log_path", display_path(&log_path)));
@@ -670,6 +758,10 @@ impl Config {
                 let value = value.parse().with_context(|| "Invalid value")?;
                 config.write().dry_run = value;
             }
+            "cache" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().cache = value;
+            }
             "function_calling" => {
                 let value = value.parse().with_context(|| "Invalid value")?;
                 if value && config.write().functions.is_empty() {
@@ -1780,6 +1872,7 @@ impl Config {
                         "rag_top_k",
                         "max_output_tokens",
                         "dry_run",
+                        "cache",
                         "function_calling",
                         "stream",
                         "save",
@@ -1803,6 +1896,7 @@ impl Config {
                     None => vec![],
                 },
                 "dry_run" => complete_bool(self.dry_run),
+                "cache" => complete_bool(self.cache),
                 "stream" => complete_bool(self.stream),
                 "save" => complete_bool(self.save),
                 "function_calling" => complete_bool(self.function_calling),
@@ -2253,6 +2347,9 @@ impl Config {
         if let Some(Some(v)) = read_env_bool(&get_env_name("dry_run")) {
             self.dry_run = v;
         }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("cache")) {
+            self.cache = v;
+        }
         if let Some(Some(v)) = read_env_bool(&get_env_name("stream")) {
             self.stream = v;
         }
@@ -2393,6 +2490,19 @@ impl Config {
         Ok(())
     }
 
+    /// Record provider requests to, or replay them from, the cassette in `AICHAT_CASSETTE`.
+    fn setup_cassette(&mut self) -> Result<()> {
+        let Ok(path) = env::var(get_env_name("cassette")) else {
+            return Ok(());
+        };
+        let mode = match env::var(get_env_name("cassette_mode")) {
+            Ok(value) => value.parse()?,
+            Err(_) => CassetteMode::Replay,
+        };
+        self.cassette = Some(Cassette::start(Path::new(&path), mode)?);
+        Ok(())
+    }
+
     fn setup_document_loaders(&mut self) {
         [("pdf", "pdftotext $1 -"), ("docx", "pandoc --to plain $1")]
             .into_iter()
@@ -2668,6 +2778,21 @@ pub(crate) fn ensure_parent_exists(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Write to a sibling temp file and rename it over `path`, so readers never see a partial file.
+pub(crate) fn write_file_atomic(path: &Path, content: &str) -> Result<()> {
+    ensure_parent_exists(path)?;
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+    std::fs::write(&tmp_path, content)
+        .with_context(|| format!("Failed to write to '{}'", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path).with_context(|| {
+        let _ = remove_file(&tmp_path);
+        format!("Failed to write to '{}'", path.display())
+    })?;
+    Ok(())
+}
+
 fn read_env_value<T>(key: &str) -> Option<Option<T>>
 where
     T: std::str::FromStr,
@@ -13,6 +13,7 @@ pub const SHELL_ROLE: &str = "%shell%";
 pub const EXPLAIN_SHELL_ROLE: &str = "%explain-shell%";
 pub const CODE_ROLE: &str = "%code%";
 pub const CREATE_TITLE_ROLE: &str = "%create-title%";
+pub const TUTOR_ROLE: &str = "%tutor%";
 
 pub const INPUT_PLACEHOLDER: &str = "__INPUT__";
 
@@ -52,6 +53,10 @@ pub struct Role {
     top_p: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     use_tools: Option<String>,
+    /// `false` to keep the role's calls out of the response cache, `true` to also cache its
+    /// answers when sampling with a nonzero temperature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<bool>,
 
     #[serde(skip)]
     model: Model,
@@ -83,6 +88,7 @@ impl Role {
                             "temperature" => role.temperature = value.as_f64(),
                             "top_p" => role.top_p = value.as_f64(),
                             "use_tools" => role.use_tools = value.as_str().map(|v| v.to_string()),
+                            "cache" => role.cache = value.as_bool(),
                             _ => (),
                         }
                     }
@@ -111,6 +117,10 @@ impl Role {
             .collect()
     }
 
+    pub fn cache(&self) -> Option<bool> {
+        self.cache
+    }
+
     pub fn has_args(&self) -> bool {
         self.name.contains('#')
     }
@@ -129,6 +139,9 @@ impl Role {
         if let Some(use_tools) = self.use_tools() {
             metadata.push(format!("use_tools: {use_tools}"));
         }
+        if let Some(cache) = self.cache {
+            metadata.push(format!("cache: {cache}"));
+        }
         if metadata.is_empty() {
             format!("{}\n", self.prompt)
         } else if self.prompt.is_empty() {
@@ -208,6 +221,10 @@ impl Role {
         self.prompt.is_empty()
     }
 
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.prompt = prompt.to_string();
+    }
+
     pub fn is_embedded_prompt(&self) -> bool {
         self.prompt.contains(INPUT_PLACEHOLDER)
     }
@@ -270,6 +270,19 @@ impl Session {
         self.dirty = true;
     }
 
+    pub fn refresh_role_prompt(&mut self, prompt: &str) {
+        if self.role_prompt == prompt {
+            return;
+        }
+        self.role_prompt = prompt.to_string();
+        if self.compressed_messages.is_empty() {
+            if let Some(message) = self.messages.first_mut().filter(|v| v.role.is_system()) {
+                message.content = MessageContent::Text(prompt.to_string());
+            }
+        }
+        self.dirty = true;
+    }
+
     pub fn clear_role(&mut self) {
         self.role_name = None;
         self.role_prompt.clear();
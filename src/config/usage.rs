@@ -0,0 +1,359 @@
+use super::*;
+
+use crate::client::{Client, TokenUsage};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{read_to_string, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageKind {
+    Chat,
+    Embeddings,
+    Rerank,
+}
+
+/// One call to a model, as a line of `usage.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEntry {
+    pub at: i64,
+    pub kind: UsageKind,
+    pub model: String,
+    /// The model group the call was routed through, `model` being the member that answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Where the call was made: `cmd`, `repl` or `serve`
+    pub mode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// The virtual key a server request was made with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+impl UsageEntry {
+    pub fn new<C: Client + ?Sized>(
+        config: &Config,
+        kind: UsageKind,
+        client: &C,
+        usage: &TokenUsage,
+    ) -> Self {
+        let mode = match config.working_mode {
+            WorkingMode::Cmd => "cmd",
+            WorkingMode::Repl => "repl",
+            WorkingMode::Serve => "serve",
+        };
+        let model = client.answered_model();
+        let group = Some(client.model().id()).filter(|v| *v != model.id());
+        Self {
+            at: Utc::now().timestamp(),
+            kind,
+            model: model.id(),
+            group,
+            mode: mode.to_string(),
+            role: config
+                .role
+                .as_ref()
+                .map(|v| v.name().to_string())
+                .filter(|v| !v.is_empty()),
+            agent: config.agent.as_ref().map(|v| v.name().to_string()),
+            session: config.session.as_ref().map(|v| v.name().to_string()),
+            api_key: None,
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            estimated: usage.estimated,
+            cost: usage.cost(&model),
+        }
+    }
+
+    pub fn with_api_key(mut self, name: Option<&str>) -> Self {
+        self.api_key = name.map(|v| v.to_string());
+        self
+    }
+}
+
+/// Every model call made through the CLI, the REPL and the server, appended to
+/// `usage.jsonl` in the config directory.
+#[derive(Debug, Clone)]
+pub struct UsageLedger {
+    path: PathBuf,
+}
+
+impl UsageLedger {
+    pub fn init() -> Self {
+        Self::new(&Config::usage_file())
+    }
+
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn record(&self, entry: &UsageEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        ensure_parent_exists(&self.path)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open '{}'", self.path.display()))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(&self) -> Result<Vec<UsageEntry>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let content = read_to_string(&self.path)
+            .with_context(|| format!("Failed to load usage at '{}'", self.path.display()))?;
+        let mut entries = vec![];
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => warn!("Skipping usage line {}, {err}", i + 1),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Add the entry to the ledger. Failures are only logged, they never fail the call itself.
+pub fn record_usage(config: &Config, entry: &UsageEntry) {
+    let ledger = config
+        .usage_ledger
+        .clone()
+        .unwrap_or_else(UsageLedger::init);
+    if let Err(err) = ledger.record(entry) {
+        warn!("Failed to record usage, {err}");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGroupBy {
+    Day,
+    Model,
+    Session,
+}
+
+impl FromStr for UsageGroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "day" => Ok(Self::Day),
+            "model" => Ok(Self::Model),
+            "session" => Ok(Self::Session),
+            _ => bail!("Invalid usage grouping '{s}', expected 'day', 'model' or 'session'"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct UsageReportRow {
+    pub key: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// `None` when no call in the row was made with a priced model
+    pub cost: Option<f64>,
+    pub estimated: bool,
+}
+
+impl UsageReportRow {
+    fn add(&mut self, entry: &UsageEntry) {
+        self.requests += 1;
+        self.input_tokens += entry.input_tokens;
+        self.output_tokens += entry.output_tokens;
+        if let Some(cost) = entry.cost {
+            self.cost = Some(self.cost.unwrap_or_default() + cost);
+        }
+        self.estimated |= entry.estimated;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub group_by: UsageGroupBy,
+    pub rows: Vec<UsageReportRow>,
+    pub total: UsageReportRow,
+}
+
+impl UsageReport {
+    /// Sum the entries per local day, oldest first, or per model or session, most
+    /// expensive first.
+    pub fn build(entries: &[UsageEntry], group_by: UsageGroupBy) -> Self {
+        let mut rows: IndexMap<String, UsageReportRow> = IndexMap::new();
+        let mut total = UsageReportRow {
+            key: "total".into(),
+            ..Default::default()
+        };
+        for entry in entries {
+            let key = match group_by {
+                UsageGroupBy::Day => DateTime::from_timestamp(entry.at, 0)
+                    .map(|v| v.with_timezone(&Local).format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+                UsageGroupBy::Model => entry.model.clone(),
+                UsageGroupBy::Session => entry.session.clone().unwrap_or_else(|| "-".into()),
+            };
+            rows.entry(key.clone())
+                .or_insert_with(|| UsageReportRow {
+                    key,
+                    ..Default::default()
+                })
+                .add(entry);
+            total.add(entry);
+        }
+        let mut rows: Vec<_> = rows.into_values().collect();
+        match group_by {
+            UsageGroupBy::Day => rows.sort_by(|a, b| a.key.cmp(&b.key)),
+            _ => rows.sort_by(|a, b| {
+                let cost = |v: &UsageReportRow| v.cost.unwrap_or_default();
+                cost(b).total_cmp(&cost(a)).then(
+                    (b.input_tokens + b.output_tokens).cmp(&(a.input_tokens + a.output_tokens)),
+                )
+            }),
+        }
+        Self {
+            group_by,
+            rows,
+            total,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let header = match self.group_by {
+            UsageGroupBy::Day => "DAY",
+            UsageGroupBy::Model => "MODEL",
+            UsageGroupBy::Session => "SESSION",
+        };
+        let width = self
+            .rows
+            .iter()
+            .map(|v| v.key.len())
+            .chain([header.len(), self.total.key.len()])
+            .max()
+            .unwrap_or_default();
+        let render_row = |key: &str, requests: &str, input: &str, output: &str, cost: &str| {
+            format!("{key:<width$}  {requests:>8}  {input:>12}  {output:>12}  {cost:>10}")
+        };
+        let render_usage = |row: &UsageReportRow| {
+            let mark = if row.estimated { "~" } else { "" };
+            let cost = match row.cost {
+                Some(cost) => format!("${cost:.6}"),
+                None => "-".into(),
+            };
+            render_row(
+                &row.key,
+                &row.requests.to_string(),
+                &format!("{mark}{}", row.input_tokens),
+                &format!("{mark}{}", row.output_tokens),
+                &cost,
+            )
+        };
+        let mut lines = vec![render_row(header, "REQUESTS", "INPUT", "OUTPUT", "COST")];
+        lines.extend(self.rows.iter().map(render_usage));
+        lines.push(render_usage(&self.total));
+        if self.total.estimated {
+            lines.push(String::new());
+            lines.push("~ includes estimates for calls the provider didn't report".into());
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(at: i64, model: &str, session: Option<&str>, cost: Option<f64>) -> UsageEntry {
+        UsageEntry {
+            at,
+            kind: UsageKind::Chat,
+            model: model.into(),
+            group: None,
+            mode: "cmd".into(),
+            role: None,
+            agent: None,
+            session: session.map(|v| v.into()),
+            api_key: None,
+            input_tokens: 100,
+            output_tokens: 20,
+            estimated: cost.is_none(),
+            cost,
+        }
+    }
+
+    #[test]
+    fn test_usage_report() {
+        let path = std::env::temp_dir().join(format!("aichat-usage-{}.jsonl", std::process::id()));
+        let ledger = UsageLedger::new(&path);
+        ledger
+            .record(&entry(
+                1_800_000_000,
+                "openai:gpt-4o",
+                Some("work"),
+                Some(0.01),
+            ))
+            .unwrap();
+        ledger
+            .record(&entry(1_800_000_100, "openai:gpt-4o", None, Some(0.02)))
+            .unwrap();
+        ledger
+            .record(&entry(1_800_200_000, "ollama:llama3", Some("work"), None))
+            .unwrap();
+        let entries = ledger.load().unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let report = UsageReport::build(&entries, UsageGroupBy::Model);
+        let rows: Vec<_> = report
+            .rows
+            .iter()
+            .map(|v| (v.key.as_str(), v.requests, v.estimated))
+            .collect();
+        assert_eq!(
+            rows,
+            [("openai:gpt-4o", 2, false), ("ollama:llama3", 1, true)]
+        );
+        assert!((report.total.cost.unwrap() - 0.03).abs() < 1e-9);
+        assert_eq!(report.total.input_tokens, 300);
+
+        let report = UsageReport::build(&entries, UsageGroupBy::Session);
+        let keys: Vec<_> = report
+            .rows
+            .iter()
+            .map(|v| (v.key.as_str(), v.requests))
+            .collect();
+        assert_eq!(keys, [("-", 1), ("work", 2)]);
+        assert_eq!(
+            UsageReport::build(&entries, UsageGroupBy::Day).rows.len(),
+            2
+        );
+        assert!(report.render().contains("~200"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
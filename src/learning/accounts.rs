@@ -0,0 +1,419 @@
+use crate::{
+    config::{write_file_atomic, Config},
+    utils::{base64_decode, base64_encode, sha256},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+const PASSWORD_ROUNDS: u32 = 600_000;
+const PASSWORD_SCHEME: &str = "pbkdf2-sha256";
+const TOKEN_PREFIX: &str = "lrn";
+pub const SESSION_COOKIE_NAME: &str = "aichat_session";
+pub const SESSION_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountRole {
+    Learner,
+    Instructor,
+    Admin,
+}
+
+impl AccountRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Learner => "learner",
+            Self::Instructor => "instructor",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for AccountRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "learner" => Ok(Self::Learner),
+            "instructor" => Ok(Self::Instructor),
+            "admin" => Ok(Self::Admin),
+            _ => bail!("Invalid role '{s}', expected 'learner', 'instructor' or 'admin'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub role: AccountRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cohort: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub username: String,
+    token_hash: String,
+    pub created_at: i64,
+}
+
+/// The authenticated user behind a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Identity {
+    pub username: String,
+    pub role: AccountRole,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cohort: Option<String>,
+}
+
+/// Local accounts and bearer tokens, stored in `accounts.yaml`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AccountStore {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    accounts: IndexMap<String, Account>,
+    #[serde(default)]
+    tokens: Vec<ApiToken>,
+}
+
+impl AccountStore {
+    pub fn init() -> Result<Self> {
+        Self::load(&Config::accounts_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut store = if path.exists() {
+            let err = || format!("Failed to load accounts at '{}'", path.display());
+            let content = read_to_string(path).with_context(err)?;
+            serde_yaml::from_str::<Self>(&content).with_context(err)?
+        } else {
+            Self::default()
+        };
+        store.path = path.to_path_buf();
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let content = serde_yaml::to_string(self).context("Failed to serde accounts")?;
+        write_file_atomic(&self.path, &content)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600));
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = (&String, &Account)> {
+        self.accounts.iter()
+    }
+
+    pub fn tokens(&self) -> &[ApiToken] {
+        &self.tokens
+    }
+
+    pub fn identity(&self, username: &str) -> Option<Identity> {
+        let account = self.accounts.get(username)?;
+        Some(Identity {
+            username: username.to_string(),
+            role: account.role,
+            cohort: account.cohort.clone(),
+        })
+    }
+
+    /// Create the account, or update the role (and password and cohort, if given) of an
+    /// existing one. An empty cohort removes the account from its cohort.
+    pub fn upsert_account(
+        &mut self,
+        username: &str,
+        role: AccountRole,
+        password: Option<&str>,
+        cohort: Option<&str>,
+    ) -> Result<()> {
+        validate_username(username)?;
+        let password_hash = password.map(hash_password).transpose()?;
+        let cohort = cohort.map(|v| v.trim()).map(|v| match v {
+            "" => None,
+            v => Some(v.to_string()),
+        });
+        match self.accounts.get_mut(username) {
+            Some(account) => {
+                account.role = role;
+                if password_hash.is_some() {
+                    account.password_hash = password_hash;
+                }
+                if let Some(cohort) = cohort {
+                    account.cohort = cohort;
+                }
+            }
+            None => {
+                self.accounts.insert(
+                    username.to_string(),
+                    Account {
+                        role,
+                        password_hash,
+                        cohort: cohort.flatten(),
+                        created_at: Utc::now().timestamp(),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the account and revoke all of its tokens.
+    pub fn remove_account(&mut self, username: &str) -> Result<()> {
+        if self.accounts.shift_remove(username).is_none() {
+            bail!("Unknown account '{username}'");
+        }
+        self.tokens.retain(|v| v.username != username);
+        Ok(())
+    }
+
+    /// Issue a bearer token, returning its id and the secret, which is only shown once.
+    pub fn issue_token(&mut self, username: &str) -> Result<(String, String)> {
+        if !self.accounts.contains_key(username) {
+            bail!("Unknown account '{username}'");
+        }
+        let id = random_hex(8);
+        let token = format!("{TOKEN_PREFIX}_{id}_{}", random_hex(32));
+        self.tokens.push(ApiToken {
+            id: id.clone(),
+            username: username.to_string(),
+            token_hash: sha256(&token),
+            created_at: Utc::now().timestamp(),
+        });
+        Ok((id, token))
+    }
+
+    pub fn revoke_token(&mut self, id: &str) -> Result<()> {
+        let len = self.tokens.len();
+        self.tokens.retain(|v| v.id != id);
+        if self.tokens.len() == len {
+            bail!("Unknown token '{id}'");
+        }
+        Ok(())
+    }
+
+    pub fn verify_password(&self, username: &str, password: &str) -> Option<Identity> {
+        let account = self.accounts.get(username)?;
+        let password_hash = account.password_hash.as_deref()?;
+        if !verify_password(password, password_hash) {
+            return None;
+        }
+        self.identity(username)
+    }
+
+    pub fn verify_token(&self, token: &str) -> Option<Identity> {
+        let token_hash = sha256(token);
+        let token = self
+            .tokens
+            .iter()
+            .find(|v| constant_time_eq(v.token_hash.as_bytes(), token_hash.as_bytes()))?;
+        self.identity(&token.username)
+    }
+}
+
+/// Keeps the account store in sync with `accounts.yaml`, and tracks browser login sessions.
+#[derive(Debug)]
+pub struct Authenticator {
+    store: AccountStore,
+    modified: Option<SystemTime>,
+    sessions: HashMap<String, (String, i64)>,
+}
+
+impl Authenticator {
+    pub fn init() -> Result<Self> {
+        let path = Config::accounts_file();
+        Ok(Self {
+            modified: modified_time(&path),
+            store: AccountStore::load(&path)?,
+            sessions: Default::default(),
+        })
+    }
+
+    /// Reload the store if the admin CLI changed it while the server is running.
+    pub fn refresh(&mut self) {
+        let modified = modified_time(&self.store.path);
+        if modified == self.modified {
+            return;
+        }
+        match AccountStore::load(&self.store.path) {
+            Ok(store) => {
+                self.store = store;
+                self.modified = modified;
+            }
+            Err(err) => warn!("{err}"),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.store.is_empty()
+    }
+
+    pub fn store(&self) -> &AccountStore {
+        &self.store
+    }
+
+    /// Start a browser session for a verified identity, returning the cookie value.
+    pub fn start_session(&mut self, identity: &Identity) -> String {
+        let session_id = random_hex(32);
+        let now = Utc::now().timestamp();
+        self.sessions.retain(|_, (_, expires_at)| *expires_at > now);
+        self.sessions.insert(
+            sha256(&session_id),
+            (identity.username.clone(), now + SESSION_TTL_SECS),
+        );
+        session_id
+    }
+
+    pub fn logout(&mut self, session_id: &str) {
+        self.sessions.remove(&sha256(session_id));
+    }
+
+    pub fn verify_session(&self, session_id: &str) -> Option<Identity> {
+        let (username, expires_at) = self.sessions.get(&sha256(session_id))?;
+        if *expires_at <= Utc::now().timestamp() {
+            return None;
+        }
+        self.store.identity(username)
+    }
+
+    pub fn verify_token(&self, token: &str) -> Option<Identity> {
+        self.store.verify_token(token)
+    }
+}
+
+fn validate_username(username: &str) -> Result<()> {
+    if username.is_empty()
+        || !username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        bail!("Invalid username '{username}', only letters, digits, '-', '_' and '.' are allowed");
+    }
+    Ok(())
+}
+
+fn hash_password(password: &str) -> Result<String> {
+    if password.is_empty() {
+        bail!("Password cannot be empty");
+    }
+    let salt = uuid::Uuid::new_v4();
+    Ok(hash_password_with(
+        password,
+        salt.as_bytes(),
+        PASSWORD_ROUNDS,
+    ))
+}
+
+fn hash_password_with(password: &str, salt: &[u8], rounds: u32) -> String {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, rounds, &mut hash);
+    format!(
+        "{PASSWORD_SCHEME}${rounds}${}${}",
+        base64_encode(salt),
+        base64_encode(hash)
+    )
+}
+
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    let parse = || -> Result<(u32, Vec<u8>)> {
+        let mut parts = password_hash.split('$');
+        if parts.next() != Some(PASSWORD_SCHEME) {
+            bail!("Unsupported password scheme");
+        }
+        let rounds = parts.next().ok_or_else(|| anyhow!("Missing rounds"))?;
+        let salt = parts.next().ok_or_else(|| anyhow!("Missing salt"))?;
+        Ok((rounds.parse()?, base64_decode(salt)?))
+    };
+    match parse() {
+        Ok((rounds, salt)) => {
+            let expect = hash_password_with(password, &salt, rounds);
+            constant_time_eq(expect.as_bytes(), password_hash.as_bytes())
+        }
+        Err(_) => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub(crate) fn random_hex(len: usize) -> String {
+    let mut output = String::new();
+    while output.len() < len {
+        output.push_str(&uuid::Uuid::new_v4().simple().to_string());
+    }
+    output.truncate(len);
+    output
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|v| v.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hash() {
+        let password_hash = hash_password_with("hunter2", b"saltsalt", 1000);
+        assert!(password_hash.starts_with("pbkdf2-sha256$1000$"));
+        assert!(verify_password("hunter2", &password_hash));
+        assert!(!verify_password("hunter3", &password_hash));
+        assert!(!verify_password("hunter2", "plain$1000$c2FsdA==$"));
+    }
+
+    #[test]
+    fn test_account_tokens() {
+        let mut store = AccountStore::default();
+        store
+            .upsert_account("alice", AccountRole::Learner, None, Some("2026-fall"))
+            .unwrap();
+        assert!(store
+            .upsert_account("../bob", AccountRole::Learner, None, None)
+            .is_err());
+
+        let (id, token) = store.issue_token("alice").unwrap();
+        assert!(token.starts_with(&format!("lrn_{id}_")));
+        assert_eq!(
+            store.verify_token(&token),
+            Some(Identity {
+                username: "alice".into(),
+                role: AccountRole::Learner,
+                cohort: Some("2026-fall".into()),
+            })
+        );
+        assert_eq!(store.verify_token("lrn_bogus"), None);
+        assert_eq!(store.verify_password("alice", ""), None);
+
+        store.revoke_token(&id).unwrap();
+        assert_eq!(store.verify_token(&token), None);
+        assert!(AccountRole::Admin > AccountRole::Instructor);
+    }
+}
@@ -0,0 +1,667 @@
+use crate::{
+    config::{ensure_parent_exists, Config},
+    learning::*,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use fancy_regex::Regex;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs::{read_to_string, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::LazyLock,
+};
+
+/// A learner whose latest runs of a lesson all failed at least this many times is stuck.
+pub const STUCK_FAILED_RUNS: usize = 3;
+const TOP_WRONG_ANSWERS: usize = 3;
+
+static RE_ERROR_CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"error\[(E\d{4})\]").unwrap());
+
+/// One learner action, appended to `activity.jsonl` as it happens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityEvent {
+    pub at: i64,
+    pub learner: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cohort: Option<String>,
+    #[serde(flatten)]
+    pub kind: ActivityKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ActivityKind {
+    QuizAnswer {
+        quiz_id: String,
+        question_id: String,
+        answer: UserAnswer,
+        correct: bool,
+    },
+    CodeRun {
+        lesson_id: Option<String>,
+        exit_code: i32,
+        #[serde(default)]
+        error_codes: Vec<String>,
+        /// The learner's code, kept for successful runs as their solution.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+        /// The output matched an example's expected output, which completes the lesson.
+        #[serde(default)]
+        passed: bool,
+    },
+    LessonView {
+        lesson_id: String,
+    },
+}
+
+impl ActivityEvent {
+    pub fn new(identity: &Identity, kind: ActivityKind) -> Self {
+        Self {
+            at: Utc::now().timestamp(),
+            learner: identity.username.clone(),
+            cohort: identity.cohort.clone(),
+            kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActivityLog {
+    path: PathBuf,
+}
+
+impl ActivityLog {
+    pub fn init() -> Self {
+        Self::new(&Config::activity_file())
+    }
+
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn record(&self, event: &ActivityEvent) -> Result<()> {
+        self.append(std::slice::from_ref(event))
+    }
+
+    /// Add the events in a single append, so concurrent writers never lose each other's lines.
+    pub fn append(&self, events: &[ActivityEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let mut content = String::new();
+        for event in events {
+            content.push_str(&serde_json::to_string(event)?);
+            content.push('\n');
+        }
+        ensure_parent_exists(&self.path)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open '{}'", self.path.display()))?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(&self, filter: &ActivityFilter) -> Result<Vec<ActivityEvent>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let content = read_to_string(&self.path)
+            .with_context(|| format!("Failed to load activity at '{}'", self.path.display()))?;
+        let mut events = vec![];
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<ActivityEvent>(line) {
+                Ok(event) if filter.matches(&event) => events.push(event),
+                Ok(_) => {}
+                Err(err) => warn!("Skipping activity line {}, {err}", i + 1),
+            }
+        }
+        // Imported progress is appended out of order
+        events.sort_by_key(|v| v.at);
+        Ok(events)
+    }
+}
+
+/// Narrows reports to a cohort and an inclusive date range.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ActivityFilter {
+    pub cohort: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl ActivityFilter {
+    /// Parse `cohort`, `from` and `to` from a query string. Dates are `YYYY-MM-DD` (UTC)
+    /// or RFC 3339 timestamps.
+    pub fn from_query(query: &str) -> Result<Self> {
+        let mut filter = Self::default();
+        for (key, value) in parse_query(query) {
+            if value.is_empty() {
+                continue;
+            }
+            match key.as_str() {
+                "cohort" => filter.cohort = Some(value),
+                "from" => filter.from = Some(parse_date(&value, false)?),
+                "to" => filter.to = Some(parse_date(&value, true)?),
+                _ => {}
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, event: &ActivityEvent) -> bool {
+        if let Some(cohort) = &self.cohort {
+            if event.cohort.as_ref() != Some(cohort) {
+                return false;
+            }
+        }
+        self.from.is_none_or(|v| event.at >= v) && self.to.is_none_or(|v| event.at <= v)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportSection {
+    Questions,
+    Lessons,
+    Errors,
+    Stuck,
+}
+
+impl ReportSection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Questions => "questions",
+            Self::Lessons => "lessons",
+            Self::Errors => "errors",
+            Self::Stuck => "stuck",
+        }
+    }
+}
+
+impl FromStr for ReportSection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "questions" => Ok(Self::Questions),
+            "lessons" => Ok(Self::Lessons),
+            "errors" => Ok(Self::Errors),
+            "stuck" => Ok(Self::Stuck),
+            _ => {
+                bail!("Invalid report '{s}', expected 'questions', 'lessons', 'errors' or 'stuck'")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CohortReport {
+    pub questions: Vec<QuestionStats>,
+    pub lessons: Vec<LessonRunStats>,
+    pub errors: Vec<ErrorCodeStats>,
+    pub stuck: Vec<StuckLearner>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuestionStats {
+    pub quiz_id: String,
+    pub question_id: String,
+    pub title: Option<String>,
+    pub attempts: usize,
+    pub correct: usize,
+    pub percent_correct: f64,
+    pub wrong_answers: Vec<AnswerCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AnswerCount {
+    pub answer: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LessonRunStats {
+    pub lesson_id: Option<String>,
+    pub runs: usize,
+    pub failed_runs: usize,
+    pub learners: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorCodeStats {
+    pub code: String,
+    pub count: usize,
+    pub learners: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StuckLearner {
+    pub learner: String,
+    pub cohort: Option<String>,
+    pub lesson_id: Option<String>,
+    pub failed_runs: usize,
+    pub last_error_code: Option<String>,
+    pub last_run_at: String,
+}
+
+impl CohortReport {
+    pub fn build(events: &[ActivityEvent], quizzes: &QuizManager) -> Self {
+        Self {
+            questions: question_stats(events, quizzes),
+            lessons: lesson_run_stats(events),
+            errors: error_code_stats(events),
+            stuck: stuck_learners(events),
+        }
+    }
+
+    pub fn to_csv(&self, section: ReportSection) -> String {
+        let mut rows: Vec<Vec<String>> = vec![];
+        match section {
+            ReportSection::Questions => {
+                rows.push(csv_header(&[
+                    "quiz_id",
+                    "question_id",
+                    "title",
+                    "attempts",
+                    "correct",
+                    "percent_correct",
+                    "common_wrong_answers",
+                ]));
+                for v in &self.questions {
+                    let wrong_answers: Vec<_> = v
+                        .wrong_answers
+                        .iter()
+                        .map(|v| format!("{} ({})", v.answer, v.count))
+                        .collect();
+                    rows.push(vec![
+                        v.quiz_id.clone(),
+                        v.question_id.clone(),
+                        v.title.clone().unwrap_or_default(),
+                        v.attempts.to_string(),
+                        v.correct.to_string(),
+                        format!("{:.1}", v.percent_correct),
+                        wrong_answers.join("; "),
+                    ]);
+                }
+            }
+            ReportSection::Lessons => {
+                rows.push(csv_header(&[
+                    "lesson_id",
+                    "runs",
+                    "failed_runs",
+                    "learners",
+                ]));
+                for v in &self.lessons {
+                    rows.push(vec![
+                        v.lesson_id.clone().unwrap_or_default(),
+                        v.runs.to_string(),
+                        v.failed_runs.to_string(),
+                        v.learners.to_string(),
+                    ]);
+                }
+            }
+            ReportSection::Errors => {
+                rows.push(csv_header(&["code", "count", "learners"]));
+                for v in &self.errors {
+                    rows.push(vec![
+                        v.code.clone(),
+                        v.count.to_string(),
+                        v.learners.to_string(),
+                    ]);
+                }
+            }
+            ReportSection::Stuck => {
+                rows.push(csv_header(&[
+                    "learner",
+                    "cohort",
+                    "lesson_id",
+                    "failed_runs",
+                    "last_error_code",
+                    "last_run_at",
+                ]));
+                for v in &self.stuck {
+                    rows.push(vec![
+                        v.learner.clone(),
+                        v.cohort.clone().unwrap_or_default(),
+                        v.lesson_id.clone().unwrap_or_default(),
+                        v.failed_runs.to_string(),
+                        v.last_error_code.clone().unwrap_or_default(),
+                        v.last_run_at.clone(),
+                    ]);
+                }
+            }
+        }
+        rows.iter()
+            .map(|row| {
+                let row: Vec<_> = row.iter().map(|v| escape_csv(v)).collect();
+                format!("{}\r\n", row.join(","))
+            })
+            .collect()
+    }
+}
+
+/// Compiler error codes such as `E0382` found in rustc output, in order of appearance.
+pub fn extract_error_codes(stderr: &str) -> Vec<String> {
+    let mut codes: Vec<String> = vec![];
+    for caps in RE_ERROR_CODE.captures_iter(stderr).flatten() {
+        let code = &caps[1];
+        if !codes.iter().any(|v| v == code) {
+            codes.push(code.to_string());
+        }
+    }
+    codes
+}
+
+#[derive(Debug, Default)]
+struct AnswerTally {
+    attempts: usize,
+    correct: usize,
+    wrong_answers: IndexMap<String, usize>,
+}
+
+fn question_stats(events: &[ActivityEvent], quizzes: &QuizManager) -> Vec<QuestionStats> {
+    let mut stats: IndexMap<(String, String), AnswerTally> = IndexMap::new();
+    for event in events {
+        let ActivityKind::QuizAnswer {
+            quiz_id,
+            question_id,
+            answer,
+            correct,
+        } = &event.kind
+        else {
+            continue;
+        };
+        let entry = stats
+            .entry((quiz_id.clone(), question_id.clone()))
+            .or_default();
+        entry.attempts += 1;
+        if *correct {
+            entry.correct += 1;
+        } else {
+            let question = find_question(quizzes, quiz_id, question_id);
+            *entry
+                .wrong_answers
+                .entry(answer_label(question, answer))
+                .or_default() += 1;
+        }
+    }
+    let mut output: Vec<_> = stats
+        .into_iter()
+        .map(
+            |(
+                (quiz_id, question_id),
+                AnswerTally {
+                    attempts,
+                    correct,
+                    wrong_answers,
+                },
+            )| {
+                let title = find_question(quizzes, &quiz_id, &question_id).map(|v| v.title.clone());
+                let mut wrong_answers: Vec<_> = wrong_answers
+                    .into_iter()
+                    .map(|(answer, count)| AnswerCount { answer, count })
+                    .collect();
+                wrong_answers.sort_by_key(|v| Reverse(v.count));
+                wrong_answers.truncate(TOP_WRONG_ANSWERS);
+                QuestionStats {
+                    quiz_id,
+                    question_id,
+                    title,
+                    attempts,
+                    correct,
+                    percent_correct: correct as f64 * 100.0 / attempts as f64,
+                    wrong_answers,
+                }
+            },
+        )
+        .collect();
+    output.sort_by(|a, b| a.percent_correct.total_cmp(&b.percent_correct));
+    output
+}
+
+fn lesson_run_stats(events: &[ActivityEvent]) -> Vec<LessonRunStats> {
+    let mut stats: IndexMap<Option<String>, (usize, usize, HashSet<&str>)> = IndexMap::new();
+    for event in events {
+        let ActivityKind::CodeRun {
+            lesson_id,
+            exit_code,
+            ..
+        } = &event.kind
+        else {
+            continue;
+        };
+        let entry = stats.entry(lesson_id.clone()).or_default();
+        entry.0 += 1;
+        if *exit_code != 0 {
+            entry.1 += 1;
+        }
+        entry.2.insert(&event.learner);
+    }
+    let mut output: Vec<_> = stats
+        .into_iter()
+        .map(
+            |(lesson_id, (runs, failed_runs, learners))| LessonRunStats {
+                lesson_id,
+                runs,
+                failed_runs,
+                learners: learners.len(),
+            },
+        )
+        .collect();
+    output.sort_by_key(|v| Reverse(v.runs));
+    output
+}
+
+fn error_code_stats(events: &[ActivityEvent]) -> Vec<ErrorCodeStats> {
+    let mut stats: IndexMap<&str, (usize, HashSet<&str>)> = IndexMap::new();
+    for event in events {
+        let ActivityKind::CodeRun { error_codes, .. } = &event.kind else {
+            continue;
+        };
+        for code in error_codes {
+            let entry = stats.entry(code).or_default();
+            entry.0 += 1;
+            entry.1.insert(&event.learner);
+        }
+    }
+    let mut output: Vec<_> = stats
+        .into_iter()
+        .map(|(code, (count, learners))| ErrorCodeStats {
+            code: code.to_string(),
+            count,
+            learners: learners.len(),
+        })
+        .collect();
+    output.sort_by_key(|v| Reverse(v.count));
+    output
+}
+
+fn stuck_learners(events: &[ActivityEvent]) -> Vec<StuckLearner> {
+    // The streak of failed runs since the last successful run, per learner and lesson
+    let mut streaks: IndexMap<(&str, &Option<String>), (usize, &ActivityEvent)> = IndexMap::new();
+    let mut events: Vec<_> = events.iter().collect();
+    events.sort_by_key(|v| v.at);
+    for event in events {
+        let ActivityKind::CodeRun {
+            lesson_id,
+            exit_code,
+            ..
+        } = &event.kind
+        else {
+            continue;
+        };
+        let entry = streaks
+            .entry((&event.learner, lesson_id))
+            .or_insert((0, event));
+        entry.0 = if *exit_code == 0 { 0 } else { entry.0 + 1 };
+        entry.1 = event;
+    }
+    let mut output: Vec<_> = streaks
+        .into_iter()
+        .filter(|(_, (failed_runs, _))| *failed_runs >= STUCK_FAILED_RUNS)
+        .map(|((learner, lesson_id), (failed_runs, last_run))| {
+            let last_error_code = match &last_run.kind {
+                ActivityKind::CodeRun { error_codes, .. } => error_codes.first().cloned(),
+                _ => None,
+            };
+            StuckLearner {
+                learner: learner.to_string(),
+                cohort: last_run.cohort.clone(),
+                lesson_id: lesson_id.clone(),
+                failed_runs,
+                last_error_code,
+                last_run_at: DateTime::from_timestamp(last_run.at, 0)
+                    .map(|v| v.to_rfc3339())
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+    output.sort_by_key(|v| Reverse(v.failed_runs));
+    output
+}
+
+fn find_question<'a>(
+    quizzes: &'a QuizManager,
+    quiz_id: &str,
+    question_id: &str,
+) -> Option<&'a Question> {
+    quizzes
+        .get_quiz(quiz_id)?
+        .questions
+        .iter()
+        .find(|v| v.id == question_id)
+}
+
+fn answer_label(question: Option<&Question>, answer: &UserAnswer) -> String {
+    match (question.map(|v| &v.question_type), answer) {
+        (
+            Some(QuestionType::MultipleChoice { options, .. }),
+            UserAnswer::MultipleChoice { selected },
+        ) => match options.get(*selected) {
+            Some(option) => format!("{}. {option}", (b'A' + *selected as u8) as char),
+            None => format!("option {}", selected + 1),
+        },
+        (_, UserAnswer::MultipleChoice { selected }) => format!("option {}", selected + 1),
+        (_, UserAnswer::ShortAnswer { text }) => text.trim().to_lowercase(),
+        (_, UserAnswer::CodeCompletion { .. }) => "(code)".into(),
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |v: &str| urlencoding::decode(&v.replace('+', " ")).map(|v| v.to_string());
+            Some((decode(key).ok()?, decode(value).ok()?))
+        })
+        .collect()
+}
+
+fn parse_date(value: &str, end_of_day: bool) -> Result<i64> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let time = match end_of_day {
+            true => date.and_hms_opt(23, 59, 59),
+            false => date.and_hms_opt(0, 0, 0),
+        };
+        return time
+            .map(|v| v.and_utc().timestamp())
+            .ok_or_else(|| anyhow!("Invalid date '{value}'"));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|v| v.timestamp())
+        .map_err(|_| anyhow!("Invalid date '{value}', expected YYYY-MM-DD or RFC 3339"))
+}
+
+fn csv_header(names: &[&str]) -> Vec<String> {
+    names.iter().map(|v| v.to_string()).collect()
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    #[test]
+    fn test_extract_error_codes() {
+        let stderr = "error[E0382]: borrow of moved value\nerror[E0308]: mismatched types\nerror[E0382]: again\nerror: aborting";
+        assert_eq!(extract_error_codes(stderr), vec!["E0382", "E0308"]);
+        assert!(extract_error_codes("warning: unused").is_empty());
+    }
+
+    #[test]
+    fn test_cohort_report() {
+        let events = vec![
+            code_run(1, "alice", "01-intro", &["E0382"]),
+            code_run(2, "alice", "01-intro", &["E0382"]),
+            code_run(3, "alice", "01-intro", &["E0308"]),
+            code_run(1, "bob", "01-intro", &["E0382"]),
+            code_run(2, "bob", "01-intro", &[]),
+            answer(0, "alice", "q1", 0, true),
+            answer(0, "bob", "q1", 3, false),
+            answer(0, "carol", "q1", 3, false),
+            answer(0, "dave", "q1", 1, false),
+        ];
+        let report = CohortReport::build(&events, &QuizManager::new());
+
+        assert_eq!(report.lessons[0].runs, 5);
+        assert_eq!(report.lessons[0].failed_runs, 4);
+        assert_eq!(report.lessons[0].learners, 2);
+
+        assert_eq!(report.errors[0].code, "E0382");
+        assert_eq!(report.errors[0].count, 3);
+        assert_eq!(report.errors[0].learners, 2);
+
+        assert_eq!(report.stuck.len(), 1);
+        assert_eq!(report.stuck[0].learner, "alice");
+        assert_eq!(report.stuck[0].last_error_code.as_deref(), Some("E0308"));
+
+        let question = &report.questions[0];
+        assert_eq!((question.attempts, question.correct), (4, 1));
+        assert_eq!(question.percent_correct, 25.0);
+        assert_eq!(
+            question.wrong_answers[0],
+            AnswerCount {
+                answer: "D. Dynamic typing".into(),
+                count: 2
+            }
+        );
+
+        let csv = report.to_csv(ReportSection::Errors);
+        assert_eq!(csv, "code,count,learners\r\nE0382,3,2\r\nE0308,1,1\r\n");
+    }
+
+    #[test]
+    fn test_activity_filter() {
+        let filter = ActivityFilter::from_query("cohort=fall+2026&from=2026-09-01&to=").unwrap();
+        assert_eq!(filter.cohort.as_deref(), Some("fall 2026"));
+        assert_eq!(filter.from, Some(1788220800));
+        assert_eq!(filter.to, None);
+        assert!(ActivityFilter::from_query("from=yesterday").is_err());
+
+        let filter = ActivityFilter::from_query("to=2026-09-01").unwrap();
+        assert_eq!(filter.to, Some(1788220800 + 86399));
+        assert_eq!(escape_csv("a, \"b\""), "\"a, \"\"b\"\"\"");
+    }
+}
@@ -0,0 +1,380 @@
+use crate::{
+    config::{write_file_atomic, Config},
+    learning::*,
+    utils::{base64_decode, base64_encode},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::DateTime;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{fs, path::PathBuf};
+
+/// Bumped whenever the signed fields of a completion record change.
+pub const COMPLETION_RECORD_VERSION: u32 = 1;
+
+/// An achievement and the rule a learner has to meet to earn it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Badge {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub rule: BadgeRule,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BadgeRule {
+    /// Every lesson of a difficulty level is complete
+    DifficultyComplete { difficulty: Difficulty },
+    /// Each of the listed lessons is complete
+    LessonsComplete { lessons: Vec<String> },
+    /// Every lesson of the course is complete
+    AllLessonsComplete,
+    /// The latest answers to a quiz score at least `min_score` percent
+    QuizScore { quiz_id: String, min_score: f64 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BadgeStatus {
+    #[serde(flatten)]
+    pub badge: Badge,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub earned_at: Option<i64>,
+    /// Only issued by an engine holding the signing key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<CompletionRecord>,
+}
+
+/// Proof that a learner earned a badge. The signature is an HMAC of the other fields, so
+/// anyone holding the signing key can check a record without the activity log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompletionRecord {
+    pub version: u32,
+    pub learner: String,
+    pub badge_id: String,
+    pub badge_title: String,
+    pub earned_at: i64,
+    pub signature: String,
+}
+
+impl CompletionRecord {
+    fn payload(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.version, self.learner, self.badge_id, self.badge_title, self.earned_at
+        )
+    }
+
+    pub fn verify(&self, key: &[u8]) -> bool {
+        let Ok(signature) = base64_decode(&self.signature) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(key) else {
+            return false;
+        };
+        mac.update(self.payload().as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+/// Awards badges from a learner's progress and signs completion records.
+#[derive(Debug)]
+pub struct BadgeEngine {
+    badges: Vec<Badge>,
+    key: Option<Vec<u8>>,
+}
+
+impl BadgeEngine {
+    pub fn new(badges: Vec<Badge>, key: &[u8]) -> Self {
+        Self {
+            badges,
+            key: Some(key.to_vec()),
+        }
+    }
+
+    /// Awards badges without issuing completion records.
+    pub fn unsigned(badges: Vec<Badge>) -> Self {
+        Self { badges, key: None }
+    }
+
+    /// Badges from `badges.yaml` beside `./lessons` and `./quizzes`, or a built-in set when
+    /// there is none. With `sign`, records are signed with the key at
+    /// [`Config::certificate_key_file`], which is created on first use.
+    pub fn init(sign: bool) -> Result<Self> {
+        let badges = load_badges()?;
+        if !sign {
+            return Ok(Self::unsigned(badges));
+        }
+        let key = load_signing_key(true)?;
+        Ok(Self::new(badges, &key))
+    }
+
+    pub fn badges_file() -> PathBuf {
+        PathBuf::from("badges.yaml")
+    }
+
+    pub fn evaluate(
+        &self,
+        learner: &str,
+        progress: &LearnerProgress,
+        lessons: &[&Lesson],
+        quizzes: &[&Quiz],
+    ) -> Vec<BadgeStatus> {
+        self.badges
+            .iter()
+            .map(|badge| {
+                let earned_at = earned_at(&badge.rule, progress, lessons, quizzes);
+                BadgeStatus {
+                    badge: badge.clone(),
+                    earned_at,
+                    record: earned_at.and_then(|at| self.sign(learner, badge, at)),
+                }
+            })
+            .collect()
+    }
+
+    pub fn sign(&self, learner: &str, badge: &Badge, earned_at: i64) -> Option<CompletionRecord> {
+        let key = self.key.as_ref()?;
+        let mut record = CompletionRecord {
+            version: COMPLETION_RECORD_VERSION,
+            learner: learner.to_string(),
+            badge_id: badge.id.clone(),
+            badge_title: badge.title.clone(),
+            earned_at,
+            signature: String::new(),
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+        mac.update(record.payload().as_bytes());
+        record.signature = base64_encode(mac.finalize().into_bytes());
+        Some(record)
+    }
+}
+
+/// When the rule was first met, if it is met at all.
+fn earned_at(
+    rule: &BadgeRule,
+    progress: &LearnerProgress,
+    lessons: &[&Lesson],
+    quizzes: &[&Quiz],
+) -> Option<i64> {
+    let all_completed = |ids: Vec<&str>| {
+        if ids.is_empty() {
+            return None;
+        }
+        ids.into_iter()
+            .map(|id| progress.completed_at(id))
+            .collect::<Option<Vec<i64>>>()?
+            .into_iter()
+            .max()
+    };
+    match rule {
+        BadgeRule::DifficultyComplete { difficulty } => all_completed(
+            lessons
+                .iter()
+                .filter(|v| &v.difficulty == difficulty)
+                .map(|v| v.id.as_str())
+                .collect(),
+        ),
+        BadgeRule::LessonsComplete { lessons } => {
+            all_completed(lessons.iter().map(|v| v.as_str()).collect())
+        }
+        BadgeRule::AllLessonsComplete => {
+            all_completed(lessons.iter().map(|v| v.id.as_str()).collect())
+        }
+        BadgeRule::QuizScore { quiz_id, min_score } => {
+            let quiz = quizzes.iter().find(|v| &v.id == quiz_id)?;
+            let (score, at) = progress.quiz_score(quiz)?;
+            (score >= *min_score).then_some(at)
+        }
+    }
+}
+
+pub fn load_badges() -> Result<Vec<Badge>> {
+    let path = BadgeEngine::badges_file();
+    if !path.exists() {
+        return Ok(default_badges());
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let badges: Vec<Badge> = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to load badges at '{}'", path.display()))?;
+    Ok(badges)
+}
+
+/// Read the signing key, generating one if `create` is set and none exists yet.
+pub fn load_signing_key(create: bool) -> Result<Vec<u8>> {
+    let path = Config::certificate_key_file();
+    if path.exists() {
+        let key = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        return Ok(key.trim().as_bytes().to_vec());
+    }
+    if !create {
+        bail!("No certificate signing key at '{}'", path.display());
+    }
+    let key = random_hex(64);
+    write_file_atomic(&path, &key)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+    }
+    Ok(key.into_bytes())
+}
+
+/// A printable certificate for an earned badge, with the signed record embedded.
+pub fn render_certificate_html(record: &CompletionRecord, description: &str) -> Result<String> {
+    let earned_at = DateTime::from_timestamp(record.earned_at, 0)
+        .ok_or_else(|| anyhow!("Invalid earned_at '{}'", record.earned_at))?
+        .format("%B %-d, %Y");
+    let record_json = serde_json::to_string_pretty(record)?;
+    Ok(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Certificate: {title}</title>
+<style>
+  body {{ font-family: Georgia, serif; background: #f7fafc; margin: 0; padding: 40px; }}
+  .certificate {{ max-width: 800px; margin: 0 auto; background: white; border: 12px double #667eea; padding: 60px; text-align: center; }}
+  h1 {{ font-size: 2.5rem; margin: 0 0 10px; }}
+  .learner {{ font-size: 2rem; font-style: italic; margin: 30px 0; }}
+  .badge {{ font-size: 1.5rem; font-weight: bold; }}
+  .record {{ margin-top: 40px; text-align: left; font-size: 0.75rem; color: #666; }}
+  pre {{ white-space: pre-wrap; word-break: break-all; }}
+  @media print {{ body {{ background: white; padding: 0; }} .record {{ page-break-inside: avoid; }} }}
+</style>
+</head>
+<body>
+<div class="certificate">
+  <h1>🦀 Certificate of Completion</h1>
+  <p>This certifies that</p>
+  <div class="learner">{learner}</div>
+  <p>has earned</p>
+  <div class="badge">{title}</div>
+  <p>{description}</p>
+  <p>on {earned_at}</p>
+  <div class="record">
+    <p>Signed completion record. Check it with <code>aichat --verify-certificate &lt;file&gt;</code>
+    or <code>POST /api/certificates/verify</code>.</p>
+    <pre>{record}</pre>
+  </div>
+</div>
+</body>
+</html>
+"#,
+        title = escape_html(&record.badge_title),
+        learner = escape_html(&record.learner),
+        description = escape_html(description),
+        record = escape_html(&record_json),
+    ))
+}
+
+fn default_badges() -> Vec<Badge> {
+    vec![
+        Badge {
+            id: "beginner-graduate".to_string(),
+            title: "Beginner Graduate".to_string(),
+            description: "Completed every Beginner lesson".to_string(),
+            rule: BadgeRule::DifficultyComplete {
+                difficulty: Difficulty::Beginner,
+            },
+        },
+        Badge {
+            id: "intro-quiz-ace".to_string(),
+            title: "Introduction Quiz Ace".to_string(),
+            description: "Scored at least 90% on the Introduction to Rust quiz".to_string(),
+            rule: BadgeRule::QuizScore {
+                quiz_id: "quiz-01-intro".to_string(),
+                min_score: 90.0,
+            },
+        },
+        Badge {
+            id: "course-complete".to_string(),
+            title: "Rust Course Graduate".to_string(),
+            description: "Completed every lesson of the course".to_string(),
+            rule: BadgeRule::AllLessonsComplete,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    #[test]
+    fn test_badges() {
+        let lessons = [
+            lesson("01-intro", Difficulty::Beginner, &[]),
+            lesson("02-variables", Difficulty::Beginner, &[]),
+            lesson("03-ownership", Difficulty::Intermediate, &[]),
+        ];
+        let lessons: Vec<&Lesson> = lessons.iter().collect();
+        let quiz_manager = QuizManager::new();
+        let quizzes = quiz_manager.list_quizzes();
+        let recommender = Recommender::new(lessons.clone(), quizzes.clone());
+
+        let run = |at: i64, lesson_id: &str| {
+            event(
+                at,
+                "alice",
+                ActivityKind::CodeRun {
+                    lesson_id: Some(lesson_id.to_string()),
+                    exit_code: 0,
+                    error_codes: vec![],
+                    code: None,
+                    passed: true,
+                },
+            )
+        };
+        let answer = |at: i64, question_id: &str, correct: bool| {
+            event(
+                at,
+                "alice",
+                ActivityKind::QuizAnswer {
+                    quiz_id: "quiz-01-intro".to_string(),
+                    question_id: question_id.to_string(),
+                    answer: UserAnswer::MultipleChoice { selected: 0 },
+                    correct,
+                },
+            )
+        };
+        let events = [
+            run(10, "01-intro"),
+            answer(15, "q1", true),
+            answer(16, "q2", false),
+            run(20, "02-variables"),
+        ];
+        let progress = recommender.progress("alice", &events);
+        let engine = BadgeEngine::new(default_badges(), b"secret");
+        let statuses = engine.evaluate("alice", &progress, &lessons, &quizzes);
+        let earned: Vec<_> = statuses
+            .iter()
+            .filter_map(|v| Some((v.badge.id.as_str(), v.record.as_ref()?.earned_at)))
+            .collect();
+        assert_eq!(earned, [("beginner-graduate", 20)]);
+
+        let record = statuses[0].record.clone().unwrap();
+        assert!(record.verify(b"secret"));
+        assert!(!record.verify(b"other"));
+        let mut forged = record.clone();
+        forged.learner = "mallory".to_string();
+        assert!(!forged.verify(b"secret"));
+
+        // Fixing the wrong answer earns the quiz badge
+        let mut events = events.to_vec();
+        events.push(answer(30, "q2", true));
+        let progress = recommender.progress("alice", &events);
+        let statuses = engine.evaluate("alice", &progress, &lessons, &quizzes);
+        assert_eq!(statuses[1].record.as_ref().map(|v| v.earned_at), Some(30));
+
+        // Without the key badges are still awarded, but nothing is signed
+        let statuses = BadgeEngine::unsigned(default_badges())
+            .evaluate("alice", &progress, &lessons, &quizzes);
+        assert_eq!(statuses[1].earned_at, Some(30));
+        assert!(statuses.iter().all(|v| v.record.is_none()));
+    }
+}
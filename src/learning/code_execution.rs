@@ -1,13 +1,24 @@
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Context, Result};
+use fancy_regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use std::{
     fs,
     path::PathBuf,
-    process::{Command, Stdio},
+    process::{Command, Output, Stdio},
+    sync::LazyLock,
     time::Duration,
 };
-use tokio::time::timeout;
+use tokio::{io::AsyncWriteExt, time::timeout};
 
-#[derive(Debug, Clone)]
+/// Crate name learner code is compiled under, which tells their functions from std's.
+const EMIT_CRATE_NAME: &str = "learner";
+pub const OPT_LEVELS: [&str; 6] = ["0", "1", "2", "3", "s", "z"];
+pub const EDITIONS: [&str; 4] = ["2015", "2018", "2021", "2024"];
+
+static RE_MANGLED_SYMBOL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?<![\w$.])_?(_ZN|_R)[\w$.]+").unwrap());
+
+#[derive(Debug, Clone, Serialize)]
 pub struct CodeExecutionResult {
     pub stdout: String,
     pub stderr: String,
@@ -15,6 +26,142 @@ pub struct CodeExecutionResult {
     pub execution_time: Duration,
 }
 
+/// What the compiler produces for a program, instead of running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmitMode {
+    Mir,
+    LlvmIr,
+    Asm,
+    /// The code after macro expansion, like `cargo expand`
+    Expand,
+    /// The high-level IR, with desugared loops and `?`
+    Hir,
+}
+
+impl EmitMode {
+    fn rustc_emit(&self) -> Option<&'static str> {
+        match self {
+            Self::Mir => Some("mir"),
+            Self::LlvmIr => Some("llvm-ir"),
+            Self::Asm => Some("asm"),
+            Self::Expand | Self::Hir => None,
+        }
+    }
+
+    /// The unstable `-Zunpretty` output for the modes rustc can't `--emit`.
+    fn unpretty(&self) -> &'static str {
+        match self {
+            Self::Hir => "hir",
+            _ => "expanded",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Mir => "mir",
+            Self::LlvmIr => "ll",
+            Self::Asm => "s",
+            Self::Expand | Self::Hir => "rs",
+        }
+    }
+}
+
+/// How learner code is built, the settings the Rust Playground offers.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub edition: String,
+    pub opt_level: String,
+    /// Build a library, which is only compiled unless `tests` is set
+    pub lib: bool,
+    /// Build with the test harness and run the tests
+    pub tests: bool,
+    pub backtrace: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            edition: "2021".to_string(),
+            opt_level: "0".to_string(),
+            lib: false,
+            tests: false,
+            backtrace: false,
+        }
+    }
+}
+
+impl BuildOptions {
+    pub fn validate(&self) -> Result<()> {
+        if !EDITIONS.contains(&self.edition.as_str()) {
+            bail!(
+                "Invalid edition '{}', expected one of {}",
+                self.edition,
+                EDITIONS.join(", ")
+            );
+        }
+        if !OPT_LEVELS.contains(&self.opt_level.as_str()) {
+            bail!(
+                "Invalid opt level '{}', expected one of {}",
+                self.opt_level,
+                OPT_LEVELS.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    fn rustc_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--edition".to_string(),
+            self.edition.clone(),
+            format!("-Copt-level={}", self.opt_level),
+        ];
+        if self.tests {
+            args.push("--test".to_string());
+        } else if self.lib {
+            args.extend(["--crate-type".to_string(), "lib".to_string()]);
+        }
+        args
+    }
+}
+
+/// How the output of [`RustCodeExecutor::emit_code_with_options`] is cleaned up.
+#[derive(Debug, Clone)]
+pub struct EmitOptions {
+    /// Only keep the learner's own functions, without directives or compiler boilerplate
+    pub filter: bool,
+    pub demangle: bool,
+    /// Intel rather than AT&T assembly syntax, on x86
+    pub intel_syntax: bool,
+}
+
+impl Default for EmitOptions {
+    fn default() -> Self {
+        Self {
+            filter: true,
+            demangle: true,
+            intel_syntax: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeEmitResult {
+    pub mode: EmitMode,
+    pub opt_level: String,
+    pub output: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    pub execution_time: Duration,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeFormatResult {
+    pub code: String,
+    pub stderr: String,
+    pub exit_code: i32,
+}
+
 #[derive(Debug)]
 pub struct RustCodeExecutor {
     temp_dir: PathBuf,
@@ -24,64 +171,232 @@ impl RustCodeExecutor {
     pub fn new() -> Result<Self> {
         let temp_dir = std::env::temp_dir().join("rust-learning");
         fs::create_dir_all(&temp_dir)?;
-        
+
         Ok(Self { temp_dir })
     }
 
     pub async fn execute_code(&self, code: &str) -> Result<CodeExecutionResult> {
+        self.execute_code_with_input(code, None).await
+    }
+
+    pub async fn execute_code_with_input(
+        &self,
+        code: &str,
+        input: Option<&str>,
+    ) -> Result<CodeExecutionResult> {
+        self.execute_code_with_options(code, input, &BuildOptions::default())
+            .await
+    }
+
+    pub async fn execute_code_with_options(
+        &self,
+        code: &str,
+        input: Option<&str>,
+        options: &BuildOptions,
+    ) -> Result<CodeExecutionResult> {
+        options.validate()?;
         let start_time = std::time::Instant::now();
-        
+
         // Create a unique temporary file
         let file_name = format!("temp_{}.rs", uuid::Uuid::new_v4());
         let source_file = self.temp_dir.join(&file_name);
-        let binary_file = self.temp_dir.join(&file_name.replace(".rs", ""));
+        let binary_file = self.temp_dir.join(file_name.replace(".rs", ""));
 
         // Write code to temporary file
         fs::write(&source_file, code)?;
 
-        // Compile the code
-        let compile_result = timeout(
-            Duration::from_secs(30),
-            self.compile_rust_code(&source_file, &binary_file)
-        ).await??;
-
-        if !compile_result.success {
-            return Ok(CodeExecutionResult {
+        // Compile the code, then execute the binary. A library without tests has nothing to run.
+        let result = match self
+            .compile_rust_code(&source_file, &binary_file, options)
+            .await
+        {
+            Ok(compile_result) if compile_result.success && (!options.lib || options.tests) => {
+                self.run_binary(&binary_file, input, options.backtrace)
+                    .await
+            }
+            Ok(compile_result) => Ok(ExecutionResult {
                 stdout: compile_result.stdout,
                 stderr: compile_result.stderr,
                 exit_code: compile_result.exit_code,
-                execution_time: start_time.elapsed(),
-            });
-        }
-
-        // Execute the compiled binary
-        let execution_result = timeout(
-            Duration::from_secs(10),
-            self.run_binary(&binary_file)
-        ).await??;
+            }),
+            Err(err) => Err(err),
+        };
 
         // Clean up temporary files
         let _ = fs::remove_file(&source_file);
         let _ = fs::remove_file(&binary_file);
 
+        let result = result?;
         Ok(CodeExecutionResult {
-            stdout: execution_result.stdout,
-            stderr: execution_result.stderr,
-            exit_code: execution_result.exit_code,
+            stdout: result.stdout,
+            stderr: result.stderr,
+            exit_code: result.exit_code,
             execution_time: start_time.elapsed(),
         })
     }
 
-    async fn compile_rust_code(&self, source_file: &PathBuf, binary_file: &PathBuf) -> Result<CompileResult> {
-        let output = Command::new("rustc")
+    /// Compile the code and return the MIR, LLVM IR, assembly or macro expansion at the
+    /// given opt level. Symbols are demangled, and with `filter` only the learner's own
+    /// functions are kept, without assembler directives or compiler boilerplate.
+    pub async fn emit_code(
+        &self,
+        code: &str,
+        mode: EmitMode,
+        opt_level: &str,
+        filter: bool,
+    ) -> Result<CodeEmitResult> {
+        let build = BuildOptions {
+            opt_level: opt_level.to_string(),
+            ..Default::default()
+        };
+        let options = EmitOptions {
+            filter,
+            ..Default::default()
+        };
+        self.emit_code_with_options(code, mode, &build, &options)
+            .await
+    }
+
+    pub async fn emit_code_with_options(
+        &self,
+        code: &str,
+        mode: EmitMode,
+        build: &BuildOptions,
+        options: &EmitOptions,
+    ) -> Result<CodeEmitResult> {
+        build.validate()?;
+        let start_time = std::time::Instant::now();
+        let file_name = format!("emit_{}", uuid::Uuid::new_v4());
+        let source_file = self.temp_dir.join(format!("{file_name}.rs"));
+        let output_file = self
+            .temp_dir
+            .join(format!("{file_name}.{}", mode.extension()));
+        fs::write(&source_file, code)?;
+
+        let mut command = tokio::process::Command::new("rustc");
+        command
+            .arg(&source_file)
+            .args(build.rustc_args())
+            .args(["--crate-name", EMIT_CRATE_NAME]);
+        match mode.rustc_emit() {
+            Some(emit) => {
+                command
+                    .arg(format!("--emit={emit}"))
+                    .arg("-o")
+                    .arg(&output_file);
+                if mode == EmitMode::Asm
+                    && options.intel_syntax
+                    && cfg!(any(target_arch = "x86", target_arch = "x86_64"))
+                {
+                    command.arg("-Cllvm-args=-x86-asm-syntax=intel");
+                }
+            }
+            // Unstable, so allowed on a stable toolchain the way cargo-expand does it
+            None => {
+                command
+                    .arg(format!("-Zunpretty={}", mode.unpretty()))
+                    .env("RUSTC_BOOTSTRAP", "1");
+            }
+        }
+        let output = run_command(command, None, Duration::from_secs(30)).await;
+        let emitted = fs::read_to_string(&output_file).ok();
+        let _ = fs::remove_file(&source_file);
+        let _ = fs::remove_file(&output_file);
+        let output = output?;
+
+        let raw = match mode.rustc_emit() {
+            Some(_) => emitted.unwrap_or_default(),
+            None => String::from_utf8_lossy(&output.stdout).to_string(),
+        };
+
+        // Filtering finds the learner's functions by their demangled names
+        let output_text = if options.demangle || options.filter {
+            demangle_symbols(&raw)
+        } else {
+            raw
+        };
+        let output_text = match (options.filter, mode) {
+            (false, _) => output_text,
+            (true, EmitMode::Mir) => filter_mir(&output_text),
+            (true, EmitMode::LlvmIr) => filter_llvm_ir(&output_text),
+            (true, EmitMode::Asm) => filter_asm(&output_text),
+            (true, EmitMode::Expand | EmitMode::Hir) => filter_expanded(&output_text),
+        };
+        Ok(CodeEmitResult {
+            mode,
+            opt_level: build.opt_level.clone(),
+            output: output_text,
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code().unwrap_or(-1),
+            execution_time: start_time.elapsed(),
+        })
+    }
+
+    /// Format the code with rustfmt.
+    pub async fn format_code(&self, code: &str, edition: &str) -> Result<CodeFormatResult> {
+        if !EDITIONS.contains(&edition) {
+            bail!(
+                "Invalid edition '{edition}', expected one of {}",
+                EDITIONS.join(", ")
+            );
+        }
+        let mut command = tokio::process::Command::new("rustfmt");
+        command.args(["--edition", edition]);
+        let output = run_command(command, Some(code), Duration::from_secs(10)).await?;
+
+        Ok(CodeFormatResult {
+            code: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code().unwrap_or(-1),
+        })
+    }
+
+    /// Check the code with clippy, whose lints end up in `stderr`.
+    pub async fn clippy_code(
+        &self,
+        code: &str,
+        options: &BuildOptions,
+    ) -> Result<CodeExecutionResult> {
+        options.validate()?;
+        let start_time = std::time::Instant::now();
+        let file_name = format!("clippy_{}", uuid::Uuid::new_v4());
+        let source_file = self.temp_dir.join(format!("{file_name}.rs"));
+        let output_file = self.temp_dir.join(format!("{file_name}.rmeta"));
+        fs::write(&source_file, code)?;
+
+        let mut command = tokio::process::Command::new("clippy-driver");
+        command
+            .arg(&source_file)
+            .args(options.rustc_args())
+            .arg("--emit=metadata")
+            .arg("-o")
+            .arg(&output_file);
+        let output = run_command(command, None, Duration::from_secs(30)).await;
+        let _ = fs::remove_file(&source_file);
+        let _ = fs::remove_file(&output_file);
+        let output = output?;
+
+        Ok(CodeExecutionResult {
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code().unwrap_or(-1),
+            execution_time: start_time.elapsed(),
+        })
+    }
+
+    async fn compile_rust_code(
+        &self,
+        source_file: &PathBuf,
+        binary_file: &PathBuf,
+        options: &BuildOptions,
+    ) -> Result<CompileResult> {
+        let mut command = tokio::process::Command::new("rustc");
+        command
             .arg(source_file)
             .arg("-o")
             .arg(binary_file)
-            .arg("--edition")
-            .arg("2021")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()?;
+            .args(options.rustc_args());
+        let output = run_command(command, None, Duration::from_secs(30)).await?;
 
         Ok(CompileResult {
             success: output.status.success(),
@@ -91,11 +406,17 @@ impl RustCodeExecutor {
         })
     }
 
-    async fn run_binary(&self, binary_file: &PathBuf) -> Result<ExecutionResult> {
-        let output = Command::new(binary_file)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()?;
+    async fn run_binary(
+        &self,
+        binary_file: &PathBuf,
+        input: Option<&str>,
+        backtrace: bool,
+    ) -> Result<ExecutionResult> {
+        let mut command = tokio::process::Command::new(binary_file);
+        if backtrace {
+            command.env("RUST_BACKTRACE", "1");
+        }
+        let output = run_command(command, input, Duration::from_secs(10)).await?;
 
         Ok(ExecutionResult {
             stdout: String::from_utf8_lossy(&output.stdout).to_string(),
@@ -104,6 +425,7 @@ impl RustCodeExecutor {
         })
     }
 
+    #[allow(unused)]
     pub fn validate_rust_syntax(&self, code: &str) -> Result<bool> {
         // Basic syntax validation using rustc --parse-only
         let file_name = format!("validate_{}.rs", uuid::Uuid::new_v4());
@@ -145,6 +467,130 @@ impl Default for RustCodeExecutor {
     }
 }
 
+/// Run the command to the end, writing `input` to its stdin while the output is read, so
+/// neither side can block on a full pipe. It is killed once `limit` is over.
+async fn run_command(
+    mut command: tokio::process::Command,
+    input: Option<&str>,
+    limit: Duration,
+) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| {
+            let program = command.as_std().get_program().to_string_lossy();
+            format!("Failed to run '{program}'")
+        })?;
+    let stdin = child.stdin.take();
+    let write_input = async move {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        }
+        // Dropping stdin closes it, as the program may read until the end
+    };
+    // Dropping the child on timeout kills it
+    let run = async { tokio::join!(write_input, child.wait_with_output()).1 };
+    match timeout(limit, run).await {
+        Ok(output) => Ok(output?),
+        Err(_) => bail!("Timed out after {} seconds", limit.as_secs()),
+    }
+}
+
+/// Replace mangled Rust symbols with readable paths, without their hashes.
+pub fn demangle_symbols(text: &str) -> String {
+    RE_MANGLED_SYMBOL
+        .replace_all(text, |caps: &Captures| {
+            let symbol = &caps[0];
+            match rustc_demangle::try_demangle(symbol) {
+                Ok(demangled) => format!("{demangled:#}"),
+                Err(_) => symbol.to_string(),
+            }
+        })
+        .to_string()
+}
+
+fn is_user_symbol(name: &str) -> bool {
+    let name = name.trim_matches('"');
+    let prefix = format!("{EMIT_CRATE_NAME}::");
+    name.starts_with(&prefix)
+        || name.starts_with(&format!("<{prefix}"))
+        || name.contains(&format!(" as {prefix}"))
+}
+
+fn filter_mir(text: &str) -> String {
+    text.lines()
+        .skip_while(|line| line.starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Keep the definitions of the learner's functions.
+fn filter_llvm_ir(text: &str) -> String {
+    let mut output = vec![];
+    let mut keep = false;
+    for line in text.lines() {
+        if let Some(rest) = line.strip_prefix("define ") {
+            let name = rest
+                .split_once('@')
+                .map(|(_, v)| v.rsplit_once('(').map_or(v, |(v, _)| v))
+                .unwrap_or_default();
+            keep = is_user_symbol(name);
+        }
+        if keep {
+            output.push(line);
+            if line == "}" {
+                output.push("");
+                keep = false;
+            }
+        }
+    }
+    output.join("\n").trim().to_string()
+}
+
+/// Keep the learner's functions, without assembler directives.
+fn filter_asm(text: &str) -> String {
+    let mut output = vec![];
+    let mut keep = false;
+    for line in text.lines() {
+        let is_label = line.ends_with(':') && !line.starts_with(|c: char| c.is_whitespace());
+        if is_label && !line.starts_with('.') {
+            if keep {
+                output.push("");
+            }
+            keep = is_user_symbol(line.trim_end_matches(':'));
+        }
+        let is_directive = line.trim_start().starts_with('.') && !is_label;
+        if keep && !is_directive {
+            output.push(line);
+        }
+    }
+    output.join("\n").trim().to_string()
+}
+
+/// Drop the prelude import the compiler adds to every crate.
+fn filter_expanded(text: &str) -> String {
+    const BOILERPLATE: [&str; 5] = [
+        "#![feature(prelude_import)]",
+        "#[prelude_import]",
+        "#[attr = PreludeImport]",
+        "#[macro_use]",
+        "extern crate std;",
+    ];
+    text.lines()
+        .filter(|line| {
+            !BOILERPLATE.contains(line) && !line.starts_with("use std::prelude::rust_20")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
 // Safety wrapper to limit what code can do
 pub fn is_safe_code(code: &str) -> bool {
     let dangerous_patterns = [
@@ -195,4 +641,74 @@ fn main() {
         assert!(is_safe_code(safe_code));
         assert!(!is_safe_code(unsafe_code));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_emit_asm() {
+        let executor = RustCodeExecutor::new().unwrap();
+        let code = r#"
+#[inline(never)]
+fn square(x: u32) -> u32 {
+    x * x
+}
+
+fn main() {
+    println!("{}", square(std::hint::black_box(7)));
+}
+"#;
+        let result = executor
+            .emit_code(code, EmitMode::Asm, "2", true)
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert!(result.output.contains("learner::square:"));
+        assert!(result.output.contains("learner::main:"));
+        assert!(!result.output.contains("lang_start"));
+        assert!(!result.output.contains(".cfi_"));
+        assert!(executor
+            .emit_code(code, EmitMode::Mir, "4", true)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_command() {
+        // Input larger than a pipe's buffer is written while the output is read
+        let input = "fn main() {}\n".repeat(100_000);
+        let output = run_command(
+            tokio::process::Command::new("cat"),
+            Some(&input),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.stdout.len(), input.len());
+
+        let start = std::time::Instant::now();
+        let mut command = tokio::process::Command::new("sleep");
+        command.arg("30");
+        let err = run_command(command, None, Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Timed out"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_input() {
+        let executor = RustCodeExecutor::new().unwrap();
+        let code = r#"
+fn main() {
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).unwrap();
+    println!("Hello, {}!", line.trim());
+}
+"#;
+
+        let result = executor
+            .execute_code_with_input(code, Some("Ferris\n"))
+            .await
+            .unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout.trim(), "Hello, Ferris!");
+    }
+}
@@ -0,0 +1,282 @@
+use crate::learning::*;
+
+use anyhow::{bail, Result};
+use bm25::{Language, SearchEngine, SearchEngineBuilder};
+use serde::Serialize;
+
+const SNIPPET_CHARS: usize = 200;
+
+/// A bm25 index over lesson and quiz content, rebuilt whenever the course changes.
+#[derive(Debug)]
+pub struct CourseIndex {
+    entries: Vec<CourseEntry>,
+    bm25: SearchEngine<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct CourseEntry {
+    kind: ContentKind,
+    id: String,
+    question_id: Option<String>,
+    title: String,
+    difficulty: Option<Difficulty>,
+    text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CourseSearchHit {
+    pub kind: ContentKind,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub question_id: Option<String>,
+    pub title: String,
+    pub difficulty: Option<Difficulty>,
+    /// HTML-escaped excerpt with matching terms wrapped in `<mark>`.
+    pub snippet: String,
+    pub score: f32,
+}
+
+impl CourseIndex {
+    pub fn build(lesson_manager: &LessonManager, quiz_manager: &QuizManager) -> Self {
+        let mut entries = vec![];
+        for lesson in lesson_manager.list_lessons() {
+            let mut text = format!(
+                "{}\n{}\n{}",
+                lesson.title, lesson.description, lesson.content
+            );
+            for example in &lesson.code_examples {
+                text.push_str(&format!(
+                    "\n{}\n{}\n{}",
+                    example.title, example.code, example.explanation
+                ));
+            }
+            entries.push(CourseEntry {
+                kind: ContentKind::Lesson,
+                id: lesson.id.clone(),
+                question_id: None,
+                title: lesson.title.clone(),
+                difficulty: Some(lesson.difficulty.clone()),
+                text,
+            });
+        }
+        // Exam questions stay out of search until learners sit the exam
+        let mut quizzes: Vec<&Quiz> = quiz_manager
+            .list_quizzes()
+            .into_iter()
+            .filter(|v| v.exam.is_none())
+            .collect();
+        quizzes.sort_by(|a, b| a.id.cmp(&b.id));
+        for quiz in quizzes {
+            let difficulty = quiz
+                .lesson_id
+                .as_deref()
+                .and_then(|id| lesson_manager.get_lesson(id))
+                .map(|v| v.difficulty.clone());
+            for question in &quiz.questions {
+                let mut text = format!("{}\n{}", question.title, question.content);
+                match &question.question_type {
+                    QuestionType::MultipleChoice { options, .. } => {
+                        for option in options {
+                            text.push_str(&format!("\n{option}"));
+                        }
+                    }
+                    QuestionType::CodeCompletion { template, .. } => {
+                        text.push_str(&format!("\n{template}"));
+                    }
+                    QuestionType::ShortAnswer { .. } => {}
+                }
+                entries.push(CourseEntry {
+                    kind: ContentKind::Quiz,
+                    id: quiz.id.clone(),
+                    question_id: Some(question.id.clone()),
+                    title: format!("{}: {}", quiz.title, question.title),
+                    difficulty: difficulty.clone(),
+                    text,
+                });
+            }
+        }
+        Self::from_entries(entries)
+    }
+
+    fn from_entries(entries: Vec<CourseEntry>) -> Self {
+        let documents = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| bm25::Document::new(i, &entry.text));
+        let bm25 = SearchEngineBuilder::<usize>::with_documents(Language::English, documents)
+            .k1(1.5)
+            .b(0.75)
+            .build();
+        Self { entries, bm25 }
+    }
+
+    pub fn search(
+        &self,
+        query: &str,
+        difficulty: Option<&Difficulty>,
+        limit: usize,
+    ) -> Vec<CourseSearchHit> {
+        let terms = query_terms(query);
+        self.bm25
+            .search(query, None)
+            .into_iter()
+            .filter_map(|result| {
+                let entry = self.entries.get(result.document.id)?;
+                if difficulty.is_some() && entry.difficulty.as_ref() != difficulty {
+                    return None;
+                }
+                Some(CourseSearchHit {
+                    kind: entry.kind,
+                    id: entry.id.clone(),
+                    question_id: entry.question_id.clone(),
+                    title: entry.title.clone(),
+                    difficulty: entry.difficulty.clone(),
+                    snippet: highlight_snippet(&entry.text, &terms),
+                    score: result.score,
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+}
+
+pub fn parse_difficulty(value: &str) -> Result<Difficulty> {
+    match value.to_lowercase().as_str() {
+        "beginner" => Ok(Difficulty::Beginner),
+        "intermediate" => Ok(Difficulty::Intermediate),
+        "advanced" => Ok(Difficulty::Advanced),
+        _ => {
+            bail!("Invalid difficulty '{value}', expected 'Beginner', 'Intermediate' or 'Advanced'")
+        }
+    }
+}
+
+fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    for term in query
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|v| v.chars().count() > 1)
+    {
+        let term = term.to_lowercase();
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// Cut an excerpt around the first matching term and mark every match in it.
+fn highlight_snippet(text: &str, terms: &[String]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| lowercase_char(*c)).collect();
+    let term_chars: Vec<Vec<char>> = terms.iter().map(|v| v.chars().collect()).collect();
+    let match_at = |i: usize| {
+        if i > 0 && lower[i - 1].is_alphanumeric() {
+            return None;
+        }
+        term_chars
+            .iter()
+            .find(|term| lower.get(i..i + term.len()) == Some(term.as_slice()))
+            .map(|term| term.len())
+    };
+
+    let first = (0..chars.len())
+        .find(|i| match_at(*i).is_some())
+        .unwrap_or(0);
+    let mut start = first.saturating_sub(SNIPPET_CHARS / 4);
+    while start > 0 && !chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
+    let end = (start + SNIPPET_CHARS).min(chars.len());
+
+    let mut output = String::new();
+    if start > 0 {
+        output.push_str("...");
+    }
+    let mut i = start;
+    while i < end {
+        match match_at(i) {
+            Some(len) => {
+                let matched: String = chars[i..i + len].iter().collect();
+                output.push_str(&format!("<mark>{}</mark>", escape_html(&matched)));
+                i += len;
+            }
+            None => {
+                output.push_str(&escape_html(&chars[i].to_string()));
+                i += 1;
+            }
+        }
+    }
+    if end < chars.len() {
+        output.push_str("...");
+    }
+    output.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lowercase without changing the number of chars, so offsets stay aligned with the original.
+fn lowercase_char(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(v), None) => v,
+        _ => c,
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_snippet() {
+        let terms = query_terms("Mut variables?");
+        assert_eq!(terms, vec!["mut", "variables"]);
+
+        let snippet = highlight_snippet("Use `mut` to make <Variables> mutable.", &terms);
+        assert_eq!(
+            snippet,
+            "Use `<mark>mut</mark>` to make &lt;<mark>Variables</mark>&gt; <mark>mut</mark>able."
+        );
+
+        let text = format!("{} ownership rules", "word ".repeat(100));
+        let snippet = highlight_snippet(&text, &query_terms("ownership"));
+        assert!(snippet.starts_with("...word"));
+        assert!(snippet.contains("<mark>ownership</mark> rules"));
+    }
+
+    #[test]
+    fn test_course_search() {
+        let quiz_manager = QuizManager::new();
+        let quiz = quiz_manager.get_quiz("quiz-01-intro").unwrap();
+        let entries = quiz
+            .questions
+            .iter()
+            .map(|question| CourseEntry {
+                kind: ContentKind::Quiz,
+                id: quiz.id.clone(),
+                question_id: Some(question.id.clone()),
+                title: question.title.clone(),
+                difficulty: Some(Difficulty::Beginner),
+                text: format!("{}\n{}", question.title, question.content),
+            })
+            .collect();
+        let index = CourseIndex::from_entries(entries);
+
+        let hits = index.search("entry point function", None, 10);
+        assert_eq!(hits[0].question_id.as_deref(), Some("q2"));
+        assert!(hits[0]
+            .snippet
+            .contains("<mark>Entry</mark> <mark>Point</mark>"));
+        assert!(index
+            .search("entry point", Some(&Difficulty::Advanced), 10)
+            .is_empty());
+        assert!(parse_difficulty("advanced").is_ok());
+        assert!(parse_difficulty("expert").is_err());
+    }
+}
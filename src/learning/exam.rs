@@ -0,0 +1,473 @@
+use crate::{
+    config::{write_file_atomic, Config},
+    learning::*,
+};
+
+use anyhow::{bail, Context, Result};
+use indexmap::IndexMap;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// Submissions are still accepted this long after the deadline, so an exam the page
+/// submits as time runs out isn't lost to network latency.
+const SUBMIT_GRACE_SECS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExamState {
+    NotStarted,
+    InProgress,
+    Submitted,
+    /// Started but not submitted before the deadline
+    Expired,
+}
+
+/// A learner's single attempt at an exam. Questions and options are shuffled for each
+/// learner, answers are stored against the original option order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExamAttempt {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cohort: Option<String>,
+    pub started_at: i64,
+    pub deadline: i64,
+    question_order: Vec<String>,
+    /// Original index of each displayed option, per multiple choice question.
+    #[serde(default)]
+    option_orders: HashMap<String, Vec<usize>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submitted_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub answers: HashMap<String, UserAnswer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<QuestionResult>,
+}
+
+impl ExamAttempt {
+    pub fn state(&self, now: i64) -> ExamState {
+        if self.submitted_at.is_some() {
+            ExamState::Submitted
+        } else if now > self.deadline + SUBMIT_GRACE_SECS {
+            ExamState::Expired
+        } else {
+            ExamState::InProgress
+        }
+    }
+
+    /// The questions as this learner sees them, without answers or hints.
+    fn paper(&self, quiz: &Quiz, with_explanations: bool) -> Vec<ExamQuestion> {
+        self.question_order
+            .iter()
+            .filter_map(|id| quiz.questions.iter().find(|v| &v.id == id))
+            .map(|question| {
+                let question_type = match &question.question_type {
+                    QuestionType::MultipleChoice { options, .. } => {
+                        let options = match self.option_orders.get(&question.id) {
+                            Some(order) => order
+                                .iter()
+                                .filter_map(|i| options.get(*i).cloned())
+                                .collect(),
+                            None => options.clone(),
+                        };
+                        ExamQuestionType::MultipleChoice { options }
+                    }
+                    QuestionType::CodeCompletion { template, .. } => {
+                        ExamQuestionType::CodeCompletion {
+                            template: template.clone(),
+                        }
+                    }
+                    QuestionType::ShortAnswer { .. } => ExamQuestionType::ShortAnswer,
+                };
+                ExamQuestion {
+                    id: question.id.clone(),
+                    title: question.title.clone(),
+                    content: question.content.clone(),
+                    question_type,
+                    explanation: question.explanation.clone().filter(|_| with_explanations),
+                }
+            })
+            .collect()
+    }
+
+    /// Map the selected options from the shuffled order back to the quiz's own.
+    fn unshuffle(&self, answers: HashMap<String, UserAnswer>) -> HashMap<String, UserAnswer> {
+        answers
+            .into_iter()
+            .map(|(id, answer)| match (answer, self.option_orders.get(&id)) {
+                (UserAnswer::MultipleChoice { selected }, Some(order)) => {
+                    let selected = order.get(selected).copied().unwrap_or(usize::MAX);
+                    (id, UserAnswer::MultipleChoice { selected })
+                }
+                (answer, _) => (id, answer),
+            })
+            .collect()
+    }
+
+    /// Quiz answers for the activity log, dated when the exam was submitted.
+    fn activity_events(&self, learner: &str, quiz_id: &str) -> Vec<ActivityEvent> {
+        let Some(submitted_at) = self.submitted_at else {
+            return vec![];
+        };
+        self.results
+            .iter()
+            .filter_map(|result| {
+                let answer = self.answers.get(&result.question_id)?;
+                Some(ActivityEvent {
+                    at: submitted_at,
+                    learner: learner.to_string(),
+                    cohort: self.cohort.clone(),
+                    kind: ActivityKind::QuizAnswer {
+                        quiz_id: quiz_id.to_string(),
+                        question_id: result.question_id.clone(),
+                        answer: answer.clone(),
+                        correct: result.correct,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExamQuestion {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub question_type: ExamQuestionType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+}
+
+/// A question type without its answer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum ExamQuestionType {
+    MultipleChoice { options: Vec<String> },
+    CodeCompletion { template: String },
+    ShortAnswer,
+}
+
+/// An exam from a learner's point of view. The questions are included while the exam is
+/// in progress, with explanations once it is over, and the score once results are released.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExamStatus {
+    pub quiz_id: String,
+    pub title: String,
+    pub description: String,
+    pub time_limit_minutes: u32,
+    pub state: ExamState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submitted_at: Option<i64>,
+    pub released: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub questions: Vec<ExamQuestion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<QuestionResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExamReport {
+    pub quiz_id: String,
+    pub title: String,
+    pub released_at: Option<i64>,
+    pub attempts: Vec<ExamAttemptSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExamAttemptSummary {
+    pub learner: String,
+    pub cohort: Option<String>,
+    pub state: ExamState,
+    pub started_at: i64,
+    pub submitted_at: Option<i64>,
+    pub score: Option<f32>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct ExamRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    released_at: Option<i64>,
+    #[serde(default)]
+    attempts: IndexMap<String, ExamAttempt>,
+}
+
+/// Exam attempts and whether their results are released, stored in `exams.yaml`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ExamStore {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    exams: IndexMap<String, ExamRecord>,
+}
+
+impl ExamStore {
+    pub fn init() -> Result<Self> {
+        Self::load(&Config::exams_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut store = if path.exists() {
+            let err = || format!("Failed to load exams at '{}'", path.display());
+            let content = read_to_string(path).with_context(err)?;
+            serde_yaml::from_str::<Self>(&content).with_context(err)?
+        } else {
+            Self::default()
+        };
+        store.path = path.to_path_buf();
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let content = serde_yaml::to_string(self).context("Failed to serde exams")?;
+        write_file_atomic(&self.path, &content)
+    }
+
+    /// `localized` is the quiz in the learner's language, used for the text of the questions.
+    pub fn status(&self, quiz: &Quiz, localized: &Quiz, learner: &str, now: i64) -> ExamStatus {
+        let record = self.exams.get(&quiz.id);
+        let released = record.is_some_and(|v| v.released_at.is_some());
+        let attempt = record.and_then(|v| v.attempts.get(learner));
+        let state = attempt.map_or(ExamState::NotStarted, |v| v.state(now));
+        let mut status = ExamStatus {
+            quiz_id: quiz.id.clone(),
+            title: localized.title.clone(),
+            description: localized.description.clone(),
+            time_limit_minutes: quiz.exam.as_ref().map_or(0, |v| v.time_limit_minutes),
+            state,
+            started_at: attempt.map(|v| v.started_at),
+            deadline: attempt.map(|v| v.deadline),
+            submitted_at: attempt.and_then(|v| v.submitted_at),
+            released,
+            questions: vec![],
+            score: None,
+            results: vec![],
+        };
+        let Some(attempt) = attempt else {
+            return status;
+        };
+        status.questions = attempt.paper(localized, state != ExamState::InProgress);
+        if released {
+            match state {
+                ExamState::Submitted => {
+                    status.score = attempt.score;
+                    status.results = attempt.results.clone();
+                }
+                ExamState::Expired => status.score = Some(0.0),
+                _ => {}
+            }
+        }
+        status
+    }
+
+    /// Start the learner's attempt, or carry on with it if it's still running.
+    pub fn start(&mut self, quiz: &Quiz, identity: &Identity, now: i64) -> Result<()> {
+        let Some(settings) = &quiz.exam else {
+            bail!("Quiz '{}' is not an exam", quiz.id);
+        };
+        let record = self.exams.entry(quiz.id.clone()).or_default();
+        match record
+            .attempts
+            .get(&identity.username)
+            .map(|v| v.state(now))
+        {
+            Some(ExamState::InProgress) => return Ok(()),
+            Some(ExamState::Submitted) => bail!("You have already submitted '{}'", quiz.title),
+            Some(_) => bail!("The time for '{}' is over", quiz.title),
+            None if record.released_at.is_some() => {
+                bail!(
+                    "The results of '{}' are out, it can no longer be taken",
+                    quiz.title
+                )
+            }
+            None => {}
+        }
+
+        let mut rng = rand::rng();
+        let mut question_order: Vec<String> = quiz.questions.iter().map(|v| v.id.clone()).collect();
+        question_order.shuffle(&mut rng);
+        let mut option_orders = HashMap::new();
+        for question in &quiz.questions {
+            if let QuestionType::MultipleChoice { options, .. } = &question.question_type {
+                let mut order: Vec<usize> = (0..options.len()).collect();
+                order.shuffle(&mut rng);
+                option_orders.insert(question.id.clone(), order);
+            }
+        }
+        let attempt = ExamAttempt {
+            cohort: identity.cohort.clone(),
+            started_at: now,
+            deadline: now + settings.time_limit_minutes as i64 * 60,
+            question_order,
+            option_orders,
+            submitted_at: None,
+            answers: HashMap::new(),
+            score: None,
+            results: vec![],
+        };
+        record.attempts.insert(identity.username.clone(), attempt);
+        self.save()
+    }
+
+    /// Grade and close the learner's attempt. Returns the quiz answers to add to the
+    /// activity log, which is nothing until the results are released.
+    pub fn submit(
+        &mut self,
+        quiz_manager: &QuizManager,
+        quiz: &Quiz,
+        learner: &str,
+        answers: HashMap<String, UserAnswer>,
+        now: i64,
+    ) -> Result<Vec<ActivityEvent>> {
+        let record = self.exams.get_mut(&quiz.id);
+        let released = record.as_ref().is_some_and(|v| v.released_at.is_some());
+        let Some(attempt) = record.and_then(|v| v.attempts.get_mut(learner)) else {
+            bail!("'{}' has not been started", quiz.title);
+        };
+        match attempt.state(now) {
+            ExamState::InProgress => {}
+            ExamState::Submitted => bail!("You have already submitted '{}'", quiz.title),
+            _ => bail!("The time for '{}' is over", quiz.title),
+        }
+        let mut quiz_attempt = QuizAttempt {
+            quiz_id: quiz.id.clone(),
+            answers: attempt.unshuffle(answers),
+            score: None,
+            completed_at: None,
+        };
+        attempt.results = quiz_manager.grade_quiz(quiz, &mut quiz_attempt);
+        attempt.answers = quiz_attempt.answers;
+        attempt.score = quiz_attempt.score;
+        attempt.submitted_at = Some(now);
+        let events = match released {
+            true => attempt.activity_events(learner, &quiz.id),
+            false => vec![],
+        };
+        self.save()?;
+        Ok(events)
+    }
+
+    /// Let learners see their scores. Returns the quiz answers of every submitted attempt,
+    /// so they can be added to the activity log.
+    pub fn release(&mut self, quiz: &Quiz, now: i64) -> Result<Vec<ActivityEvent>> {
+        if quiz.exam.is_none() {
+            bail!("Quiz '{}' is not an exam", quiz.id);
+        }
+        let record = self.exams.entry(quiz.id.clone()).or_default();
+        if record.released_at.is_some() {
+            bail!("The results of '{}' are already released", quiz.title);
+        }
+        record.released_at = Some(now);
+        let events = record
+            .attempts
+            .iter()
+            .flat_map(|(learner, attempt)| attempt.activity_events(learner, &quiz.id))
+            .collect();
+        self.save()?;
+        Ok(events)
+    }
+
+    pub fn report(&self, quiz: &Quiz, now: i64) -> ExamReport {
+        let record = self.exams.get(&quiz.id);
+        let attempts = record
+            .map(|v| v.attempts.iter())
+            .into_iter()
+            .flatten()
+            .map(|(learner, attempt)| ExamAttemptSummary {
+                learner: learner.clone(),
+                cohort: attempt.cohort.clone(),
+                state: attempt.state(now),
+                started_at: attempt.started_at,
+                submitted_at: attempt.submitted_at,
+                score: attempt.score,
+            })
+            .collect();
+        ExamReport {
+            quiz_id: quiz.id.clone(),
+            title: quiz.title.clone(),
+            released_at: record.and_then(|v| v.released_at),
+            attempts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    fn learner(username: &str) -> Identity {
+        Identity {
+            username: username.to_string(),
+            role: AccountRole::Learner,
+            cohort: None,
+        }
+    }
+
+    #[test]
+    fn test_exam_attempt() {
+        let quiz_manager = QuizManager::new();
+        let mut quiz = quiz_manager.get_quiz("quiz-01-intro").unwrap().clone();
+        quiz.exam = Some(ExamSettings {
+            time_limit_minutes: 10,
+        });
+        let path = temp_path("exams").with_extension("yaml");
+        let mut store = ExamStore::load(&path).unwrap();
+
+        store.start(&quiz, &learner("alice"), 1000).unwrap();
+        let status = store.status(&quiz, &quiz, "alice", 1100);
+        assert_eq!(status.state, ExamState::InProgress);
+        assert_eq!(status.deadline, Some(1600));
+        assert!(status.questions.iter().all(|v| v.explanation.is_none()));
+        let ExamQuestionType::MultipleChoice { options } = &status
+            .questions
+            .iter()
+            .find(|v| v.id == "q1")
+            .unwrap()
+            .question_type
+        else {
+            panic!("q1 is a multiple choice question");
+        };
+        let selected = options
+            .iter()
+            .position(|v| v == "Memory safety and performance")
+            .unwrap();
+        let answers = HashMap::from([("q1".to_string(), UserAnswer::MultipleChoice { selected })]);
+        let events = store
+            .submit(&quiz_manager, &quiz, "alice", answers, 1200)
+            .unwrap();
+        assert!(events.is_empty());
+        let status = store.status(&quiz, &quiz, "alice", 1300);
+        assert_eq!((status.state, status.score), (ExamState::Submitted, None));
+        assert!(status.questions.iter().all(|v| v.explanation.is_some()));
+        assert!(store.start(&quiz, &learner("alice"), 1300).is_err());
+
+        // Answers after the deadline are refused
+        store.start(&quiz, &learner("bob"), 1000).unwrap();
+        let late = 1000 + 600 + SUBMIT_GRACE_SECS + 1;
+        assert!(store
+            .submit(&quiz_manager, &quiz, "bob", HashMap::new(), late)
+            .is_err());
+
+        let events = store.release(&quiz, 2000).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0].kind,
+            ActivityKind::QuizAnswer { correct: true, .. }
+        ));
+        assert_eq!(store.status(&quiz, &quiz, "alice", 2000).score, Some(50.0));
+        assert_eq!(store.status(&quiz, &quiz, "bob", 2000).score, Some(0.0));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::learning::*;
+
+use std::path::PathBuf;
+
+pub fn lesson(id: &str, difficulty: Difficulty, topics: &[&str]) -> Lesson {
+    Lesson {
+        id: id.to_string(),
+        title: id.to_string(),
+        description: String::new(),
+        difficulty,
+        content: format!("# {id}"),
+        code_examples: vec![],
+        next_lesson: None,
+        prev_lesson: None,
+        topics: topics.iter().map(|v| v.to_string()).collect(),
+    }
+}
+
+pub fn code_example(code: &str, expected_output: Option<&str>) -> CodeExample {
+    CodeExample {
+        title: "Example".to_string(),
+        code: code.to_string(),
+        explanation: String::new(),
+        runnable: true,
+        expected_output: expected_output.map(|v| v.to_string()),
+        emit: None,
+        opt_level: None,
+    }
+}
+
+pub fn event(at: i64, learner: &str, kind: ActivityKind) -> ActivityEvent {
+    ActivityEvent {
+        at,
+        learner: learner.to_string(),
+        cohort: None,
+        kind,
+    }
+}
+
+pub fn view(at: i64, learner: &str, lesson_id: &str) -> ActivityEvent {
+    let lesson_id = lesson_id.to_string();
+    event(at, learner, ActivityKind::LessonView { lesson_id })
+}
+
+/// An answer to a question of `quiz-01-intro`.
+pub fn answer(
+    at: i64,
+    learner: &str,
+    question_id: &str,
+    selected: usize,
+    correct: bool,
+) -> ActivityEvent {
+    event(
+        at,
+        learner,
+        ActivityKind::QuizAnswer {
+            quiz_id: "quiz-01-intro".to_string(),
+            question_id: question_id.to_string(),
+            answer: UserAnswer::MultipleChoice { selected },
+            correct,
+        },
+    )
+}
+
+/// A run of the lesson's code, which passed unless it failed with errors.
+pub fn code_run(at: i64, learner: &str, lesson_id: &str, error_codes: &[&str]) -> ActivityEvent {
+    event(
+        at,
+        learner,
+        ActivityKind::CodeRun {
+            lesson_id: Some(lesson_id.to_string()),
+            exit_code: if error_codes.is_empty() { 0 } else { 1 },
+            error_codes: error_codes.iter().map(|v| v.to_string()).collect(),
+            code: None,
+            passed: error_codes.is_empty(),
+        },
+    )
+}
+
+/// A path in the temp dir that no other test or test run uses.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("aichat-{name}-{}", std::process::id()))
+}
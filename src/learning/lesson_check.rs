@@ -0,0 +1,349 @@
+use crate::{config::GlobalConfig, learning::*};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LessonCheckFormat {
+    Json,
+    Junit,
+}
+
+impl LessonCheckFormat {
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value.unwrap_or("json") {
+            "json" => Ok(Self::Json),
+            "junit" | "xml" => Ok(Self::Junit),
+            v => bail!("Invalid check format '{v}', expected 'json' or 'junit'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LessonCheckReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub suites: Vec<LessonCheckSuite>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LessonCheckSuite {
+    pub id: String,
+    pub title: String,
+    pub cases: Vec<LessonCheckCase>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LessonCheckCase {
+    pub name: String,
+    pub status: LessonCheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    pub time_ms: u128,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LessonCheckStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl LessonCheckReport {
+    pub fn is_success(&self) -> bool {
+        self.failed == 0
+    }
+
+    pub fn render(&self, format: LessonCheckFormat) -> Result<String> {
+        match format {
+            LessonCheckFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            LessonCheckFormat::Junit => Ok(self.to_junit()),
+        }
+    }
+
+    fn to_junit(&self) -> String {
+        let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        output.push_str(&format!(
+            "<testsuites name=\"lessons\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n",
+            self.passed + self.failed + self.skipped,
+            self.failed,
+            self.skipped
+        ));
+        for suite in &self.suites {
+            let count = |status| suite.cases.iter().filter(|v| v.status == status).count();
+            let time: u128 = suite.cases.iter().map(|v| v.time_ms).sum();
+            output.push_str(&format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+                xml_escape(&suite.id),
+                suite.cases.len(),
+                count(LessonCheckStatus::Failed),
+                count(LessonCheckStatus::Skipped),
+                time as f64 / 1000.0,
+            ));
+            for case in &suite.cases {
+                output.push_str(&format!(
+                    "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                    xml_escape(&suite.id),
+                    xml_escape(&case.name),
+                    case.time_ms as f64 / 1000.0,
+                ));
+                let message = xml_escape(case.message.as_deref().unwrap_or_default());
+                match case.status {
+                    LessonCheckStatus::Passed => output.push_str(" />\n"),
+                    LessonCheckStatus::Skipped => {
+                        output.push_str(&format!(">\n      <skipped message=\"{message}\" />\n"));
+                        output.push_str("    </testcase>\n");
+                    }
+                    LessonCheckStatus::Failed => {
+                        output.push_str(&format!(">\n      <failure message=\"{message}\" />\n"));
+                        if !case.stdout.is_empty() {
+                            output.push_str(&format!(
+                                "      <system-out>{}</system-out>\n",
+                                xml_escape(&case.stdout)
+                            ));
+                        }
+                        if !case.stderr.is_empty() {
+                            output.push_str(&format!(
+                                "      <system-err>{}</system-err>\n",
+                                xml_escape(&case.stderr)
+                            ));
+                        }
+                        output.push_str("    </testcase>\n");
+                    }
+                }
+            }
+            output.push_str("  </testsuite>\n");
+        }
+        output.push_str("</testsuites>\n");
+        output
+    }
+}
+
+/// Compile and run every runnable lesson example and every code-completion quiz solution.
+pub async fn check_lessons(config: &GlobalConfig) -> Result<LessonCheckReport> {
+    let lesson_manager = LessonManager::new(config.clone())?;
+    let quiz_manager = QuizManager::new();
+    let executor = RustCodeExecutor::new()?;
+
+    let mut quizzes = quiz_manager.list_quizzes();
+    quizzes.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut suites = vec![];
+    for lesson in lesson_manager.list_lessons() {
+        let mut cases = vec![];
+        for (i, example) in lesson.code_examples.iter().enumerate() {
+            let name = format!("example[{i}] {}", example.title);
+            if !example.runnable {
+                cases.push(LessonCheckCase::skipped(name, "not runnable"));
+                continue;
+            }
+            let case = check_code(
+                &executor,
+                name,
+                &example.code,
+                None,
+                example.expected_output.as_deref(),
+            )
+            .await;
+            cases.push(case);
+        }
+        for quiz in quizzes
+            .iter()
+            .filter(|v| v.lesson_id.as_deref() == Some(&lesson.id))
+        {
+            cases.extend(check_quiz(&executor, quiz).await);
+        }
+        suites.push(LessonCheckSuite {
+            id: lesson.id.clone(),
+            title: lesson.title.clone(),
+            cases,
+        });
+    }
+    for quiz in quizzes.iter().filter(|v| {
+        v.lesson_id
+            .as_deref()
+            .and_then(|id| lesson_manager.get_lesson(id))
+            .is_none()
+    }) {
+        suites.push(LessonCheckSuite {
+            id: quiz.id.clone(),
+            title: quiz.title.clone(),
+            cases: check_quiz(&executor, quiz).await,
+        });
+    }
+
+    let count = |status| {
+        suites
+            .iter()
+            .flat_map(|v| v.cases.iter())
+            .filter(|v| v.status == status)
+            .count()
+    };
+    Ok(LessonCheckReport {
+        passed: count(LessonCheckStatus::Passed),
+        failed: count(LessonCheckStatus::Failed),
+        skipped: count(LessonCheckStatus::Skipped),
+        suites,
+    })
+}
+
+async fn check_quiz(executor: &RustCodeExecutor, quiz: &Quiz) -> Vec<LessonCheckCase> {
+    let mut cases = vec![];
+    for question in &quiz.questions {
+        let QuestionType::CodeCompletion {
+            solution,
+            test_cases,
+            ..
+        } = &question.question_type
+        else {
+            continue;
+        };
+        let name = format!("{}/{} solution", quiz.id, question.id);
+        if test_cases.is_empty() {
+            cases.push(check_code(executor, name, solution, None, None).await);
+            continue;
+        }
+        for (i, test_case) in test_cases.iter().enumerate() {
+            let case = check_code(
+                executor,
+                format!("{name} [{i}]"),
+                solution,
+                Some(&test_case.input),
+                Some(&test_case.expected_output),
+            )
+            .await;
+            cases.push(case);
+        }
+    }
+    cases
+}
+
+async fn check_code(
+    executor: &RustCodeExecutor,
+    name: String,
+    code: &str,
+    input: Option<&str>,
+    expected_output: Option<&str>,
+) -> LessonCheckCase {
+    let start_time = Instant::now();
+    if !is_safe_code(code) {
+        return LessonCheckCase::failed(name, "Rejected by the code safety check", start_time);
+    }
+    let input = input.filter(|v| !v.is_empty());
+    let result = match executor.execute_code_with_input(code, input).await {
+        Ok(v) => v,
+        Err(err) => return LessonCheckCase::failed(name, &err.to_string(), start_time),
+    };
+    let message = if result.exit_code != 0 {
+        Some(format!("Exited with code {}", result.exit_code))
+    } else {
+        expected_output
+            .filter(|expected| normalize_output(expected) != normalize_output(&result.stdout))
+            .map(|expected| format!("Expected output {expected:?}, got {:?}", result.stdout))
+    };
+    LessonCheckCase {
+        name,
+        status: match message {
+            Some(_) => LessonCheckStatus::Failed,
+            None => LessonCheckStatus::Passed,
+        },
+        message,
+        stdout: result.stdout,
+        stderr: result.stderr,
+        time_ms: start_time.elapsed().as_millis(),
+    }
+}
+
+impl LessonCheckCase {
+    fn failed(name: String, message: &str, start_time: Instant) -> Self {
+        Self {
+            name,
+            status: LessonCheckStatus::Failed,
+            message: Some(message.to_string()),
+            stdout: String::new(),
+            stderr: String::new(),
+            time_ms: start_time.elapsed().as_millis(),
+        }
+    }
+
+    fn skipped(name: String, message: &str) -> Self {
+        Self {
+            name,
+            status: LessonCheckStatus::Skipped,
+            message: Some(message.to_string()),
+            stdout: String::new(),
+            stderr: String::new(),
+            time_ms: 0,
+        }
+    }
+}
+
+/// Compare program output ignoring surrounding blank lines and trailing spaces.
+pub(crate) fn normalize_output(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(|v| v.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_output() {
+        assert_eq!(normalize_output("a  \r\nb\n\n"), "a\nb");
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let report = LessonCheckReport {
+            passed: 1,
+            failed: 1,
+            skipped: 0,
+            suites: vec![LessonCheckSuite {
+                id: "01-intro".into(),
+                title: "Intro".into(),
+                cases: vec![
+                    LessonCheckCase {
+                        name: "example[0] Hello".into(),
+                        status: LessonCheckStatus::Passed,
+                        message: None,
+                        stdout: "Hello".into(),
+                        stderr: String::new(),
+                        time_ms: 10,
+                    },
+                    LessonCheckCase {
+                        name: "example[1] <Broken>".into(),
+                        status: LessonCheckStatus::Failed,
+                        message: Some("Exited with code 1".into()),
+                        stdout: String::new(),
+                        stderr: "error[E0308]".into(),
+                        time_ms: 20,
+                    },
+                ],
+            }],
+        };
+        let output = report.render(LessonCheckFormat::Junit).unwrap();
+        assert!(output.contains("<testsuites name=\"lessons\" tests=\"2\" failures=\"1\""));
+        assert!(output.contains("name=\"example[1] &lt;Broken&gt;\""));
+        assert!(output.contains("<failure message=\"Exited with code 1\" />"));
+        assert!(output.contains("<system-err>error[E0308]</system-err>"));
+    }
+}
@@ -1,5 +1,9 @@
-use crate::config::GlobalConfig;
-use anyhow::{anyhow, Result};
+use crate::{
+    config::{write_file_atomic, GlobalConfig},
+    learning::*,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -7,7 +11,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Lesson {
     pub id: String,
     pub title: String,
@@ -17,27 +21,52 @@ pub struct Lesson {
     pub code_examples: Vec<CodeExample>,
     pub next_lesson: Option<String>,
     pub prev_lesson: Option<String>,
+    /// Concepts the lesson teaches, used to recommend review material.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Lesson {
+    /// Whether a run printed the expected output of one of the lesson's examples, which is
+    /// what completes the lesson.
+    pub fn is_solved_by(&self, stdout: &str) -> bool {
+        let stdout = normalize_output(stdout);
+        self.code_examples
+            .iter()
+            .filter_map(|v| v.expected_output.as_deref())
+            .any(|expected| normalize_output(expected) == stdout)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Difficulty {
     Beginner,
     Intermediate,
     Advanced,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CodeExample {
     pub title: String,
     pub code: String,
     pub explanation: String,
     pub runnable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_output: Option<String>,
+    /// Show what the compiler produces for the example next to its source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emit: Option<EmitMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opt_level: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct LessonManager {
     lessons: HashMap<String, Lesson>,
     lesson_order: Vec<String>,
+    paths: HashMap<String, PathBuf>,
+    translations: Translations<Lesson>,
+    #[allow(unused)]
     config: GlobalConfig,
 }
 
@@ -46,15 +75,18 @@ impl LessonManager {
         let mut manager = Self {
             lessons: HashMap::new(),
             lesson_order: Vec::new(),
+            paths: HashMap::new(),
+            translations: Translations::default(),
             config,
         };
         manager.load_lessons()?;
         Ok(manager)
     }
 
+    /// Load every lesson file, replacing the lessons in memory only if all of them parse.
     pub fn load_lessons(&mut self) -> Result<()> {
         let lessons_dir = self.get_lessons_dir();
-        
+
         // If lessons directory doesn't exist, create it with default lessons
         if !lessons_dir.exists() {
             fs::create_dir_all(&lessons_dir)?;
@@ -62,22 +94,150 @@ impl LessonManager {
         }
 
         // Load all lesson files
+        let mut lessons = HashMap::new();
+        let mut paths = HashMap::new();
+        let mut translations = Translations::default();
         for entry in fs::read_dir(&lessons_dir)? {
             let entry = entry?;
             let path = entry.path();
-            
-            if path.extension().and_then(|s| s.to_str()) == Some("yaml") {
-                let content = fs::read_to_string(&path)?;
-                let lesson: Lesson = serde_yaml::from_str(&content)?;
-                self.lessons.insert(lesson.id.clone(), lesson);
+
+            let Some(file) = ContentFile::classify(&path) else {
+                continue;
+            };
+            let content = fs::read_to_string(&path)?;
+            let lesson: Lesson = serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to load lesson at '{}'", path.display()))?;
+            match file {
+                ContentFile::Default => {
+                    paths.insert(lesson.id.clone(), path);
+                    lessons.insert(lesson.id.clone(), lesson);
+                }
+                ContentFile::Translation(locale) => {
+                    let id = lesson.id.clone();
+                    translations.insert(&path, &locale, &id, lesson);
+                }
             }
         }
+        self.lessons = lessons;
+        self.paths = paths;
+        self.translations = translations;
 
         // Build lesson order
         self.build_lesson_order();
         Ok(())
     }
 
+    pub fn create_lesson(&mut self, lesson: Lesson) -> Result<()> {
+        validate_lesson(&lesson)?;
+        if self.lessons.contains_key(&lesson.id) {
+            bail!("Lesson '{}' already exists", lesson.id);
+        }
+        let path = self.get_lessons_dir().join(format!("{}.yaml", lesson.id));
+        if path.exists() {
+            bail!("Lesson file '{}' already exists", path.display());
+        }
+        self.write_lesson(lesson, path)
+    }
+
+    /// Replace an existing lesson. The id cannot change, since other lessons link to it.
+    pub fn update_lesson(&mut self, id: &str, lesson: Lesson) -> Result<()> {
+        validate_lesson(&lesson)?;
+        if lesson.id != id {
+            bail!("Lesson id '{}' does not match '{id}'", lesson.id);
+        }
+        let path = self
+            .paths
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Lesson not found: {id}"))?;
+        self.write_lesson(lesson, path)
+    }
+
+    pub fn delete_lesson(&mut self, id: &str) -> Result<Lesson> {
+        let path = self
+            .paths
+            .get(id)
+            .ok_or_else(|| anyhow!("Lesson not found: {id}"))?;
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove lesson at '{}'", path.display()))?;
+        self.paths.remove(id);
+        let lesson = self
+            .lessons
+            .remove(id)
+            .ok_or_else(|| anyhow!("Lesson not found: {id}"))?;
+        self.build_lesson_order();
+        Ok(lesson)
+    }
+
+    /// Re-read one lesson file after it changed on disk. If the file no longer parses,
+    /// the previous version of the lesson stays in place and the error is returned.
+    pub fn reload_lesson_file(&mut self, path: &Path) -> Result<Option<ContentChange>> {
+        match ContentFile::classify(path) {
+            Some(ContentFile::Default) => {}
+            Some(ContentFile::Translation(locale)) => {
+                return self.reload_translation_file(path, &locale)
+            }
+            None => return Ok(None),
+        }
+        let old_id = self
+            .paths
+            .iter()
+            .find(|(_, v)| v.as_path() == path)
+            .map(|(id, _)| id.clone());
+        if !path.exists() {
+            let Some(id) = old_id else {
+                return Ok(None);
+            };
+            self.paths.remove(&id);
+            self.lessons.remove(&id);
+            self.build_lesson_order();
+            return Ok(Some(ContentChange::removed(ContentKind::Lesson, &id)));
+        }
+        let content = fs::read_to_string(path)?;
+        let lesson: Lesson = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to load lesson at '{}'", path.display()))?;
+        if let Some(old_id) = old_id.filter(|v| v != &lesson.id) {
+            self.paths.remove(&old_id);
+            self.lessons.remove(&old_id);
+        } else if self.lessons.get(&lesson.id) == Some(&lesson) {
+            return Ok(None);
+        }
+        let id = lesson.id.clone();
+        self.paths.insert(id.clone(), path.to_path_buf());
+        self.lessons.insert(id.clone(), lesson);
+        self.build_lesson_order();
+        Ok(Some(ContentChange::updated(ContentKind::Lesson, &id)))
+    }
+
+    /// A translation changing is reported as an update of the lesson it translates.
+    fn reload_translation_file(
+        &mut self,
+        path: &Path,
+        locale: &str,
+    ) -> Result<Option<ContentChange>> {
+        if !path.exists() {
+            let id = self.translations.remove_path(path);
+            return Ok(id.map(|id| ContentChange::updated(ContentKind::Lesson, &id)));
+        }
+        let content = fs::read_to_string(path)?;
+        let lesson: Lesson = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to load lesson at '{}'", path.display()))?;
+        let id = lesson.id.clone();
+        if !self.translations.insert(path, locale, &id, lesson) {
+            return Ok(None);
+        }
+        Ok(Some(ContentChange::updated(ContentKind::Lesson, &id)))
+    }
+
+    fn write_lesson(&mut self, lesson: Lesson, path: PathBuf) -> Result<()> {
+        let content = serde_yaml::to_string(&lesson).context("Failed to serde lesson")?;
+        write_file_atomic(&path, &content)?;
+        self.paths.insert(lesson.id.clone(), path);
+        self.lessons.insert(lesson.id.clone(), lesson);
+        self.build_lesson_order();
+        Ok(())
+    }
+
     pub fn get_lesson(&self, id: &str) -> Option<&Lesson> {
         self.lessons.get(id)
     }
@@ -89,17 +249,41 @@ impl LessonManager {
             .collect()
     }
 
+    /// The lesson in the given locale, falling back to the default one.
+    pub fn get_localized_lesson(&self, id: &str, locale: &str) -> Option<&Lesson> {
+        let lesson = self.lessons.get(id)?;
+        Some(self.translations.get(locale, id).unwrap_or(lesson))
+    }
+
+    pub fn list_localized_lessons(&self, locale: &str) -> Vec<&Lesson> {
+        self.lesson_order
+            .iter()
+            .filter_map(|id| self.get_localized_lesson(id, locale))
+            .collect()
+    }
+
+    pub fn has_translation(&self, id: &str, locale: &str) -> bool {
+        self.translations.get(locale, id).is_some()
+    }
+
+    pub fn locales(&self) -> impl Iterator<Item = String> + '_ {
+        self.translations.locales().map(|v| v.to_string())
+    }
+
+    #[allow(unused)]
     pub fn get_lessons_by_difficulty(&self, difficulty: &Difficulty) -> Vec<&Lesson> {
         self.lessons
             .values()
-            .filter(|lesson| std::mem::discriminant(&lesson.difficulty) == std::mem::discriminant(difficulty))
+            .filter(|lesson| {
+                std::mem::discriminant(&lesson.difficulty) == std::mem::discriminant(difficulty)
+            })
             .collect()
     }
 
     pub fn render_lesson_html(&self, lesson: &Lesson) -> String {
         // Simple HTML rendering - replace markdown-like syntax
         let content = &lesson.content;
-        
+
         // Convert basic markdown to HTML
         let html = content
             .replace("# ", "<h1>")
@@ -110,12 +294,12 @@ impl LessonManager {
             .replace("**", "</strong>")
             .replace("- ", "<li>")
             .replace("\n", "</li>\n");
-            
+
         // Wrap in basic HTML structure
         format!("<div class='lesson-content'>{}</div>", html)
     }
 
-    fn get_lessons_dir(&self) -> PathBuf {
+    pub fn get_lessons_dir(&self) -> PathBuf {
         // Try to get lessons directory from config, default to ./lessons
         PathBuf::from("lessons")
     }
@@ -164,10 +348,14 @@ This program defines a `main` function, which is the entry point of every Rust p
 }"#.to_string(),
                     explanation: "This is the simplest Rust program. It prints 'Hello, World!' to the console.".to_string(),
                     runnable: true,
+                    expected_output: Some("Hello, World!".to_string()),
+                    emit: None,
+                    opt_level: None,
                 }
             ],
             next_lesson: Some("02-variables".to_string()),
             prev_lesson: None,
+            topics: vec!["basics".to_string(), "functions".to_string(), "macros".to_string()],
         };
 
         let variables_lesson = Lesson {
@@ -214,6 +402,9 @@ Rust has several built-in data types:
 }"#.to_string(),
                     explanation: "Variables are immutable by default in Rust.".to_string(),
                     runnable: true,
+                    expected_output: Some("The value of x is: 5".to_string()),
+                    emit: None,
+                    opt_level: None,
                 },
                 CodeExample {
                     title: "Mutable Variables".to_string(),
@@ -226,10 +417,14 @@ Rust has several built-in data types:
 }"#.to_string(),
                     explanation: "Use 'mut' to make variables mutable.".to_string(),
                     runnable: true,
+                    expected_output: Some("The value of x is: 5\nThe value of x is: 6".to_string()),
+                    emit: None,
+                    opt_level: None,
                 }
             ],
             next_lesson: Some("03-ownership".to_string()),
             prev_lesson: Some("01-intro".to_string()),
+            topics: vec!["variables".to_string(), "mutability".to_string(), "types".to_string()],
         };
 
         // Write lessons to files
@@ -243,4 +438,97 @@ Rust has several built-in data types:
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Ids that would be shadowed by `/api/lessons/preview` and `/api/lessons/reload`.
+const RESERVED_LESSON_IDS: [&str; 2] = ["preview", "reload"];
+
+/// Check that a lesson submitted through the authoring API is complete enough to save.
+pub fn validate_lesson(lesson: &Lesson) -> Result<()> {
+    if lesson.id.is_empty()
+        || !lesson
+            .id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(
+            "Invalid lesson id '{}', only letters, digits, '-' and '_' are allowed",
+            lesson.id
+        );
+    }
+    if RESERVED_LESSON_IDS.contains(&lesson.id.as_str()) {
+        bail!(
+            "Invalid lesson id '{}', it's reserved for a route",
+            lesson.id
+        );
+    }
+    if lesson.title.trim().is_empty() {
+        bail!("Lesson '{}' has no title", lesson.id);
+    }
+    if lesson.content.trim().is_empty() {
+        bail!("Lesson '{}' has no content", lesson.id);
+    }
+    for link in [&lesson.next_lesson, &lesson.prev_lesson]
+        .into_iter()
+        .flatten()
+    {
+        if link == &lesson.id {
+            bail!("Lesson '{}' cannot link to itself", lesson.id);
+        }
+    }
+    for (i, example) in lesson.code_examples.iter().enumerate() {
+        if example.title.trim().is_empty() || example.code.trim().is_empty() {
+            bail!(
+                "Code example {} of lesson '{}' needs a title and code",
+                i + 1,
+                lesson.id
+            );
+        }
+        if let Some(opt_level) = example
+            .opt_level
+            .as_deref()
+            .filter(|v| !OPT_LEVELS.contains(v))
+        {
+            bail!(
+                "Code example {} of lesson '{}' has an invalid opt level '{opt_level}'",
+                i + 1,
+                lesson.id
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    #[test]
+    fn test_validate_lesson() {
+        let lesson = |id| lesson(id, Difficulty::Intermediate, &["ownership"]);
+        assert!(validate_lesson(&lesson("03-ownership")).is_ok());
+        assert!(validate_lesson(&lesson("../03")).is_err());
+        assert!(validate_lesson(&lesson("")).is_err());
+        assert!(validate_lesson(&lesson("preview")).is_err());
+
+        let mut value = lesson("03-ownership");
+        value.next_lesson = Some("03-ownership".into());
+        assert!(validate_lesson(&value).is_err());
+
+        let mut value = lesson("03-ownership");
+        value.code_examples.push(code_example(" ", None));
+        assert!(validate_lesson(&value).is_err());
+    }
+
+    #[test]
+    fn test_is_solved_by() {
+        let mut value = lesson("03-ownership", Difficulty::Intermediate, &[]);
+        assert!(!value.is_solved_by(""));
+        value
+            .code_examples
+            .push(code_example("fn main() {}", Some("hello\nworld")));
+        assert!(value.is_solved_by("hello  \nworld\n"));
+        assert!(!value.is_solved_by(""));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::{
+    config::{GlobalConfig, Input},
+    learning::*,
+    utils::{extract_code_block, strip_think_tag},
+};
+
+use anyhow::{bail, Context, Result};
+use fancy_regex::Regex;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+/// The language lesson and quiz files are written in when they carry no locale suffix.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// UI strings for the learning page, one JSON catalog per locale.
+pub const UI_STRINGS: &[(&str, &str)] = &[
+    ("en", include_str!("../../assets/locales/en.json")),
+    ("fr", include_str!("../../assets/locales/fr.json")),
+];
+
+const DRAFT_SUFFIX: &str = ".draft";
+
+const TRANSLATE_PROMPT: &str = r#"Translate the following YAML document from a Rust course into the language with the locale code `__LOCALE__`.
+
+Rules:
+- Keep the exact same YAML structure and keys.
+- Do not change `id`, `lesson_id`, `next_lesson`, `prev_lesson`, `difficulty`, `topics`, `runnable`, `type`, `correct_answer` or any other identifier or number.
+- Keep Rust code unchanged, except for comments and the text of string literals shown to the user.
+- Keep the order of multiple choice options.
+- Output only the translated YAML, without code fences."#;
+
+static RE_LOCALE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z]{2}(-[a-z0-9]{2,8})*$").unwrap());
+
+/// ISO 639-1 language codes. A locale must start with one of them, so a suffix such as
+/// `.old` or `.bak` is never taken for a translation.
+const LANGUAGES: &str = "aa ab ae af ak am an ar as av ay az ba be bg bh bi bm bn bo br bs \
+    ca ce ch co cr cs cu cv cy da de dv dz ee el en eo es et eu fa ff fi fj fo fr fy ga gd \
+    gl gn gu gv ha he hi ho hr ht hu hy hz ia id ie ig ii ik io is it iu ja jv ka kg ki kj \
+    kk kl km kn ko kr ks ku kv kw ky la lb lg li ln lo lt lu lv mg mh mi mk ml mn mr ms mt \
+    my na nb nd ne ng nl nn no nr nv ny oc oj om or os pa pi pl ps pt qu rm rn ro ru rw sa \
+    sc sd se sg si sk sl sm sn so sq sr ss st su sv sw ta te tg th ti tk tl tn to tr ts tt \
+    tw ty ug uk ur uz ve vi vo wa wo xh yi yo za zh zu";
+
+/// How a file in the lessons or quizzes directory is loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentFile {
+    /// `01-intro.yaml`, written in the default locale
+    Default,
+    /// `01-intro.fr.yaml`, a variant of the lesson with the same id
+    Translation(String),
+}
+
+impl ContentFile {
+    /// Classify a path, or return `None` for files that are not loaded at all,
+    /// such as drafted translations waiting for review (`01-intro.fr.draft.yaml`).
+    pub fn classify(path: &Path) -> Option<Self> {
+        if path.extension().and_then(|v| v.to_str()) != Some("yaml") {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        if stem.ends_with(DRAFT_SUFFIX) {
+            return None;
+        }
+        match stem.rsplit_once('.').and_then(|(_, v)| normalize_locale(v)) {
+            Some(locale) => Some(Self::Translation(locale)),
+            None => Some(Self::Default),
+        }
+    }
+}
+
+/// Per-locale variants of lessons or quizzes, keyed by locale and then by id.
+#[derive(Debug)]
+pub struct Translations<T> {
+    items: HashMap<String, HashMap<String, T>>,
+    paths: HashMap<PathBuf, (String, String)>,
+}
+
+impl<T> Default for Translations<T> {
+    fn default() -> Self {
+        Self {
+            items: HashMap::new(),
+            paths: HashMap::new(),
+        }
+    }
+}
+
+impl<T: PartialEq> Translations<T> {
+    pub fn get(&self, locale: &str, id: &str) -> Option<&T> {
+        self.items.get(locale)?.get(id)
+    }
+
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.items.keys().map(|v| v.as_str())
+    }
+
+    /// Add or replace the variant loaded from `path`, returns whether anything changed.
+    pub fn insert(&mut self, path: &Path, locale: &str, id: &str, item: T) -> bool {
+        let key = (locale.to_string(), id.to_string());
+        let old_key = self.paths.insert(path.to_path_buf(), key.clone());
+        if let Some(old_key) = old_key.filter(|v| v != &key) {
+            self.remove_key(&old_key);
+        } else if self.get(locale, id) == Some(&item) {
+            return false;
+        }
+        self.items.entry(key.0).or_default().insert(key.1, item);
+        true
+    }
+
+    /// Forget the variant loaded from `path`, returns its id.
+    pub fn remove_path(&mut self, path: &Path) -> Option<String> {
+        let key = self.paths.remove(path)?;
+        self.remove_key(&key);
+        Some(key.1)
+    }
+
+    fn remove_key(&mut self, (locale, id): &(String, String)) {
+        if let Some(items) = self.items.get_mut(locale) {
+            items.remove(id);
+            if items.is_empty() {
+                self.items.remove(locale);
+            }
+        }
+    }
+}
+
+/// Lowercase a locale tag and use `-` as separator, e.g. `pt_BR` becomes `pt-br`. Tags in
+/// an unknown language are rejected.
+pub fn normalize_locale(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase().replace('_', "-");
+    let language = tag.split('-').next()?;
+    let known = LANGUAGES.split_whitespace().any(|v| v == language);
+    (known && RE_LOCALE.is_match(&tag).ok()?).then_some(tag)
+}
+
+/// Pick the best available locale, trying the explicitly requested one first, then the
+/// `Accept-Language` preferences by weight. A region falls back to its language, so
+/// `fr-CA` is served `fr`, and everything falls back to [`DEFAULT_LOCALE`].
+pub fn negotiate_locale(
+    requested: Option<&str>,
+    accept_language: Option<&str>,
+    available: &[String],
+) -> String {
+    let mut preferences: Vec<(&str, f32)> = accept_language
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let weight = parts
+                .find_map(|v| v.trim().strip_prefix("q="))
+                .map(|v| v.trim().parse().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && weight > 0.0).then_some((tag, weight))
+        })
+        .collect();
+    preferences.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let candidates = requested
+        .into_iter()
+        .chain(preferences.into_iter().map(|(tag, _)| tag))
+        .filter_map(normalize_locale);
+    for tag in candidates {
+        if available.contains(&tag) {
+            return tag;
+        }
+        if let Some((language, _)) = tag.split_once('-') {
+            if available.iter().any(|v| v == language) {
+                return language.to_string();
+            }
+        }
+    }
+    DEFAULT_LOCALE.to_string()
+}
+
+pub fn ui_strings(locale: &str) -> &'static str {
+    UI_STRINGS
+        .iter()
+        .find(|(v, _)| *v == locale)
+        .or_else(|| UI_STRINGS.iter().find(|(v, _)| *v == DEFAULT_LOCALE))
+        .map(|(_, v)| *v)
+        .unwrap_or("{}")
+}
+
+/// Every locale with at least some translated content or UI strings.
+pub fn available_locales(
+    lesson_manager: &LessonManager,
+    quiz_manager: &QuizManager,
+) -> Vec<String> {
+    let mut locales: BTreeSet<String> = UI_STRINGS.iter().map(|(v, _)| v.to_string()).collect();
+    locales.insert(DEFAULT_LOCALE.to_string());
+    locales.extend(lesson_manager.locales());
+    locales.extend(quiz_manager.locales());
+    locales.into_iter().collect()
+}
+
+/// Path of the drafted translation next to its source, e.g. `lessons/01-intro.fr.draft.yaml`.
+pub fn draft_translation_path(dir: &Path, id: &str, locale: &str) -> PathBuf {
+    dir.join(format!("{id}.{locale}{DRAFT_SUFFIX}.yaml"))
+}
+
+/// Draft translations of every lesson and quiz without one, using the current model.
+/// Drafts are ignored until a reviewer renames them to `<id>.<locale>.yaml`.
+pub async fn draft_translations(config: &GlobalConfig, locale: &str) -> Result<Vec<PathBuf>> {
+    let Some(locale) = normalize_locale(locale).filter(|v| v != DEFAULT_LOCALE) else {
+        bail!("Invalid locale '{locale}', expected a tag such as 'fr' or 'pt-BR'");
+    };
+    let lesson_manager = LessonManager::new(config.clone())?;
+    let quiz_manager = QuizManager::new();
+    let prompt = TRANSLATE_PROMPT.replace("__LOCALE__", &locale);
+    let mut drafted = vec![];
+
+    let lessons_dir = lesson_manager.get_lessons_dir();
+    for lesson in lesson_manager.list_lessons() {
+        if lesson_manager.has_translation(&lesson.id, &locale) {
+            continue;
+        }
+        let source = serde_yaml::to_string(lesson)?;
+        let text = translate_yaml(config, &prompt, &source).await?;
+        let translated: Lesson = serde_yaml::from_str(&text)
+            .with_context(|| format!("Invalid translation of lesson '{}'", lesson.id))?;
+        if translated.id != lesson.id
+            || translated.code_examples.len() != lesson.code_examples.len()
+        {
+            bail!(
+                "The translation of lesson '{}' changed its structure",
+                lesson.id
+            );
+        }
+        let path = draft_translation_path(&lessons_dir, &lesson.id, &locale);
+        fs::write(&path, serde_yaml::to_string(&translated)?)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+        drafted.push(path);
+    }
+
+    let quizzes_dir = QuizManager::quizzes_dir();
+    let mut quizzes = quiz_manager.list_quizzes();
+    quizzes.sort_by(|a, b| a.id.cmp(&b.id));
+    for quiz in quizzes {
+        if quiz_manager.has_translation(&quiz.id, &locale) {
+            continue;
+        }
+        let source = serde_yaml::to_string(quiz)?;
+        let text = translate_yaml(config, &prompt, &source).await?;
+        let translated: Quiz = serde_yaml::from_str(&text)
+            .with_context(|| format!("Invalid translation of quiz '{}'", quiz.id))?;
+        let same_questions = translated.questions.len() == quiz.questions.len()
+            && translated
+                .questions
+                .iter()
+                .zip(&quiz.questions)
+                .all(|(a, b)| a.id == b.id);
+        if translated.id != quiz.id || !same_questions {
+            bail!(
+                "The translation of quiz '{}' changed its structure",
+                quiz.id
+            );
+        }
+        fs::create_dir_all(&quizzes_dir)?;
+        let path = draft_translation_path(&quizzes_dir, &quiz.id, &locale);
+        fs::write(&path, serde_yaml::to_string(&translated)?)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+        drafted.push(path);
+    }
+    Ok(drafted)
+}
+
+async fn translate_yaml(config: &GlobalConfig, prompt: &str, source: &str) -> Result<String> {
+    let text = format!("{prompt}\n\n```yaml\n{source}```");
+    let input = Input::from_str(config, &text, None);
+    let output = input.fetch_chat_text().await?;
+    Ok(extract_code_block(&strip_think_tag(&output)).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_locale() {
+        let available = vec!["en".to_string(), "fr".to_string(), "pt-br".to_string()];
+        assert_eq!(negotiate_locale(None, None, &available), "en");
+        assert_eq!(
+            negotiate_locale(None, Some("de-DE,fr-CA;q=0.8,en;q=0.5"), &available),
+            "fr"
+        );
+        assert_eq!(
+            negotiate_locale(None, Some("en;q=0.2, pt-BR"), &available),
+            "pt-br"
+        );
+        assert_eq!(
+            negotiate_locale(Some("pt_BR"), Some("fr"), &available),
+            "pt-br"
+        );
+        assert_eq!(negotiate_locale(Some("de"), Some("*"), &available), "en");
+    }
+
+    #[test]
+    fn test_classify_content_file() {
+        let classify = |v: &str| ContentFile::classify(Path::new(v));
+        assert_eq!(
+            classify("lessons/01-intro.yaml"),
+            Some(ContentFile::Default)
+        );
+        assert_eq!(
+            classify("lessons/01-intro.fr.yaml"),
+            Some(ContentFile::Translation("fr".into()))
+        );
+        assert_eq!(
+            classify("lessons/01-intro.pt_BR.yaml"),
+            Some(ContentFile::Translation("pt-br".into()))
+        );
+        assert_eq!(
+            classify("lessons/01-intro.old.yaml"),
+            Some(ContentFile::Default)
+        );
+        assert_eq!(
+            classify("lessons/01-intro.bak.yaml"),
+            Some(ContentFile::Default)
+        );
+        assert_eq!(classify("lessons/01-intro.fr.draft.yaml"), None);
+        assert_eq!(classify("lessons/notes.md"), None);
+    }
+}
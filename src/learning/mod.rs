@@ -1,11 +1,39 @@
+pub mod accounts;
+pub mod analytics;
+pub mod badges;
+pub mod code_execution;
+pub mod course_search;
+pub mod exam;
+#[cfg(test)]
+mod fixtures;
+pub mod lesson_check;
 pub mod lessons;
+pub mod locale;
+pub mod playground;
+pub mod progress_bundle;
 pub mod quiz;
-pub mod code_execution;
+pub mod recommender;
 pub mod rust_docs_rag;
+pub mod similarity;
 pub mod test;
+pub mod tutor;
+pub mod watcher;
 
+pub use accounts::*;
+pub use analytics::*;
+pub use badges::*;
+pub use code_execution::*;
+pub use course_search::*;
+pub use exam::*;
+pub use lesson_check::*;
 pub use lessons::*;
+pub use locale::*;
+pub use playground::*;
+pub use progress_bundle::*;
 pub use quiz::*;
-pub use code_execution::*;
+pub use recommender::*;
 pub use rust_docs_rag::*;
-pub use test::*;
\ No newline at end of file
+pub use similarity::*;
+pub use test::*;
+pub use tutor::*;
+pub use watcher::*;
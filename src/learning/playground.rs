@@ -0,0 +1,257 @@
+use crate::learning::*;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Answered for code `is_safe_code` refuses, where compiler errors would go so editors show it.
+const REFUSED_CODE: &str = "Code contains potentially unsafe operations";
+
+/// Body of `/execute` on play.rust-lang.org. The local toolchain is used whatever the
+/// `channel`, and like the public playground a missing edition means 2015.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PlaygroundExecuteRequest {
+    pub channel: String,
+    pub mode: String,
+    pub edition: String,
+    pub crate_type: String,
+    pub tests: bool,
+    pub backtrace: bool,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaygroundExecuteResponse {
+    pub success: bool,
+    pub exit_detail: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PlaygroundCompileRequest {
+    pub target: String,
+    pub assembly_flavor: String,
+    pub demangle_assembly: String,
+    pub process_assembly: String,
+    #[serde(flatten)]
+    pub build: PlaygroundExecuteRequest,
+}
+
+/// Response of `/compile` and `/format`, where `code` is the compiler or rustfmt output.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaygroundCompileResponse {
+    pub success: bool,
+    pub exit_detail: String,
+    pub code: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PlaygroundFormatRequest {
+    pub channel: String,
+    pub edition: String,
+    pub code: String,
+}
+
+/// Body of the legacy `/evaluate.json`, still used by older snippets.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PlaygroundEvaluateRequest {
+    pub version: String,
+    pub optimize: String,
+    pub code: String,
+    pub edition: String,
+    pub tests: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaygroundEvaluateResponse {
+    pub result: String,
+    pub error: Option<String>,
+}
+
+impl PlaygroundExecuteRequest {
+    pub fn build_options(&self) -> Result<BuildOptions> {
+        let opt_level = match self.mode.as_str() {
+            "" | "debug" => "0",
+            "release" => "3",
+            mode => bail!("Invalid mode '{mode}', expected debug or release"),
+        };
+        let lib = match self.crate_type.as_str() {
+            "" | "bin" => false,
+            "lib" => true,
+            crate_type => bail!("Unsupported crate type '{crate_type}'"),
+        };
+        Ok(BuildOptions {
+            edition: playground_edition(&self.edition).to_string(),
+            opt_level: opt_level.to_string(),
+            lib,
+            tests: self.tests,
+            backtrace: self.backtrace,
+        })
+    }
+}
+
+impl PlaygroundCompileRequest {
+    pub fn emit_mode(&self) -> Result<EmitMode> {
+        match self.target.as_str() {
+            "asm" => Ok(EmitMode::Asm),
+            "llvm-ir" => Ok(EmitMode::LlvmIr),
+            "mir" => Ok(EmitMode::Mir),
+            "hir" => Ok(EmitMode::Hir),
+            target => bail!("Unsupported target '{target}'"),
+        }
+    }
+
+    pub fn emit_options(&self) -> EmitOptions {
+        EmitOptions {
+            filter: self.process_assembly != "raw",
+            demangle: self.demangle_assembly != "mangle",
+            intel_syntax: self.assembly_flavor != "att",
+        }
+    }
+}
+
+impl PlaygroundEvaluateRequest {
+    pub fn build_options(&self) -> BuildOptions {
+        BuildOptions {
+            edition: playground_edition(&self.edition).to_string(),
+            opt_level: if matches!(self.optimize.as_str(), "" | "0") {
+                "0"
+            } else {
+                "3"
+            }
+            .to_string(),
+            tests: self.tests,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<CodeExecutionResult> for PlaygroundExecuteResponse {
+    fn from(result: CodeExecutionResult) -> Self {
+        Self {
+            success: result.exit_code == 0,
+            exit_detail: exit_detail(result.exit_code),
+            stdout: result.stdout,
+            stderr: result.stderr,
+        }
+    }
+}
+
+impl From<CodeExecutionResult> for PlaygroundEvaluateResponse {
+    fn from(result: CodeExecutionResult) -> Self {
+        Self {
+            result: result.stdout,
+            error: (result.exit_code != 0).then_some(result.stderr),
+        }
+    }
+}
+
+impl From<CodeEmitResult> for PlaygroundCompileResponse {
+    fn from(result: CodeEmitResult) -> Self {
+        Self {
+            success: result.exit_code == 0,
+            exit_detail: exit_detail(result.exit_code),
+            code: result.output,
+            stdout: String::new(),
+            stderr: result.stderr,
+        }
+    }
+}
+
+impl From<CodeFormatResult> for PlaygroundCompileResponse {
+    fn from(result: CodeFormatResult) -> Self {
+        Self {
+            success: result.exit_code == 0,
+            exit_detail: exit_detail(result.exit_code),
+            code: result.code,
+            stdout: String::new(),
+            stderr: result.stderr,
+        }
+    }
+}
+
+impl PlaygroundExecuteResponse {
+    pub fn refused() -> Self {
+        Self {
+            success: false,
+            exit_detail: String::new(),
+            stdout: String::new(),
+            stderr: REFUSED_CODE.to_string(),
+        }
+    }
+}
+
+impl PlaygroundCompileResponse {
+    pub fn refused() -> Self {
+        Self {
+            success: false,
+            exit_detail: String::new(),
+            code: String::new(),
+            stdout: String::new(),
+            stderr: REFUSED_CODE.to_string(),
+        }
+    }
+}
+
+impl PlaygroundEvaluateResponse {
+    pub fn refused() -> Self {
+        Self {
+            result: String::new(),
+            error: Some(REFUSED_CODE.to_string()),
+        }
+    }
+}
+
+pub fn playground_edition(edition: &str) -> &str {
+    match edition {
+        "" => "2015",
+        edition => edition,
+    }
+}
+
+fn exit_detail(exit_code: i32) -> String {
+    match exit_code {
+        -1 => "Terminated by a signal".to_string(),
+        code => format!("Exited with status {code}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playground_requests() {
+        let req: PlaygroundCompileRequest = serde_json::from_str(
+            r#"{"channel":"stable","mode":"release","edition":"2021","crateType":"lib",
+            "tests":false,"code":"","backtrace":false,"target":"asm",
+            "assemblyFlavor":"att","demangleAssembly":"demangle","processAssembly":"raw"}"#,
+        )
+        .unwrap();
+        let build = req.build.build_options().unwrap();
+        assert_eq!((build.opt_level.as_str(), build.lib), ("3", true));
+        assert_eq!(req.emit_mode().unwrap(), EmitMode::Asm);
+        let options = req.emit_options();
+        assert!(!options.filter && options.demangle && !options.intel_syntax);
+
+        let req: PlaygroundEvaluateRequest =
+            serde_json::from_str(r#"{"version":"stable","optimize":"2","code":""}"#).unwrap();
+        let build = req.build_options();
+        assert_eq!(
+            (build.edition.as_str(), build.opt_level.as_str()),
+            ("2015", "3")
+        );
+
+        let req: PlaygroundExecuteRequest =
+            serde_json::from_str(r#"{"crateType":"proc-macro","code":""}"#).unwrap();
+        assert!(req.build_options().is_err());
+    }
+}
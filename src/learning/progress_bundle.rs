@@ -0,0 +1,239 @@
+use crate::{config::write_file_atomic, learning::*};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Bumped whenever the bundle layout changes in a way older versions can't read.
+pub const PROGRESS_BUNDLE_VERSION: u32 = 1;
+
+/// Everything a learner has done, in one JSON file that can be carried to another machine:
+/// their activity (quiz answers, code runs with the code of successful ones, lesson views)
+/// and their tutor conversations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressBundle {
+    pub version: u32,
+    pub learner: String,
+    pub exported_at: i64,
+    pub events: Vec<ActivityEvent>,
+    #[serde(default)]
+    pub tutor_sessions: Vec<TutorSessionFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TutorSessionFile {
+    pub lesson_id: String,
+    /// Last modification time of the session file, decides which copy wins on import.
+    pub updated_at: i64,
+    pub content: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ImportSummary {
+    pub learner: String,
+    pub events_added: usize,
+    pub events_skipped: usize,
+    pub sessions_imported: usize,
+    pub sessions_kept: usize,
+}
+
+impl ProgressBundle {
+    pub fn export(
+        learner: &str,
+        activity: &ActivityLog,
+        sessions_dir: &Path,
+        lesson_ids: &[String],
+    ) -> Result<Self> {
+        let events = activity
+            .load(&ActivityFilter::default())?
+            .into_iter()
+            .filter(|v| v.learner == learner)
+            .collect();
+        let mut tutor_sessions = vec![];
+        for lesson_id in lesson_ids {
+            let path = tutor_session_path(sessions_dir, learner, lesson_id);
+            let Some(updated_at) = modified_at(&path) else {
+                continue;
+            };
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read '{}'", path.display()))?;
+            tutor_sessions.push(TutorSessionFile {
+                lesson_id: lesson_id.clone(),
+                updated_at,
+                content,
+            });
+        }
+        Ok(Self {
+            version: PROGRESS_BUNDLE_VERSION,
+            learner: learner.to_string(),
+            exported_at: Utc::now().timestamp(),
+            events,
+            tutor_sessions,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let bundle: Self = serde_json::from_slice(data).context("Invalid progress bundle")?;
+        if bundle.version > PROGRESS_BUNDLE_VERSION {
+            bail!(
+                "Progress bundle version {} is newer than the supported version {PROGRESS_BUNDLE_VERSION}",
+                bundle.version
+            );
+        }
+        Ok(bundle)
+    }
+
+    /// Merge the bundle into local progress under the given learner name and cohort, the
+    /// bundle's own are ignored. Activity is combined, skipping events that are already
+    /// present, and for tutor sessions the copy modified last wins.
+    pub fn import(
+        self,
+        learner: &str,
+        cohort: Option<&str>,
+        activity: &ActivityLog,
+        sessions_dir: &Path,
+    ) -> Result<ImportSummary> {
+        let mut summary = ImportSummary {
+            learner: learner.to_string(),
+            ..Default::default()
+        };
+
+        let mut seen = activity
+            .load(&ActivityFilter::default())?
+            .iter()
+            .map(dedup_key)
+            .collect::<Result<HashSet<_>>>()?;
+        let mut events = vec![];
+        for mut event in self.events {
+            event.learner = learner.to_string();
+            event.cohort = cohort.map(|v| v.to_string());
+            if seen.insert(dedup_key(&event)?) {
+                events.push(event);
+            } else {
+                summary.events_skipped += 1;
+            }
+        }
+        summary.events_added = events.len();
+        activity.append(&events)?;
+
+        for session in self.tutor_sessions {
+            let path = tutor_session_path(sessions_dir, learner, &session.lesson_id);
+            if modified_at(&path).is_some_and(|v| v >= session.updated_at) {
+                summary.sessions_kept += 1;
+                continue;
+            }
+            write_file_atomic(&path, &session.content)?;
+            // Keep the original time, so importing the file back doesn't look like a newer edit
+            if let Some(time) = DateTime::from_timestamp(session.updated_at, 0) {
+                File::options()
+                    .write(true)
+                    .open(&path)?
+                    .set_modified(SystemTime::from(time))?;
+            }
+            summary.sessions_imported += 1;
+        }
+        Ok(summary)
+    }
+}
+
+/// What makes two events the same. The cohort is left out, as it is only a label of the
+/// learner at the time and may have changed since.
+fn dedup_key(event: &ActivityEvent) -> Result<String> {
+    let event = ActivityEvent {
+        cohort: None,
+        ..event.clone()
+    };
+    Ok(serde_json::to_string(&event)?)
+}
+
+fn tutor_session_path(sessions_dir: &Path, learner: &str, lesson_id: &str) -> PathBuf {
+    sessions_dir.join(format!("{}.yaml", tutor_session_name(learner, lesson_id)))
+}
+
+fn modified_at(path: &Path) -> Option<i64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(DateTime::<Utc>::from(modified).timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    #[test]
+    fn test_progress_bundle_roundtrip() {
+        let root = temp_path("progress");
+        let (laptop, lab) = (root.join("laptop"), root.join("lab"));
+        let lesson_ids = vec!["01-intro".to_string(), "02-variables".to_string()];
+
+        let laptop_log = ActivityLog::new(&laptop.join("activity.jsonl"));
+        laptop_log.record(&view(10, "alice", "01-intro")).unwrap();
+        laptop_log
+            .record(&view(30, "alice", "02-variables"))
+            .unwrap();
+        laptop_log.record(&view(40, "bob", "01-intro")).unwrap();
+        let laptop_sessions = laptop.join("sessions");
+        let session_path = tutor_session_path(&laptop_sessions, "alice", "01-intro");
+        write_file_atomic(&session_path, "messages: []\n").unwrap();
+
+        let lab_log = ActivityLog::new(&lab.join("activity.jsonl"));
+        lab_log.record(&view(10, "alice", "01-intro")).unwrap();
+        lab_log.record(&view(20, "carol", "02-variables")).unwrap();
+        let lab_sessions = lab.join("sessions");
+
+        let bundle =
+            ProgressBundle::export("alice", &laptop_log, &laptop_sessions, &lesson_ids).unwrap();
+        assert_eq!(bundle.events.len(), 2);
+        assert_eq!(bundle.tutor_sessions.len(), 1);
+
+        let data = serde_json::to_vec(&bundle).unwrap();
+        let summary = ProgressBundle::parse(&data)
+            .unwrap()
+            .import("alice", None, &lab_log, &lab_sessions)
+            .unwrap();
+        assert_eq!(summary.events_added, 1);
+        assert_eq!(summary.events_skipped, 1);
+        assert_eq!(summary.sessions_imported, 1);
+        let events = lab_log.load(&ActivityFilter::default()).unwrap();
+        let ats: Vec<_> = events.iter().map(|v| (v.at, v.learner.as_str())).collect();
+        assert_eq!(ats, [(10, "alice"), (20, "carol"), (30, "alice")]);
+
+        // Importing the same bundle again changes nothing
+        let summary = bundle
+            .import("alice", None, &lab_log, &lab_sessions)
+            .unwrap();
+        assert_eq!((summary.events_added, summary.sessions_kept), (0, 1));
+
+        // The cohort comes from the importer, never from the bundle
+        let mut forged = ProgressBundle::parse(&data).unwrap();
+        for event in forged.events.iter_mut() {
+            event.cohort = Some("forged".into());
+        }
+        forged
+            .import("dave", Some("rust-101"), &lab_log, &lab_sessions)
+            .unwrap();
+        let events = lab_log.load(&ActivityFilter::default()).unwrap();
+        assert!(events
+            .iter()
+            .filter(|v| v.learner == "dave")
+            .all(|v| v.cohort.as_deref() == Some("rust-101")));
+
+        // Moving to another cohort doesn't make the same events look new
+        let summary = ProgressBundle::parse(&data)
+            .unwrap()
+            .import("dave", Some("rust-102"), &lab_log, &lab_sessions)
+            .unwrap();
+        assert_eq!(summary.events_added, 0);
+
+        let mut future = ProgressBundle::parse(&data).unwrap();
+        future.version = PROGRESS_BUNDLE_VERSION + 1;
+        assert!(ProgressBundle::parse(&serde_json::to_vec(&future).unwrap()).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
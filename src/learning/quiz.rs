@@ -1,17 +1,32 @@
+use crate::learning::*;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Quiz {
     pub id: String,
     pub title: String,
     pub description: String,
     pub lesson_id: Option<String>,
     pub questions: Vec<Question>,
+    /// Makes the quiz an end-of-module assessment taken under exam conditions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exam: Option<ExamSettings>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExamSettings {
+    pub time_limit_minutes: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Question {
     pub id: String,
     pub question_type: QuestionType,
@@ -21,7 +36,7 @@ pub struct Question {
     pub explanation: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum QuestionType {
     MultipleChoice {
@@ -38,7 +53,7 @@ pub enum QuestionType {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TestCase {
     pub input: String,
     pub expected_output: String,
@@ -60,36 +75,160 @@ pub enum UserAnswer {
     ShortAnswer { text: String },
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionResult {
+    pub question_id: String,
+    pub correct: bool,
+    pub feedback: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct QuizManager {
     quizzes: HashMap<String, Quiz>,
+    paths: HashMap<String, PathBuf>,
+    translations: Translations<Quiz>,
 }
 
 impl QuizManager {
     pub fn new() -> Self {
         let mut manager = Self {
             quizzes: HashMap::new(),
+            paths: HashMap::new(),
+            translations: Translations::default(),
         };
-        manager.load_default_quizzes();
+        for quiz in Self::default_quizzes() {
+            manager.quizzes.insert(quiz.id.clone(), quiz);
+        }
+        manager.load_quiz_files();
         manager
     }
 
+    /// Quizzes in `./quizzes/*.yaml` are added to the built-in ones, replacing any with the same id.
+    pub fn quizzes_dir() -> PathBuf {
+        PathBuf::from("quizzes")
+    }
+
+    fn load_quiz_files(&mut self) {
+        let Ok(entries) = fs::read_dir(Self::quizzes_dir()) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if let Err(err) = self.reload_quiz_file(&entry.path()) {
+                warn!("{err:#}");
+            }
+        }
+    }
+
+    /// Re-read one quiz file after it changed on disk. If the file no longer parses,
+    /// the previous version of the quiz stays in place and the error is returned.
+    pub fn reload_quiz_file(&mut self, path: &Path) -> Result<Option<ContentChange>> {
+        match ContentFile::classify(path) {
+            Some(ContentFile::Default) => {}
+            Some(ContentFile::Translation(locale)) => {
+                return self.reload_translation_file(path, &locale)
+            }
+            None => return Ok(None),
+        }
+        let old_id = self
+            .paths
+            .iter()
+            .find(|(_, v)| v.as_path() == path)
+            .map(|(id, _)| id.clone());
+        if !path.exists() {
+            let Some(id) = old_id else {
+                return Ok(None);
+            };
+            return Ok(Some(self.remove_quiz_file(&id)));
+        }
+        let content = fs::read_to_string(path)?;
+        let quiz: Quiz = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to load quiz at '{}'", path.display()))?;
+        if let Some(old_id) = old_id.filter(|v| v != &quiz.id) {
+            self.remove_quiz_file(&old_id);
+        } else if self.quizzes.get(&quiz.id) == Some(&quiz) {
+            return Ok(None);
+        }
+        let id = quiz.id.clone();
+        self.paths.insert(id.clone(), path.to_path_buf());
+        self.quizzes.insert(id.clone(), quiz);
+        Ok(Some(ContentChange::updated(ContentKind::Quiz, &id)))
+    }
+
+    /// Drop the quiz a file defined. A built-in quiz the file replaced comes back.
+    fn remove_quiz_file(&mut self, id: &str) -> ContentChange {
+        self.paths.remove(id);
+        match Self::default_quizzes().into_iter().find(|v| v.id == id) {
+            Some(quiz) => {
+                self.quizzes.insert(quiz.id.clone(), quiz);
+                ContentChange::updated(ContentKind::Quiz, id)
+            }
+            None => {
+                self.quizzes.remove(id);
+                ContentChange::removed(ContentKind::Quiz, id)
+            }
+        }
+    }
+
+    /// A translation changing is reported as an update of the quiz it translates.
+    fn reload_translation_file(
+        &mut self,
+        path: &Path,
+        locale: &str,
+    ) -> Result<Option<ContentChange>> {
+        if !path.exists() {
+            let id = self.translations.remove_path(path);
+            return Ok(id.map(|id| ContentChange::updated(ContentKind::Quiz, &id)));
+        }
+        let content = fs::read_to_string(path)?;
+        let quiz: Quiz = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to load quiz at '{}'", path.display()))?;
+        let id = quiz.id.clone();
+        if !self.translations.insert(path, locale, &id, quiz) {
+            return Ok(None);
+        }
+        Ok(Some(ContentChange::updated(ContentKind::Quiz, &id)))
+    }
+
     pub fn get_quiz(&self, id: &str) -> Option<&Quiz> {
         self.quizzes.get(id)
     }
 
+    /// The quiz in the given locale, falling back to the default one.
+    pub fn get_localized_quiz(&self, id: &str, locale: &str) -> Option<&Quiz> {
+        let quiz = self.quizzes.get(id)?;
+        Some(self.translations.get(locale, id).unwrap_or(quiz))
+    }
+
+    pub fn has_translation(&self, id: &str, locale: &str) -> bool {
+        self.translations.get(locale, id).is_some()
+    }
+
+    pub fn locales(&self) -> impl Iterator<Item = String> + '_ {
+        self.translations.locales().map(|v| v.to_string())
+    }
+
     pub fn list_quizzes(&self) -> Vec<&Quiz> {
         self.quizzes.values().collect()
     }
 
-    pub fn evaluate_answer(&self, question: &Question, answer: &UserAnswer) -> (bool, Option<String>) {
+    pub fn evaluate_answer(
+        &self,
+        question: &Question,
+        answer: &UserAnswer,
+    ) -> (bool, Option<String>) {
         match (&question.question_type, answer) {
-            (QuestionType::MultipleChoice { correct_answer, .. }, UserAnswer::MultipleChoice { selected }) => {
+            (
+                QuestionType::MultipleChoice { correct_answer, .. },
+                UserAnswer::MultipleChoice { selected },
+            ) => {
                 let is_correct = *selected == *correct_answer;
                 let feedback = if is_correct {
                     Some("Correct!".to_string())
                 } else {
-                    Some(format!("Incorrect. The correct answer was option {}.", *correct_answer + 1))
+                    Some(format!(
+                        "Incorrect. The correct answer was option {}.",
+                        *correct_answer + 1
+                    ))
                 };
                 (is_correct, feedback)
             }
@@ -98,21 +237,59 @@ impl QuizManager {
                 let matching_keywords: Vec<_> = expected_keywords
                     .iter()
                     .filter(|keyword| text_lower.contains(&keyword.to_lowercase()))
+                    .map(|keyword| keyword.as_str())
                     .collect();
-                
+
                 let is_correct = !matching_keywords.is_empty();
                 let feedback = if is_correct {
-                    Some(format!("Good! You mentioned: {}", matching_keywords.join(", ")))
+                    Some(format!(
+                        "Good! You mentioned: {}",
+                        matching_keywords.join(", ")
+                    ))
                 } else {
-                    Some(format!("Try to include these concepts: {}", expected_keywords.join(", ")))
+                    Some(format!(
+                        "Try to include these concepts: {}",
+                        expected_keywords.join(", ")
+                    ))
                 };
                 (is_correct, feedback)
             }
-            _ => (false, Some("Answer type mismatch".to_string()))
+            _ => (false, Some("Answer type mismatch".to_string())),
         }
     }
 
-    fn load_default_quizzes(&mut self) {
+    /// Score the attempt, filling in its score (0-100) and completion time. Unanswered
+    /// questions count as incorrect.
+    pub fn grade_attempt(&self, attempt: &mut QuizAttempt) -> Result<Vec<QuestionResult>> {
+        let quiz = self
+            .get_quiz(&attempt.quiz_id)
+            .ok_or_else(|| anyhow!("Quiz not found: {}", attempt.quiz_id))?;
+        Ok(self.grade_quiz(quiz, attempt))
+    }
+
+    pub fn grade_quiz(&self, quiz: &Quiz, attempt: &mut QuizAttempt) -> Vec<QuestionResult> {
+        let results: Vec<_> = quiz
+            .questions
+            .iter()
+            .map(|question| {
+                let (correct, feedback) = match attempt.answers.get(&question.id) {
+                    Some(answer) => self.evaluate_answer(question, answer),
+                    None => (false, None),
+                };
+                QuestionResult {
+                    question_id: question.id.clone(),
+                    correct,
+                    feedback,
+                }
+            })
+            .collect();
+        let correct = results.iter().filter(|v| v.correct).count();
+        attempt.score = Some(correct as f32 * 100.0 / results.len().max(1) as f32);
+        attempt.completed_at = Some(Utc::now().to_rfc3339());
+        results
+    }
+
+    fn default_quizzes() -> Vec<Quiz> {
         // Create a sample quiz for the intro lesson
         let intro_quiz = Quiz {
             id: "quiz-01-intro".to_string(),
@@ -147,8 +324,9 @@ impl QuizManager {
                     explanation: Some("The main() function is the entry point where Rust programs begin execution.".to_string()),
                 },
             ],
+            exam: None,
         };
 
-        self.quizzes.insert(intro_quiz.id.clone(), intro_quiz);
+        vec![intro_quiz]
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,529 @@
+use crate::learning::*;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Share of quiz questions a learner must get right to pass a quiz or not be weak at a topic.
+const PASS_RATIO: f64 = 0.6;
+/// Mapped compile errors on a topic before it counts as weak.
+const WEAK_TOPIC_ERRORS: usize = 3;
+/// A lesson that took this much longer than the learner's median completion time is slow.
+const SLOW_COMPLETION_FACTOR: f64 = 1.5;
+/// Completed lessons needed before completion times are compared at all.
+const MIN_TIMED_COMPLETIONS: usize = 3;
+
+/// Compiler errors that point at a gap in a specific topic.
+pub const ERROR_TOPICS: &[(&str, &str, &str)] = &[
+    ("E0382", "ownership", "use of moved value"),
+    ("E0505", "ownership", "move out of borrowed value"),
+    ("E0499", "borrowing", "multiple mutable borrows"),
+    (
+        "E0502",
+        "borrowing",
+        "conflicting shared and mutable borrows",
+    ),
+    (
+        "E0384",
+        "mutability",
+        "assigning twice to an immutable variable",
+    ),
+    (
+        "E0596",
+        "mutability",
+        "borrowing an immutable value as mutable",
+    ),
+    ("E0308", "types", "mismatched types"),
+    ("E0599", "types", "no such method"),
+    ("E0425", "variables", "unresolved name"),
+    ("E0106", "lifetimes", "missing lifetime specifier"),
+    ("E0277", "traits", "trait bound not satisfied"),
+    ("E0061", "functions", "wrong number of arguments"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendationKind {
+    NextLesson,
+    ReviewQuiz,
+    Remedial,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Recommendation {
+    pub kind: RecommendationKind,
+    pub id: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
+    /// Questions worth retrying, for review quizzes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub question_ids: Vec<String>,
+    /// Human readable explanations of why the item was suggested.
+    pub reasons: Vec<String>,
+    pub score: f64,
+}
+
+/// What a learner has done so far, rebuilt from the activity log.
+#[derive(Debug, Default)]
+pub struct LearnerProgress {
+    first_viewed: HashMap<String, i64>,
+    completed: IndexMap<String, i64>,
+    answers: HashMap<(String, String), (bool, i64)>,
+    error_counts: IndexMap<String, usize>,
+}
+
+impl LearnerProgress {
+    pub fn is_completed(&self, lesson_id: &str) -> bool {
+        self.completed.contains_key(lesson_id)
+    }
+
+    pub fn completed_at(&self, lesson_id: &str) -> Option<i64> {
+        self.completed.get(lesson_id).copied()
+    }
+
+    /// Percent of the quiz's questions whose latest answer is right, and when the
+    /// last of those answers was given.
+    pub fn quiz_score(&self, quiz: &Quiz) -> Option<(f64, i64)> {
+        let mut correct = 0;
+        let mut answered_at = None;
+        for question in &quiz.questions {
+            let key = (quiz.id.clone(), question.id.clone());
+            if let Some((ok, at)) = self.answers.get(&key) {
+                correct += *ok as usize;
+                answered_at = answered_at.max(Some(*at));
+            }
+        }
+        if quiz.questions.is_empty() {
+            return None;
+        }
+        let score = correct as f64 * 100.0 / quiz.questions.len() as f64;
+        Some((score, answered_at?))
+    }
+
+    /// Seconds between the first view of a lesson and its completion.
+    fn completion_time(&self, lesson_id: &str) -> Option<i64> {
+        let viewed = self.first_viewed.get(lesson_id)?;
+        let completed = self.completed.get(lesson_id)?;
+        Some(completed - viewed).filter(|v| *v > 0)
+    }
+
+    /// Latest result of each answered question of the quiz, in question order.
+    fn quiz_results<'a>(&self, quiz: &'a Quiz) -> Vec<(&'a Question, bool)> {
+        quiz.questions
+            .iter()
+            .filter_map(|question| {
+                let key = (quiz.id.clone(), question.id.clone());
+                self.answers
+                    .get(&key)
+                    .map(|(correct, _)| (question, *correct))
+            })
+            .collect()
+    }
+}
+
+/// Suggests what a learner should study next from their quiz, compile and reading history.
+#[derive(Debug)]
+pub struct Recommender<'a> {
+    lessons: Vec<&'a Lesson>,
+    quizzes: Vec<&'a Quiz>,
+}
+
+impl<'a> Recommender<'a> {
+    pub fn new(lessons: Vec<&'a Lesson>, mut quizzes: Vec<&'a Quiz>) -> Self {
+        quizzes.sort_by(|a, b| a.id.cmp(&b.id));
+        Self { lessons, quizzes }
+    }
+
+    pub fn progress(&self, learner: &str, events: &[ActivityEvent]) -> LearnerProgress {
+        let mut progress = LearnerProgress::default();
+        for event in events.iter().filter(|v| v.learner == learner) {
+            match &event.kind {
+                ActivityKind::LessonView { lesson_id } => {
+                    progress
+                        .first_viewed
+                        .entry(lesson_id.clone())
+                        .or_insert(event.at);
+                }
+                ActivityKind::CodeRun {
+                    lesson_id,
+                    error_codes,
+                    passed,
+                    ..
+                } => {
+                    for code in error_codes {
+                        *progress.error_counts.entry(code.clone()).or_default() += 1;
+                    }
+                    if let Some(lesson_id) = lesson_id {
+                        if *passed {
+                            progress
+                                .completed
+                                .entry(lesson_id.clone())
+                                .or_insert(event.at);
+                        }
+                    }
+                }
+                ActivityKind::QuizAnswer {
+                    quiz_id,
+                    question_id,
+                    correct,
+                    ..
+                } => {
+                    progress
+                        .answers
+                        .insert((quiz_id.clone(), question_id.clone()), (*correct, event.at));
+                    let Some(quiz) = self.quizzes.iter().find(|v| &v.id == quiz_id) else {
+                        continue;
+                    };
+                    if let Some(lesson_id) = &quiz.lesson_id {
+                        let results = progress.quiz_results(quiz);
+                        let correct = results.iter().filter(|(_, v)| *v).count();
+                        if !quiz.questions.is_empty()
+                            && correct as f64 >= quiz.questions.len() as f64 * PASS_RATIO
+                        {
+                            progress
+                                .completed
+                                .entry(lesson_id.clone())
+                                .or_insert(event.at);
+                        }
+                    }
+                }
+            }
+        }
+        progress
+    }
+
+    pub fn recommend(&self, progress: &LearnerProgress) -> Vec<Recommendation> {
+        let mut output = self.remedial(progress);
+        output.extend(self.review_quizzes(progress));
+        if let Some(next) = self.next_lesson(progress) {
+            match output
+                .iter_mut()
+                .find(|v| v.kind == RecommendationKind::Remedial && v.id == next.id)
+            {
+                Some(existing) => existing.reasons.extend(next.reasons),
+                None => output.push(next),
+            }
+        }
+        output.sort_by(|a, b| b.score.total_cmp(&a.score));
+        output
+    }
+
+    fn remedial(&self, progress: &LearnerProgress) -> Vec<Recommendation> {
+        let mut weak_topics: IndexMap<String, Vec<String>> = IndexMap::new();
+
+        let mut topic_answers: IndexMap<&str, (usize, usize)> = IndexMap::new();
+        for quiz in &self.quizzes {
+            for topic in self.quiz_topics(quiz) {
+                let entry = topic_answers.entry(topic).or_default();
+                for (_, correct) in progress.quiz_results(quiz) {
+                    entry.0 += correct as usize;
+                    entry.1 += 1;
+                }
+            }
+        }
+        for (topic, (correct, total)) in topic_answers {
+            if total >= 2 && (correct as f64) < total as f64 * PASS_RATIO {
+                weak_topics
+                    .entry(topic.to_string())
+                    .or_default()
+                    .push(format!(
+                        "You answered {correct} of {total} questions on {topic} correctly ({}%)",
+                        correct * 100 / total
+                    ));
+            }
+        }
+
+        let mut topic_errors: IndexMap<&str, Vec<(&str, &str, usize)>> = IndexMap::new();
+        for (code, count) in &progress.error_counts {
+            if let Some((code, topic, summary)) = ERROR_TOPICS.iter().find(|(v, ..)| v == code) {
+                topic_errors
+                    .entry(topic)
+                    .or_default()
+                    .push((code, summary, *count));
+            }
+        }
+        for (topic, errors) in topic_errors {
+            if errors.iter().map(|(.., count)| count).sum::<usize>() >= WEAK_TOPIC_ERRORS {
+                let reasons = weak_topics.entry(topic.to_string()).or_default();
+                for (code, summary, count) in errors {
+                    reasons.push(format!(
+                        "Your code hit {code} ({summary}) {count} time{}",
+                        if count == 1 { "" } else { "s" }
+                    ));
+                }
+            }
+        }
+
+        let times: Vec<(&Lesson, i64)> = self
+            .lessons
+            .iter()
+            .filter_map(|lesson| Some((*lesson, progress.completion_time(&lesson.id)?)))
+            .collect();
+        if times.len() >= MIN_TIMED_COMPLETIONS {
+            let mut secs: Vec<i64> = times.iter().map(|(_, v)| *v).collect();
+            secs.sort_unstable();
+            let median = secs[secs.len() / 2];
+            for (lesson, secs) in times {
+                if secs as f64 > median as f64 * SLOW_COMPLETION_FACTOR {
+                    for topic in &lesson.topics {
+                        weak_topics.entry(topic.clone()).or_default().push(format!(
+                            "'{}' took you {}, longer than your usual {}",
+                            lesson.title,
+                            format_duration(secs),
+                            format_duration(median)
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut output: Vec<Recommendation> = vec![];
+        for (topic, reasons) in weak_topics {
+            let Some(lesson) = self.lessons.iter().find(|v| v.topics.contains(&topic)) else {
+                continue;
+            };
+            let score = 3.0 + reasons.len() as f64 * 0.1;
+            match output.iter_mut().find(|v| v.id == lesson.id) {
+                Some(existing) => {
+                    existing.topics.push(topic);
+                    existing.reasons.extend(reasons);
+                    existing.score = existing.score.max(score);
+                }
+                None => output.push(Recommendation {
+                    kind: RecommendationKind::Remedial,
+                    id: lesson.id.clone(),
+                    title: lesson.title.clone(),
+                    topics: vec![topic],
+                    question_ids: vec![],
+                    reasons,
+                    score,
+                }),
+            }
+        }
+        output
+    }
+
+    fn review_quizzes(&self, progress: &LearnerProgress) -> Vec<Recommendation> {
+        let mut output = vec![];
+        // Exams allow a single attempt, so there is nothing to retake
+        for quiz in self.quizzes.iter().filter(|v| v.exam.is_none()) {
+            let topics: Vec<String> = self.quiz_topics(quiz).map(|v| v.to_string()).collect();
+            let results = progress.quiz_results(quiz);
+            if results.is_empty() {
+                let lesson = quiz
+                    .lesson_id
+                    .as_deref()
+                    .filter(|id| progress.is_completed(id))
+                    .and_then(|id| self.lessons.iter().find(|v| v.id == id));
+                if let Some(lesson) = lesson {
+                    output.push(Recommendation {
+                        kind: RecommendationKind::ReviewQuiz,
+                        id: quiz.id.clone(),
+                        title: quiz.title.clone(),
+                        topics,
+                        question_ids: vec![],
+                        reasons: vec![format!(
+                            "You finished '{}' but haven't taken its quiz yet",
+                            lesson.title
+                        )],
+                        score: 1.5,
+                    });
+                }
+                continue;
+            }
+            let missed: Vec<&Question> = results
+                .iter()
+                .filter(|(_, correct)| !correct)
+                .map(|(question, _)| *question)
+                .collect();
+            if missed.is_empty() {
+                continue;
+            }
+            let titles: Vec<String> = missed.iter().map(|v| format!("'{}'", v.title)).collect();
+            output.push(Recommendation {
+                kind: RecommendationKind::ReviewQuiz,
+                id: quiz.id.clone(),
+                title: quiz.title.clone(),
+                topics,
+                question_ids: missed.iter().map(|v| v.id.clone()).collect(),
+                reasons: vec![format!(
+                    "Your last answers to {} were wrong",
+                    titles.join(", ")
+                )],
+                score: 2.0 + missed.len() as f64 / results.len() as f64,
+            });
+        }
+        output
+    }
+
+    fn next_lesson(&self, progress: &LearnerProgress) -> Option<Recommendation> {
+        let level = self
+            .lessons
+            .iter()
+            .filter(|v| progress.is_completed(&v.id))
+            .map(|v| difficulty_rank(&v.difficulty))
+            .max();
+        let pending = |rank: usize| {
+            self.lessons
+                .iter()
+                .find(|v| difficulty_rank(&v.difficulty) == rank && !progress.is_completed(&v.id))
+        };
+
+        let mut reasons = vec![];
+        let lesson = match level {
+            None => {
+                let lesson = self.lessons.first()?;
+                reasons.push("It's the first lesson of the course".to_string());
+                lesson
+            }
+            Some(level) => match pending(level) {
+                Some(lesson) => {
+                    reasons.push(format!(
+                        "It's the next {:?} lesson you haven't completed",
+                        lesson.difficulty
+                    ));
+                    lesson
+                }
+                None => {
+                    let lesson = (level + 1..=difficulty_rank(&Difficulty::Advanced))
+                        .find_map(pending)
+                        .or_else(|| self.lessons.iter().find(|v| !progress.is_completed(&v.id)))?;
+                    reasons.push(format!(
+                        "You've completed every {:?} lesson",
+                        self.lessons
+                            .iter()
+                            .find(|v| difficulty_rank(&v.difficulty) == level)?
+                            .difficulty
+                    ));
+                    lesson
+                }
+            },
+        };
+        if let Some(prev) = lesson
+            .prev_lesson
+            .as_deref()
+            .filter(|id| progress.is_completed(id))
+            .and_then(|id| self.lessons.iter().find(|v| v.id == id))
+        {
+            reasons.push(format!(
+                "It builds on '{}', which you've finished",
+                prev.title
+            ));
+        }
+        Some(Recommendation {
+            kind: RecommendationKind::NextLesson,
+            id: lesson.id.clone(),
+            title: lesson.title.clone(),
+            topics: lesson.topics.clone(),
+            question_ids: vec![],
+            reasons,
+            score: 1.0,
+        })
+    }
+
+    /// A quiz covers the topics of the lesson it belongs to.
+    fn quiz_topics(&self, quiz: &Quiz) -> impl Iterator<Item = &'a str> {
+        let lesson = quiz
+            .lesson_id
+            .as_deref()
+            .and_then(|id| self.lessons.iter().find(|v| v.id == id).copied());
+        let mut seen = HashSet::new();
+        lesson
+            .into_iter()
+            .flat_map(|v| v.topics.iter().map(|v| v.as_str()))
+            .filter(move |v| seen.insert(*v))
+    }
+}
+
+fn difficulty_rank(difficulty: &Difficulty) -> usize {
+    match difficulty {
+        Difficulty::Beginner => 0,
+        Difficulty::Intermediate => 1,
+        Difficulty::Advanced => 2,
+    }
+}
+
+fn format_duration(secs: i64) -> String {
+    if secs < 60 {
+        format!("{secs}s")
+    } else if secs < 3600 {
+        format!("{} min", secs / 60)
+    } else {
+        format!("{}h {} min", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    #[test]
+    fn test_recommend() {
+        let lessons = [
+            lesson("01-intro", Difficulty::Beginner, &["basics", "functions"]),
+            lesson(
+                "02-variables",
+                Difficulty::Beginner,
+                &["variables", "mutability"],
+            ),
+            lesson("03-ownership", Difficulty::Intermediate, &["ownership"]),
+        ];
+        let quiz_manager = QuizManager::new();
+        let recommender = Recommender::new(
+            lessons.iter().collect(),
+            quiz_manager
+                .list_quizzes()
+                .into_iter()
+                .filter(|v| v.id == "quiz-01-intro")
+                .collect(),
+        );
+
+        let progress = recommender.progress("alice", &[]);
+        let items = recommender.recommend(&progress);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].kind, RecommendationKind::NextLesson);
+        assert_eq!(items[0].id, "01-intro");
+
+        let events = [
+            answer(10, "alice", "q1", 0, false),
+            answer(20, "alice", "q2", 0, false),
+            code_run(30, "alice", "02-variables", &["E0384"]),
+            code_run(40, "alice", "02-variables", &["E0384"]),
+            code_run(50, "alice", "02-variables", &["E0596"]),
+            code_run(60, "alice", "02-variables", &[]),
+        ];
+        let progress = recommender.progress("alice", &events);
+        assert!(progress.is_completed("02-variables"));
+        assert!(!progress.is_completed("01-intro"));
+        // Code that runs without printing the expected output completes nothing
+        let mut empty_main = code_run(70, "alice", "03-ownership", &[]);
+        if let ActivityKind::CodeRun { passed, .. } = &mut empty_main.kind {
+            *passed = false;
+        }
+        let empty_progress = recommender.progress("alice", &[empty_main]);
+        assert!(!empty_progress.is_completed("03-ownership"));
+
+        let items = recommender.recommend(&progress);
+        let kinds: Vec<_> = items.iter().map(|v| (v.kind, v.id.as_str())).collect();
+        assert_eq!(
+            kinds,
+            [
+                (RecommendationKind::Remedial, "02-variables"),
+                (RecommendationKind::Remedial, "01-intro"),
+                (RecommendationKind::ReviewQuiz, "quiz-01-intro"),
+            ]
+        );
+        assert_eq!(items[0].topics, ["mutability"]);
+        assert!(items[0].reasons[0].contains("E0384"));
+        assert_eq!(items[1].topics, ["basics", "functions"]);
+        assert!(items[1].reasons[0].contains("0 of 2 questions on basics"));
+        // The next lesson is also the remedial one, so its reasons are merged
+        assert!(items[1]
+            .reasons
+            .iter()
+            .any(|v| v.contains("next Beginner lesson")));
+        assert_eq!(items[2].question_ids, ["q1", "q2"]);
+    }
+}
@@ -1,68 +1,321 @@
 use crate::{config::GlobalConfig, rag::Rag, utils::*};
-use anyhow::{anyhow, Result};
-use std::path::PathBuf;
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::{
+    fs::{remove_file, rename},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, LazyLock},
+};
+
+pub const RUST_DOCS_RAG_NAME: &str = "rust-docs";
+
+pub const RUST_TOOLCHAIN_METADATA: &str = "rust_toolchain";
+
+/// The toolchain doesn't change while the process runs, so rustc is only asked once.
+static LOCAL_RUST_DOCS: LazyLock<Option<LocalRustDocs>> = LazyLock::new(LocalRustDocs::find);
+
+/// HTML documentation installed by `rustup component add rust-docs`.
+#[derive(Debug, Clone)]
+pub struct LocalRustDocs {
+    pub html_dir: PathBuf,
+    pub version: String,
+}
+
+impl LocalRustDocs {
+    const BOOKS: [&'static str; 4] = ["book", "std", "reference", "rust-by-example"];
+
+    pub fn detect() -> Option<Self> {
+        LOCAL_RUST_DOCS.clone()
+    }
+
+    fn find() -> Option<Self> {
+        let sysroot = rustc_output(&["--print", "sysroot"])?;
+        let html_dir = Path::new(&sysroot).join("share/doc/rust/html");
+        Self::from_dir(html_dir, rustc_output(&["--version"])?)
+    }
+
+    fn from_dir(html_dir: PathBuf, version: String) -> Option<Self> {
+        let docs = Self { html_dir, version };
+        if docs.document_paths().is_empty() {
+            return None;
+        }
+        Some(docs)
+    }
+
+    pub fn document_paths(&self) -> Vec<String> {
+        Self::BOOKS
+            .iter()
+            .map(|v| self.html_dir.join(v))
+            .filter(|v| v.is_dir())
+            .map(|v| format!("{}/**/*.html", v.display()))
+            .collect()
+    }
+
+    /// Whether the rag was built from the docs of a different toolchain.
+    pub fn is_stale(&self, rag: &Rag) -> bool {
+        rag.document_tag(RUST_TOOLCHAIN_METADATA)
+            .is_some_and(|v| v != self.version)
+    }
+}
 
 pub async fn setup_rust_docs_rag(config: &GlobalConfig) -> Result<()> {
     println!("📚 Setting up Rust documentation RAG...");
-    
+
     // Check if Rust docs RAG already exists
-    let rag_name = "rust-docs";
+    let rag_name = RUST_DOCS_RAG_NAME;
     let rag_path = config.read().rag_file(rag_name);
-    
+    let local_docs = LocalRustDocs::detect();
+
     if rag_path.exists() {
-        println!("✅ Rust docs RAG already exists at {}", rag_path.display());
-        return Ok(());
-    }
-    
-    // Define Rust documentation sources
-    let rust_doc_sources = vec![
-        // Core Rust documentation
-        "https://doc.rust-lang.org/book/**".to_string(),
-        "https://doc.rust-lang.org/std/**".to_string(),
-        "https://doc.rust-lang.org/reference/**".to_string(),
-        "https://doc.rust-lang.org/rust-by-example/**".to_string(),
-        
-        // Additional learning resources
-        "https://doc.rust-lang.org/rustc/**".to_string(),
-        "https://doc.rust-lang.org/cargo/**".to_string(),
-    ];
-    
+        let rag = Rag::load(config, rag_name, &rag_path)?;
+        match &local_docs {
+            Some(docs) if docs.is_stale(&rag) => {
+                println!(
+                    "🔄 Rust toolchain changed from '{}' to '{}', rebuilding Rust docs RAG...",
+                    rag.document_tag(RUST_TOOLCHAIN_METADATA)
+                        .unwrap_or_default(),
+                    docs.version
+                );
+            }
+            _ => {
+                println!("✅ Rust docs RAG already exists at {}", rag_path.display());
+                return Ok(());
+            }
+        }
+    }
+
+    // Prefer the docs installed with the toolchain, fall back to doc.rust-lang.org
+    let rust_doc_sources = match &local_docs {
+        Some(docs) => {
+            println!(
+                "📦 Using local Rust docs for '{}' at {}",
+                docs.version,
+                docs.html_dir.display()
+            );
+            docs.document_paths()
+        }
+        None => {
+            println!("💡 No local Rust docs found, run `rustup component add rust-docs` to set up offline");
+            vec![
+                // Core Rust documentation
+                "https://doc.rust-lang.org/book/**".to_string(),
+                "https://doc.rust-lang.org/std/**".to_string(),
+                "https://doc.rust-lang.org/reference/**".to_string(),
+                "https://doc.rust-lang.org/rust-by-example/**".to_string(),
+                // Additional learning resources
+                "https://doc.rust-lang.org/rustc/**".to_string(),
+                "https://doc.rust-lang.org/cargo/**".to_string(),
+            ]
+        }
+    };
+
     println!("🔄 Creating Rust docs RAG with sources:");
     for source in &rust_doc_sources {
         println!("  - {}", source);
     }
-    
-    // Create abort signal for the operation
-    let abort_signal = create_abort_signal();
-    
-    // Initialize the RAG with Rust documentation
-    match Rag::init(config, rag_name, &rag_path, &rust_doc_sources, abort_signal).await {
-        Ok(_) => {
+
+    // Build next to the current RAG and swap it in once complete, so a failed rebuild keeps it
+    let build_path = rag_path.with_file_name(format!("{rag_name}.building.yaml"));
+    match build_rust_docs_rag(config, &build_path, &rust_doc_sources, local_docs.as_ref()).await {
+        Ok(()) => {
+            rename(&build_path, &rag_path)?;
             println!("✅ Successfully created Rust docs RAG!");
             println!("💡 You can now use RAG-powered Rust documentation lookup in lessons");
         }
         Err(e) => {
+            let _ = remove_file(&build_path);
             println!("⚠️ Failed to create Rust docs RAG: {}", e);
             println!("💡 You can manually add Rust documentation later using the --rag flag");
         }
     }
-    
+
     Ok(())
 }
 
-pub async fn search_rust_docs(config: &GlobalConfig, query: &str) -> Result<String> {
-    let rag_name = "rust-docs";
-    let rag_path = config.read().rag_file(rag_name);
-    
+async fn build_rust_docs_rag(
+    config: &GlobalConfig,
+    path: &Path,
+    sources: &[String],
+    local_docs: Option<&LocalRustDocs>,
+) -> Result<()> {
+    if !*IS_STDOUT_TERMINAL {
+        bail!("Failed to init rag in non-interactive mode");
+    }
+    let mut rag = Rag::empty(config, RUST_DOCS_RAG_NAME, path)?;
+    // The installed docs are HTML pages, which are split and embedded as markdown
+    rag.convert_html();
+    rag.refresh_document_paths(sources, true, config, create_abort_signal())
+        .await?;
+    if let Some(docs) = local_docs {
+        rag.tag_documents(RUST_TOOLCHAIN_METADATA, &docs.version);
+        rag.save()?;
+    }
+    Ok(())
+}
+
+fn rustc_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("rustc").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8(output.stdout).ok()?;
+    Some(output.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Load the Rust docs RAG if it has been built.
+pub fn load_rust_docs_rag(config: &GlobalConfig) -> Result<Option<Arc<Rag>>> {
+    let rag_path = config.read().rag_file(RUST_DOCS_RAG_NAME);
     if !rag_path.exists() {
-        return Err(anyhow!("Rust docs RAG not found. Run setup first."));
+        return Ok(None);
     }
-    
-    let rag = Rag::load(config, rag_name, &rag_path)?;
+    let rag = Rag::load(config, RUST_DOCS_RAG_NAME, &rag_path)?;
+    if LocalRustDocs::detect().is_some_and(|docs| docs.is_stale(&rag)) {
+        warn!("The Rust docs RAG was built for another toolchain, run --setup-rust-docs to rebuild it");
+    }
+    Ok(Some(Arc::new(rag)))
+}
+
+/// A citable search result from the Rust docs RAG.
+#[derive(Debug, Clone, Serialize)]
+pub struct RustDocsHit {
+    pub source: String,
+    pub url: Option<String>,
+    pub heading: Option<String>,
+    pub snippet: String,
+    #[serde(skip)]
+    pub content: String,
+    pub vector_score: Option<f32>,
+    pub keyword_score: Option<f32>,
+    pub rerank_score: Option<f64>,
+}
+
+const SNIPPET_LENGTH: usize = 300;
+
+pub async fn search_rust_docs(config: &GlobalConfig, query: &str) -> Result<Vec<RustDocsHit>> {
+    let rag = load_rust_docs_rag(config)?
+        .ok_or_else(|| anyhow!("Rust docs RAG not found. Run setup first."))?;
+    search_rust_docs_with(&rag, query).await
+}
+
+pub async fn search_rust_docs_with(rag: &Rag, query: &str) -> Result<Vec<RustDocsHit>> {
     let (reranker_model, top_k) = rag.get_config();
     let abort_signal = create_abort_signal();
-    
-    let (results, _) = rag.search(query, top_k, reranker_model.as_deref(), abort_signal).await?;
-    
-    Ok(results)
-}
\ No newline at end of file
+    let toolchain = rag.document_tag(RUST_TOOLCHAIN_METADATA);
+
+    let hits = rag
+        .search_hits(query, top_k, reranker_model.as_deref(), abort_signal)
+        .await?;
+
+    let output = hits
+        .into_iter()
+        .map(|hit| RustDocsHit {
+            url: rust_docs_url(&hit.source, toolchain),
+            snippet: create_snippet(&hit.content),
+            source: hit.source,
+            heading: hit.heading,
+            content: hit.content,
+            vector_score: hit.vector_score,
+            keyword_score: hit.keyword_score,
+            rerank_score: hit.rerank_score,
+        })
+        .collect();
+    Ok(output)
+}
+
+/// Render hits as numbered context so the LLM can cite them as `[n]`.
+pub fn rust_docs_context(hits: &[RustDocsHit]) -> String {
+    hits.iter()
+        .enumerate()
+        .map(|(i, hit)| {
+            let title = hit.heading.as_deref().unwrap_or("Untitled");
+            let source = hit.url.as_deref().unwrap_or(&hit.source);
+            format!("[{}] {title} ({source})\n{}", i + 1, hit.content.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Map a crawled URL or a locally installed doc page to its doc.rust-lang.org address.
+fn rust_docs_url(source: &str, toolchain: Option<&str>) -> Option<String> {
+    if is_url(source) {
+        return Some(source.to_string());
+    }
+    let source = source.replace('\\', "/");
+    let (_, relative_path) = source.split_once("share/doc/rust/html/")?;
+    let channel = match toolchain.and_then(|v| v.split_whitespace().nth(1)) {
+        Some(v) if v.contains("nightly") => "nightly",
+        Some(v) if v.contains("beta") => "beta",
+        Some(v) => v,
+        None => "stable",
+    };
+    Some(format!(
+        "https://doc.rust-lang.org/{channel}/{relative_path}"
+    ))
+}
+
+fn create_snippet(content: &str) -> String {
+    let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    match content.char_indices().nth(SNIPPET_LENGTH) {
+        Some((index, _)) => format!("{}...", &content[..index]),
+        None => content,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    #[test]
+    fn test_local_rust_docs_paths() {
+        let html_dir = temp_path("rust-docs");
+        assert!(LocalRustDocs::from_dir(html_dir.clone(), "rustc 1.0.0".into()).is_none());
+
+        std::fs::create_dir_all(html_dir.join("book")).unwrap();
+        std::fs::create_dir_all(html_dir.join("std")).unwrap();
+        let docs = LocalRustDocs::from_dir(html_dir.clone(), "rustc 1.0.0".into()).unwrap();
+        assert_eq!(
+            docs.document_paths(),
+            vec![
+                format!("{}/**/*.html", html_dir.join("book").display()),
+                format!("{}/**/*.html", html_dir.join("std").display()),
+            ]
+        );
+        std::fs::remove_dir_all(&html_dir).unwrap();
+    }
+
+    #[test]
+    fn test_rust_docs_url() {
+        let toolchain = Some("rustc 1.95.0 (59807616e 2026-04-14)");
+        assert_eq!(
+            rust_docs_url(
+                "/root/.rustup/toolchains/stable/share/doc/rust/html/std/vec/struct.Vec.html",
+                toolchain
+            )
+            .as_deref(),
+            Some("https://doc.rust-lang.org/1.95.0/std/vec/struct.Vec.html")
+        );
+        assert_eq!(
+            rust_docs_url(
+                "/toolchains/nightly/share/doc/rust/html/book/ch01-00.html",
+                Some("rustc 1.97.0-nightly (abc 2026-05-01)")
+            )
+            .as_deref(),
+            Some("https://doc.rust-lang.org/nightly/book/ch01-00.html")
+        );
+        assert_eq!(
+            rust_docs_url("https://doc.rust-lang.org/book/", None).as_deref(),
+            Some("https://doc.rust-lang.org/book/")
+        );
+        assert_eq!(rust_docs_url("/tmp/notes.md", toolchain), None);
+    }
+
+    #[test]
+    fn test_create_snippet() {
+        assert_eq!(create_snippet("  a\n\n b  "), "a b");
+        let snippet = create_snippet(&"é".repeat(SNIPPET_LENGTH + 10));
+        assert_eq!(snippet.chars().count(), SNIPPET_LENGTH + 3);
+        assert!(snippet.ends_with("..."));
+    }
+}
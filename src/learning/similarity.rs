@@ -0,0 +1,504 @@
+use crate::{
+    client::{init_client, EmbeddingsData, Model, ModelType},
+    config::GlobalConfig,
+    learning::*,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+/// Number of consecutive tokens hashed into one fingerprint. Shorter matches are ignored.
+const KGRAM_SIZE: usize = 5;
+/// Winnowing window, a match of `KGRAM_SIZE + WINNOW_WINDOW - 1` tokens is always detected.
+const WINNOW_WINDOW: usize = 4;
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimilarityMethod {
+    /// Winnowing over tokens with identifiers and literals normalized, so renaming
+    /// variables or reformatting doesn't hide a copy
+    #[default]
+    Fingerprint,
+    /// Cosine similarity of the embeddings from `rag_embedding_model`
+    Embedding,
+}
+
+impl SimilarityMethod {
+    pub fn default_min_similarity(&self) -> f64 {
+        match self {
+            Self::Fingerprint => 0.5,
+            Self::Embedding => 0.9,
+        }
+    }
+}
+
+impl FromStr for SimilarityMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fingerprint" => Ok(Self::Fingerprint),
+            "embedding" => Ok(Self::Embedding),
+            _ => bail!("Invalid method '{s}', expected 'fingerprint' or 'embedding'"),
+        }
+    }
+}
+
+/// The code a learner submitted for an exercise.
+#[derive(Debug, Clone)]
+pub struct CodeSubmission {
+    pub learner: String,
+    pub cohort: Option<String>,
+    pub at: i64,
+    pub code: String,
+}
+
+impl CodeSubmission {
+    /// The latest successful run of each learner for the lesson, in learner order.
+    pub fn collect(events: &[ActivityEvent], lesson_id: &str) -> Vec<Self> {
+        let mut latest: HashMap<&str, Self> = HashMap::new();
+        for event in events {
+            let ActivityKind::CodeRun {
+                lesson_id: Some(id),
+                code: Some(code),
+                ..
+            } = &event.kind
+            else {
+                continue;
+            };
+            if id != lesson_id
+                || latest
+                    .get(event.learner.as_str())
+                    .is_some_and(|v| v.at > event.at)
+            {
+                continue;
+            }
+            latest.insert(
+                &event.learner,
+                Self {
+                    learner: event.learner.clone(),
+                    cohort: event.cohort.clone(),
+                    at: event.at,
+                    code: code.clone(),
+                },
+            );
+        }
+        let mut submissions: Vec<Self> = latest.into_values().collect();
+        submissions.sort_by(|a, b| a.learner.cmp(&b.learner));
+        submissions
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarityReport {
+    pub lesson_id: String,
+    pub method: SimilarityMethod,
+    pub min_similarity: f64,
+    pub submissions: usize,
+    pub pairs: Vec<SimilarPair>,
+}
+
+/// Two submissions that look alike, most similar first.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarPair {
+    pub similarity: f64,
+    pub left: SubmissionMatch,
+    pub right: SubmissionMatch,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubmissionMatch {
+    pub learner: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cohort: Option<String>,
+    pub at: i64,
+    /// Matching regions as 1-based, inclusive line ranges.
+    pub lines: Vec<(usize, usize)>,
+    /// HTML-escaped code with the matching regions wrapped in `<mark>`.
+    pub html: String,
+}
+
+/// Compare every pair of submissions. Code from `boilerplate`, such as the lesson's own
+/// examples learners start from, never counts as a match. With `embeddings`, one per
+/// submission, pairs are scored by cosine similarity instead of shared fingerprints.
+pub fn find_similar_submissions(
+    submissions: &[CodeSubmission],
+    boilerplate: &[&str],
+    embeddings: Option<&[Vec<f32>]>,
+    min_similarity: f64,
+) -> Vec<SimilarPair> {
+    let boilerplate: HashSet<u64> = boilerplate
+        .iter()
+        .flat_map(|code| kgram_hashes(&tokenize(code)))
+        .collect();
+    let fingerprinted: Vec<Fingerprinted> = submissions
+        .iter()
+        .map(|v| Fingerprinted::new(&v.code, &boilerplate))
+        .collect();
+
+    let mut pairs = vec![];
+    for i in 0..submissions.len() {
+        for j in (i + 1)..submissions.len() {
+            let (a, b) = (&fingerprinted[i], &fingerprinted[j]);
+            let shared: HashSet<u64> = a
+                .fingerprints
+                .intersection(&b.fingerprints)
+                .copied()
+                .collect();
+            let similarity = match embeddings {
+                Some(embeddings) => cosine_similarity(&embeddings[i], &embeddings[j]),
+                None => {
+                    let smaller = a.fingerprints.len().min(b.fingerprints.len());
+                    if smaller == 0 {
+                        continue;
+                    }
+                    shared.len() as f64 / smaller as f64
+                }
+            };
+            if similarity < min_similarity {
+                continue;
+            }
+            pairs.push(SimilarPair {
+                similarity,
+                left: a.matched(&submissions[i], &shared),
+                right: b.matched(&submissions[j], &shared),
+            });
+        }
+    }
+    pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    pairs
+}
+
+/// Embed each submission with the configured `rag_embedding_model`, in batches of the model's
+/// `max_batch_size` as RAG does.
+pub async fn embed_submissions(
+    config: &GlobalConfig,
+    submissions: &[CodeSubmission],
+) -> Result<Vec<Vec<f32>>> {
+    if submissions.is_empty() {
+        return Ok(vec![]);
+    }
+    let model = {
+        let config = config.read();
+        let model_id = config
+            .rag_embedding_model
+            .clone()
+            .ok_or_else(|| anyhow!("No embedding model configured, set 'rag_embedding_model'"))?;
+        Model::retrieve_model(&config, &model_id, ModelType::Embedding)?
+    };
+    let batch_size = model.max_batch_size().unwrap_or(1).max(1);
+    let client = init_client(config, Some(model))?;
+    let texts: Vec<_> = submissions.iter().map(|v| v.code.clone()).collect();
+    let mut embeddings = vec![];
+    for batch in texts.chunks(batch_size) {
+        let output = client
+            .embeddings(&EmbeddingsData::new(batch.to_vec(), false))
+            .await?;
+        embeddings.extend(output);
+    }
+    if embeddings.len() != submissions.len() {
+        bail!(
+            "The embedding model returned {} embeddings for {} submissions",
+            embeddings.len(),
+            submissions.len()
+        );
+    }
+    Ok(embeddings)
+}
+
+struct Token {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+struct Fingerprinted {
+    tokens: Vec<Token>,
+    /// Hash of every k-gram, by the index of its first token.
+    kgrams: Vec<u64>,
+    fingerprints: HashSet<u64>,
+}
+
+impl Fingerprinted {
+    fn new(code: &str, boilerplate: &HashSet<u64>) -> Self {
+        let tokens = tokenize(code);
+        let kgrams = kgram_hashes(&tokens);
+        let fingerprints = winnow(&kgrams)
+            .into_iter()
+            .filter(|v| !boilerplate.contains(v))
+            .collect();
+        Self {
+            tokens,
+            kgrams,
+            fingerprints,
+        }
+    }
+
+    /// Highlight every k-gram whose fingerprint both submissions share.
+    fn matched(&self, submission: &CodeSubmission, shared: &HashSet<u64>) -> SubmissionMatch {
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        for (i, hash) in self.kgrams.iter().enumerate() {
+            if !shared.contains(hash) {
+                continue;
+            }
+            let (start, end) = (self.tokens[i].start, self.tokens[i + KGRAM_SIZE - 1].end);
+            match ranges.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        let code = &submission.code;
+        let line_of = |offset: usize| code[..offset].matches('\n').count() + 1;
+        let lines = ranges
+            .iter()
+            .map(|(start, end)| (line_of(*start), line_of(*end)))
+            .collect();
+        let mut html = String::new();
+        let mut last = 0;
+        for (start, end) in &ranges {
+            html.push_str(&escape_html(&code[last..*start]));
+            html.push_str(&format!(
+                "<mark>{}</mark>",
+                escape_html(&code[*start..*end])
+            ));
+            last = *end;
+        }
+        html.push_str(&escape_html(&code[last..]));
+
+        SubmissionMatch {
+            learner: submission.learner.clone(),
+            cohort: submission.cohort.clone(),
+            at: submission.at,
+            lines,
+            html,
+        }
+    }
+}
+
+/// Split Rust code into tokens, dropping whitespace and comments. Identifiers become `v`,
+/// string and char literals `s` and numbers `n`, while keywords and punctuation are kept.
+fn tokenize(code: &str) -> Vec<Token> {
+    let chars: Vec<(usize, char)> = code.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(code.len(), |v| v.0);
+    let at = |i: usize| chars.get(i).map(|v| v.1);
+    let mut tokens = vec![];
+    let mut i = 0;
+    while let Some(c) = at(i) {
+        let start = i;
+        let text = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c == '/' && at(i + 1) == Some('/') {
+            while at(i).is_some_and(|v| v != '\n') {
+                i += 1;
+            }
+            continue;
+        } else if c == '/' && at(i + 1) == Some('*') {
+            i += 2;
+            while at(i).is_some() && !(at(i) == Some('*') && at(i + 1) == Some('/')) {
+                i += 1;
+            }
+            i += 2;
+            continue;
+        } else if c == 'r' && matches!(at(i + 1), Some('"' | '#')) {
+            // Raw string, r"..." or r#"..."#
+            let mut hashes = 0;
+            i += 1;
+            while at(i) == Some('#') {
+                hashes += 1;
+                i += 1;
+            }
+            i += 1;
+            while at(i).is_some() {
+                if at(i) == Some('"') && (1..=hashes).all(|n| at(i + n) == Some('#')) {
+                    i += hashes;
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+            "s"
+        } else if c == '"' {
+            i += 1;
+            while let Some(v) = at(i) {
+                i += if v == '\\' { 2 } else { 1 };
+                if v == '"' {
+                    break;
+                }
+            }
+            "s"
+        } else if c == '\'' && (at(i + 1) == Some('\\') || at(i + 2) == Some('\'')) {
+            i += 1;
+            while let Some(v) = at(i) {
+                i += if v == '\\' { 2 } else { 1 };
+                if v == '\'' {
+                    break;
+                }
+            }
+            "s"
+        } else if c.is_ascii_digit() {
+            while at(i).is_some_and(|v| {
+                v.is_alphanumeric()
+                    || v == '_'
+                    || (v == '.' && at(i + 1).is_some_and(|v| v.is_ascii_digit()))
+            }) {
+                i += 1;
+            }
+            "n"
+        } else if c.is_alphabetic() || c == '_' {
+            while at(i).is_some_and(|v| v.is_alphanumeric() || v == '_') {
+                i += 1;
+            }
+            let word = &code[offset(start)..offset(i)];
+            match RUST_KEYWORDS.contains(&word) {
+                true => word,
+                false => "v",
+            }
+        } else {
+            i += 1;
+            &code[offset(start)..offset(i)]
+        };
+        tokens.push(Token {
+            text: text.to_string(),
+            start: offset(start),
+            end: offset(i),
+        });
+    }
+    tokens
+}
+
+fn kgram_hashes(tokens: &[Token]) -> Vec<u64> {
+    if tokens.len() < KGRAM_SIZE {
+        return vec![];
+    }
+    tokens
+        .windows(KGRAM_SIZE)
+        .map(|window| {
+            // FNV-1a, stable across runs unlike the std hasher
+            let mut hash: u64 = 0xcbf29ce484222325;
+            for token in window {
+                for byte in token.text.bytes().chain([0]) {
+                    hash ^= byte as u64;
+                    hash = hash.wrapping_mul(0x100000001b3);
+                }
+            }
+            hash
+        })
+        .collect()
+}
+
+/// Keep the smallest hash of every window of k-grams, the fingerprints of the code.
+fn winnow(hashes: &[u64]) -> Vec<u64> {
+    let window = WINNOW_WINDOW.min(hashes.len());
+    if window == 0 {
+        return vec![];
+    }
+    let mut output = vec![];
+    let mut last_pick = None;
+    for start in 0..=(hashes.len() - window) {
+        // Prefer the rightmost minimum, so a run of equal hashes yields one fingerprint
+        let pick = (start..start + window)
+            .rev()
+            .min_by_key(|i| hashes[*i])
+            .unwrap_or(start);
+        if last_pick != Some(pick) {
+            output.push(hashes[pick]);
+            last_pick = Some(pick);
+        }
+    }
+    output
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submission(learner: &str, code: &str) -> CodeSubmission {
+        CodeSubmission {
+            learner: learner.to_string(),
+            cohort: None,
+            at: 0,
+            code: code.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_similar_submissions() {
+        let starter = "fn main() {\n    println!(\"Hello, world!\");\n}\n";
+        let alice = r#"fn main() {
+    // Sum the even squares
+    let numbers = vec![1, 2, 3, 4, 5, 6];
+    let mut total = 0;
+    for n in numbers.iter() {
+        if n % 2 == 0 {
+            total += n * n;
+        }
+    }
+    println!("Total: {}", total);
+}
+"#;
+        // The same solution with renamed variables, other literals and no comments
+        let bob = r#"fn main() {
+    let values = vec![7, 8, 9, 10, 11, 12];
+    let mut sum = 0;
+    for x in values.iter() {
+        if x % 2 == 0 {
+            sum += x * x;
+        }
+    }
+    println!("Sum = {}", sum);
+}
+"#;
+        let carol = r#"fn main() {
+    let total: i32 = (1..=6).filter(|n| n % 2 == 0).map(|n| n * n).sum();
+    println!("{total}");
+}
+"#;
+        let submissions = vec![
+            submission("alice", alice),
+            submission("bob", bob),
+            submission("carol", carol),
+            submission("dave", starter),
+            submission("erin", starter),
+        ];
+        let pairs = find_similar_submissions(&submissions, &[starter], None, 0.5);
+        assert_eq!(pairs.len(), 1);
+        let pair = &pairs[0];
+        assert_eq!(
+            (pair.left.learner.as_str(), pair.right.learner.as_str()),
+            ("alice", "bob")
+        );
+        assert!(pair.similarity > 0.9);
+        assert_eq!(
+            (pair.left.lines.clone(), pair.right.lines.clone()),
+            (vec![(1, 11)], vec![(1, 10)])
+        );
+        assert!(pair
+            .right
+            .html
+            .starts_with("fn main(<mark>) {\n    let values"));
+    }
+}
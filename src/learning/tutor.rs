@@ -0,0 +1,151 @@
+use crate::{
+    config::{GlobalConfig, TUTOR_ROLE},
+    learning::*,
+};
+
+use anyhow::Result;
+
+pub const DEFAULT_LEARNER: &str = "default";
+
+/// The learner's most recent attempt, shared with the tutor on every turn.
+#[derive(Debug, Default, Clone)]
+pub struct LearnerWork<'a> {
+    pub code: Option<&'a str>,
+    pub errors: Option<&'a str>,
+}
+
+/// Each learner gets one tutor session per lesson, stored under `sessions/tutor/`.
+pub fn tutor_session_name(learner: &str, lesson_id: &str) -> String {
+    format!(
+        "tutor/{}-{}",
+        sanitize_name(learner),
+        sanitize_name(lesson_id)
+    )
+}
+
+pub fn build_tutor_prompt(base: &str, lesson: &Lesson, work: &LearnerWork) -> String {
+    let mut prompt = format!(
+        "{}\n\n<lesson>\n# {}\n\n{}\n\n{}\n</lesson>",
+        base.trim(),
+        lesson.title,
+        lesson.description,
+        lesson.content.trim()
+    );
+    if !lesson.code_examples.is_empty() {
+        prompt.push_str("\n\n<code_examples>");
+        for example in &lesson.code_examples {
+            prompt.push_str(&format!(
+                "\n## {}\n```rust\n{}\n```\n{}\n",
+                example.title,
+                example.code.trim(),
+                example.explanation
+            ));
+        }
+        prompt.push_str("</code_examples>");
+    }
+    if let Some(code) = work.code.filter(|v| !v.trim().is_empty()) {
+        prompt.push_str(&format!(
+            "\n\n<learner_code>\n```rust\n{}\n```\n</learner_code>",
+            code.trim()
+        ));
+    }
+    if let Some(errors) = work.errors.filter(|v| !v.trim().is_empty()) {
+        prompt.push_str(&format!(
+            "\n\n<learner_errors>\n{}\n</learner_errors>",
+            errors.trim()
+        ));
+    }
+    prompt
+}
+
+/// Enter the learner's tutor session for the lesson, refreshing its system prompt with
+/// the lesson material and the learner's latest code and errors.
+pub fn use_tutor_session(
+    config: &GlobalConfig,
+    learner: &str,
+    lesson: &Lesson,
+    work: &LearnerWork,
+) -> Result<String> {
+    let mut role = config.read().retrieve_role(TUTOR_ROLE)?;
+    let prompt = build_tutor_prompt(role.prompt(), lesson, work);
+    role.set_prompt(&prompt);
+
+    let session_name = tutor_session_name(learner, &lesson.id);
+    let mut config = config.write();
+    config.use_session(Some(&session_name))?;
+    match config.session.as_mut() {
+        Some(session) if !session.is_empty() => session.refresh_role_prompt(&prompt),
+        _ => config.use_role_obj(role)?,
+    }
+    Ok(session_name)
+}
+
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match name.trim_matches('-') {
+        "" => DEFAULT_LEARNER.to_string(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tutor_session_name() {
+        assert_eq!(
+            tutor_session_name("alice", "01-intro"),
+            "tutor/alice-01-intro"
+        );
+        assert_eq!(
+            tutor_session_name("../bob smith", "02/vars"),
+            "tutor/bob-smith-02-vars"
+        );
+        assert_eq!(tutor_session_name("", "01"), "tutor/default-01");
+    }
+
+    #[test]
+    fn test_build_tutor_prompt() {
+        let lesson = Lesson {
+            id: "01-intro".into(),
+            title: "Intro".into(),
+            description: "Getting started".into(),
+            difficulty: Difficulty::Beginner,
+            content: "Rust is a systems language.".into(),
+            code_examples: vec![CodeExample {
+                title: "Hello".into(),
+                code: "fn main() {}".into(),
+                explanation: "An empty program".into(),
+                runnable: true,
+                expected_output: None,
+                emit: None,
+                opt_level: None,
+            }],
+            next_lesson: None,
+            prev_lesson: None,
+            topics: vec![],
+        };
+        let work = LearnerWork {
+            code: Some("fn main() { let x: i32 = \"a\"; }"),
+            errors: Some("error[E0308]: mismatched types"),
+        };
+        let prompt = build_tutor_prompt("You are a tutor.", &lesson, &work);
+        assert!(prompt.starts_with("You are a tutor.\n\n<lesson>\n# Intro"));
+        assert!(prompt.contains("```rust\nfn main() {}\n```"));
+        assert!(prompt.contains("<learner_code>"));
+        assert!(prompt.contains("<learner_errors>\nerror[E0308]"));
+
+        let prompt = build_tutor_prompt("You are a tutor.", &lesson, &LearnerWork::default());
+        assert!(!prompt.contains("<learner_code>"));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::learning::*;
+
+use anyhow::{Context, Result};
+use notify::{event::ModifyKind, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc::unbounded_channel};
+
+/// Editors often write a file in several steps, so wait for them to settle before re-parsing.
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentKind {
+    Lesson,
+    Quiz,
+}
+
+/// A change to the course content, pushed to browsers over `/api/events`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ContentChange {
+    Updated {
+        kind: ContentKind,
+        id: String,
+    },
+    Removed {
+        kind: ContentKind,
+        id: String,
+    },
+    Failed {
+        kind: ContentKind,
+        path: String,
+        error: String,
+    },
+}
+
+impl ContentChange {
+    pub fn updated(kind: ContentKind, id: &str) -> Self {
+        Self::Updated {
+            kind,
+            id: id.to_string(),
+        }
+    }
+
+    pub fn removed(kind: ContentKind, id: &str) -> Self {
+        Self::Removed {
+            kind,
+            id: id.to_string(),
+        }
+    }
+}
+
+/// Watches the lessons and quizzes directories and swaps changed files into the managers.
+#[derive(Debug)]
+pub struct ContentWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl ContentWatcher {
+    pub fn spawn(
+        lesson_manager: Arc<RwLock<LessonManager>>,
+        quiz_manager: Arc<RwLock<QuizManager>>,
+        changes: broadcast::Sender<ContentChange>,
+    ) -> Result<Self> {
+        let dirs: Vec<(ContentKind, PathBuf)> = [
+            (ContentKind::Lesson, lesson_manager.read().get_lessons_dir()),
+            (ContentKind::Quiz, QuizManager::quizzes_dir()),
+        ]
+        .into_iter()
+        .filter(|(_, dir)| dir.is_dir())
+        .collect();
+
+        let (tx, mut rx) = unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else {
+                return;
+            };
+            // Reading a file while re-parsing it must not trigger another reload
+            let is_change = match event.kind {
+                EventKind::Modify(ModifyKind::Metadata(_)) => false,
+                kind => kind.is_create() || kind.is_modify() || kind.is_remove(),
+            };
+            if is_change {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+        })
+        .context("Failed to create the content watcher")?;
+        let mut watched_dirs = vec![];
+        for (kind, dir) in dirs {
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("Failed to watch '{}'", dir.display()))?;
+            watched_dirs.push((kind, dir.canonicalize()?, dir));
+        }
+
+        tokio::spawn(async move {
+            while let Some(path) = rx.recv().await {
+                let mut paths = BTreeSet::from([path]);
+                tokio::time::sleep(DEBOUNCE_DELAY).await;
+                while let Ok(path) = rx.try_recv() {
+                    paths.insert(path);
+                }
+                for path in paths {
+                    let Some((kind, path)) = resolve_content_path(&watched_dirs, &path) else {
+                        continue;
+                    };
+                    let ret = match kind {
+                        ContentKind::Lesson => lesson_manager.write().reload_lesson_file(&path),
+                        ContentKind::Quiz => quiz_manager.write().reload_quiz_file(&path),
+                    };
+                    let change = match ret {
+                        Ok(Some(change)) => change,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            warn!("{err:#}");
+                            ContentChange::Failed {
+                                kind,
+                                path: path.display().to_string(),
+                                error: format!("{err:#}"),
+                            }
+                        }
+                    };
+                    info!("Content changed: {change:?}");
+                    let _ = changes.send(change);
+                }
+            }
+        });
+
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// Map a watcher event path to the path the managers know the file by.
+fn resolve_content_path(
+    watched_dirs: &[(ContentKind, PathBuf, PathBuf)],
+    path: &Path,
+) -> Option<(ContentKind, PathBuf)> {
+    if path.extension().and_then(|v| v.to_str()) != Some("yaml") {
+        return None;
+    }
+    let file_name = path.file_name()?;
+    let parent = path.parent()?.canonicalize().ok()?;
+    watched_dirs
+        .iter()
+        .find(|(_, canonical_dir, _)| canonical_dir == &parent)
+        .map(|(kind, _, dir)| (*kind, dir.join(file_name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::learning::fixtures::*;
+
+    const QUIZ: &str = r#"id: quiz-hot-reload
+title: Borrowing
+description: Shared and mutable references
+lesson_id: null
+questions: []
+"#;
+
+    #[test]
+    fn test_reload_quiz_file() {
+        let dir = temp_path("watcher");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("borrowing.yaml");
+        let mut manager = QuizManager::new();
+
+        std::fs::write(&path, QUIZ).unwrap();
+        assert_eq!(
+            manager.reload_quiz_file(&path).unwrap(),
+            Some(ContentChange::updated(ContentKind::Quiz, "quiz-hot-reload"))
+        );
+        assert_eq!(manager.reload_quiz_file(&path).unwrap(), None);
+
+        // A broken edit keeps the last good version
+        std::fs::write(&path, "id: [oops").unwrap();
+        assert!(manager.reload_quiz_file(&path).is_err());
+        assert_eq!(
+            manager.get_quiz("quiz-hot-reload").unwrap().title,
+            "Borrowing"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            manager.reload_quiz_file(&path).unwrap(),
+            Some(ContentChange::removed(ContentKind::Quiz, "quiz-hot-reload"))
+        );
+        assert!(manager.get_quiz("quiz-hot-reload").is_none());
+
+        // Deleting a file that replaced a built-in quiz brings the built-in one back
+        let path = dir.join("intro.yaml");
+        std::fs::write(&path, QUIZ.replace("quiz-hot-reload", "quiz-01-intro")).unwrap();
+        manager.reload_quiz_file(&path).unwrap();
+        assert_eq!(
+            manager.get_quiz("quiz-01-intro").unwrap().title,
+            "Borrowing"
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            manager.reload_quiz_file(&path).unwrap(),
+            Some(ContentChange::updated(ContentKind::Quiz, "quiz-01-intro"))
+        );
+        assert_eq!(
+            manager.get_quiz("quiz-01-intro").unwrap().title,
+            "Introduction to Rust Quiz"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -19,17 +19,22 @@ use crate::client::{
 };
 use crate::config::{
     ensure_parent_exists, list_agents, load_env_file, macro_execute, Config, GlobalConfig, Input,
-    WorkingMode, CODE_ROLE, EXPLAIN_SHELL_ROLE, SHELL_ROLE, TEMP_SESSION_NAME,
+    UsageGroupBy, UsageLedger, UsageReport, WorkingMode, CODE_ROLE, EXPLAIN_SHELL_ROLE, SHELL_ROLE,
+    TEMP_SESSION_NAME,
+};
+use crate::learning::{
+    check_lessons, draft_translations, load_signing_key, setup_rust_docs_rag,
+    test_learning_components, AccountRole, AccountStore, ActivityLog, CompletionRecord,
+    LessonCheckFormat, LessonManager, ProgressBundle,
 };
-use crate::learning::{test_learning_components, setup_rust_docs_rag};
 use crate::render::render_error;
 use crate::repl::Repl;
 use crate::utils::*;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
 use inquire::validator::Validation;
-use inquire::Text;
+use inquire::{Password, Text};
 use is_terminal::IsTerminal;
 use parking_lot::RwLock;
 use simplelog::{format_description, ConfigBuilder, LevelFilter, SimpleLogger, WriteLogger};
@@ -42,9 +47,8 @@ async fn main() -> Result<()> {
     let text = cli.text()?;
     let working_mode = if cli.serve.is_some() {
         WorkingMode::Serve
-    } else if cli.learn {
-        WorkingMode::Repl // Treat learning mode as interactive
-    } else if text.is_none() && cli.file.is_empty() {
+    } else if cli.learn || (text.is_none() && cli.file.is_empty()) {
+        // Learning mode is treated as interactive
         WorkingMode::Repl
     } else {
         WorkingMode::Cmd
@@ -56,7 +60,9 @@ async fn main() -> Result<()> {
         || cli.list_agents
         || cli.list_rags
         || cli.list_macros
-        || cli.list_sessions;
+        || cli.list_sessions
+        || cli.list_accounts
+        || cli.usage.is_some();
     setup_logger(working_mode.is_serve())?;
     let config = Arc::new(RwLock::new(Config::init(working_mode, info_flag).await?));
     if let Err(err) = run(config, cli, text).await {
@@ -101,6 +107,21 @@ async fn run(config: GlobalConfig, cli: Cli, text: Option<String>) -> Result<()>
         return Ok(());
     }
 
+    if let Some(group_by) = &cli.usage {
+        let group_by: UsageGroupBy = group_by.as_deref().unwrap_or("day").parse()?;
+        let report = UsageReport::build(&UsageLedger::init().load()?, group_by);
+        if cli.usage_json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("{}", report.render());
+        }
+        return Ok(());
+    }
+
+    if run_account_command(&cli)? {
+        return Ok(());
+    }
+
     if cli.dry_run {
         config.write().dry_run = true;
     }
@@ -166,7 +187,7 @@ async fn run(config: GlobalConfig, cli: Cli, text: Option<String>) -> Result<()>
     if let Some(addr) = cli.serve {
         return serve::run(config, addr).await;
     }
-    
+
     if cli.test_learning {
         if let Err(err) = test_learning_components() {
             eprintln!("❌ Learning components test failed: {}", err);
@@ -174,7 +195,83 @@ async fn run(config: GlobalConfig, cli: Cli, text: Option<String>) -> Result<()>
         }
         return Ok(());
     }
-    
+
+    if let Some(format) = &cli.check_lessons {
+        let format = LessonCheckFormat::parse(format.as_deref())?;
+        let report = check_lessons(&config).await?;
+        println!("{}", report.render(format)?);
+        if !report.is_success() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(locale) = &cli.translate_lessons {
+        let drafted = draft_translations(&config, locale).await?;
+        if drafted.is_empty() {
+            println!("Nothing to translate, every lesson and quiz has a '{locale}' version.");
+        }
+        for path in drafted {
+            println!("✓ Drafted '{}'", path.display());
+        }
+        return Ok(());
+    }
+
+    if let Some(learner) = &cli.export_progress {
+        let lesson_manager = LessonManager::new(config.clone())?;
+        let lesson_ids: Vec<String> = lesson_manager
+            .list_lessons()
+            .into_iter()
+            .map(|v| v.id.clone())
+            .collect();
+        let sessions_dir = config.read().sessions_dir();
+        let bundle =
+            ProgressBundle::export(learner, &ActivityLog::init(), &sessions_dir, &lesson_ids)?;
+        println!("{}", serde_json::to_string_pretty(&bundle)?);
+        return Ok(());
+    }
+
+    if let Some(file) = &cli.import_progress {
+        let data = std::fs::read(file).with_context(|| format!("Failed to read '{file}'"))?;
+        let bundle = ProgressBundle::parse(&data)?;
+        let learner = cli
+            .progress_learner
+            .clone()
+            .unwrap_or_else(|| bundle.learner.clone());
+        let cohort = AccountStore::init()?
+            .identity(&learner)
+            .and_then(|v| v.cohort);
+        let sessions_dir = config.read().sessions_dir();
+        let summary = bundle.import(
+            &learner,
+            cohort.as_deref(),
+            &ActivityLog::init(),
+            &sessions_dir,
+        )?;
+        println!(
+            "✓ Imported progress for '{learner}': {} new events ({} already present), {} tutor sessions updated ({} local copies were newer).",
+            summary.events_added,
+            summary.events_skipped,
+            summary.sessions_imported,
+            summary.sessions_kept
+        );
+        return Ok(());
+    }
+
+    if let Some(file) = &cli.verify_certificate {
+        let data = std::fs::read(file).with_context(|| format!("Failed to read '{file}'"))?;
+        let record: CompletionRecord =
+            serde_json::from_slice(&data).context("Invalid completion record")?;
+        if !record.verify(&load_signing_key(false)?) {
+            bail!("Invalid signature, the record was not issued with this key or was altered");
+        }
+        println!(
+            "✓ Valid: '{}' earned '{}'.",
+            record.learner, record.badge_title
+        );
+        return Ok(());
+    }
+
     if cli.setup_rust_docs {
         println!("🦀 Setting up Rust documentation RAG...");
         if let Err(err) = setup_rust_docs_rag(&config).await {
@@ -183,7 +280,7 @@ async fn run(config: GlobalConfig, cli: Cli, text: Option<String>) -> Result<()>
         }
         return Ok(());
     }
-    
+
     if cli.learn {
         println!("🦀 Starting Rust Learning App...");
         println!("📚 Open your browser to http://localhost:8000/learn");
@@ -390,21 +487,92 @@ async fn create_input(
     Ok(input)
 }
 
+/// Handle the admin commands that manage server accounts, returns whether one ran.
+fn run_account_command(cli: &Cli) -> Result<bool> {
+    if cli.list_accounts {
+        let store = AccountStore::init()?;
+        for (username, account) in store.accounts() {
+            match &account.cohort {
+                Some(cohort) => println!("{username}\t{}\t{cohort}", account.role.as_str()),
+                None => println!("{username}\t{}", account.role.as_str()),
+            }
+            for token in store.tokens().iter().filter(|v| &v.username == username) {
+                println!("  token {}", token.id);
+            }
+        }
+        return Ok(true);
+    }
+    if let Some(username) = &cli.add_account {
+        let role = match &cli.account_role {
+            Some(role) => role.parse()?,
+            None => AccountRole::Learner,
+        };
+        let password = match env::var(get_env_name("account_password")) {
+            Ok(password) => password,
+            Err(_) if *IS_STDOUT_TERMINAL => Password::new("Password:").prompt()?,
+            Err(_) => bail!(
+                "Cannot prompt for a password, set {} instead",
+                get_env_name("account_password")
+            ),
+        };
+        let mut store = AccountStore::init()?;
+        store.upsert_account(
+            username,
+            role,
+            Some(&password),
+            cli.account_cohort.as_deref(),
+        )?;
+        store.save()?;
+        println!("✓ Saved account '{username}'.");
+        return Ok(true);
+    }
+    if let Some(username) = &cli.remove_account {
+        let mut store = AccountStore::init()?;
+        store.remove_account(username)?;
+        store.save()?;
+        println!("✓ Removed account '{username}'.");
+        return Ok(true);
+    }
+    if let Some(username) = &cli.issue_token {
+        let mut store = AccountStore::init()?;
+        let (id, token) = store.issue_token(username)?;
+        store.save()?;
+        println!("✓ Issued token '{id}' for '{username}', it will not be shown again:");
+        println!("{token}");
+        return Ok(true);
+    }
+    if let Some(id) = &cli.revoke_token {
+        let mut store = AccountStore::init()?;
+        store.revoke_token(id)?;
+        store.save()?;
+        println!("✓ Revoked token '{id}'.");
+        return Ok(true);
+    }
+    Ok(false)
+}
+
 fn setup_logger(is_serve: bool) -> Result<()> {
     let (log_level, log_path) = Config::log_config(is_serve)?;
     if log_level == LevelFilter::Off {
         return Ok(());
     }
     let crate_name = env!("CARGO_CRATE_NAME");
-    let log_filter = match std::env::var(get_env_name("log_filter")) {
-        Ok(v) => v,
+    let log_filters = match std::env::var(get_env_name("log_filter")) {
+        Ok(v) => vec![v],
+        // The server also logs which member of a model group answered
         Err(_) => match is_serve {
-            true => format!("{crate_name}::serve"),
-            false => crate_name.into(),
+            true => vec![
+                format!("{crate_name}::serve"),
+                format!("{crate_name}::client::model_group"),
+            ],
+            false => vec![crate_name.into()],
         },
     };
-    let config = ConfigBuilder::new()
-        .add_filter_allow(log_filter)
+    let mut config = ConfigBuilder::new();
+    for log_filter in log_filters {
+        config.add_filter_allow(log_filter);
+    }
+    let config = config
         .set_time_format_custom(format_description!(
             "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z"
         ))
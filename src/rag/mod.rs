@@ -67,20 +67,7 @@ impl Rag {
             bail!("Failed to init rag in non-interactive mode");
         }
         println!("⚙ Initializing RAG...");
-        let (embedding_model, chunk_size, chunk_overlap) = Self::create_config(config)?;
-        let (reranker_model, top_k) = {
-            let config = config.read();
-            (config.rag_reranker_model.clone(), config.rag_top_k)
-        };
-        let data = RagData::new(
-            embedding_model.id(),
-            chunk_size,
-            chunk_overlap,
-            reranker_model,
-            top_k,
-            embedding_model.max_batch_size(),
-        );
-        let mut rag = Self::create(config, name, save_path, data)?;
+        let mut rag = Self::empty(config, name, save_path)?;
         let mut paths = doc_paths.to_vec();
         if paths.is_empty() {
             paths = add_documents()?;
@@ -99,6 +86,24 @@ impl Rag {
         Ok(rag)
     }
 
+    /// A rag without documents, set up from the embedding and chunking config.
+    pub fn empty(config: &GlobalConfig, name: &str, save_path: &Path) -> Result<Self> {
+        let (embedding_model, chunk_size, chunk_overlap) = Self::create_config(config)?;
+        let (reranker_model, top_k) = {
+            let config = config.read();
+            (config.rag_reranker_model.clone(), config.rag_top_k)
+        };
+        let data = RagData::new(
+            embedding_model.id(),
+            chunk_size,
+            chunk_overlap,
+            reranker_model,
+            top_k,
+            embedding_model.max_batch_size(),
+        );
+        Self::create(config, name, save_path, data)
+    }
+
     pub fn load(config: &GlobalConfig, name: &str, path: &Path) -> Result<Self> {
         let err = || format!("Failed to load rag '{name}' at '{}'", path.display());
         let content = fs::read_to_string(path).with_context(err)?;
@@ -128,6 +133,30 @@ impl Rag {
         &self.data.document_paths
     }
 
+    /// Convert local HTML files to markdown when loading them, for a rag built from HTML pages
+    /// such as the Rust docs.
+    pub fn convert_html(&mut self) {
+        self.data.html_to_md = true;
+    }
+
+    /// Attach a metadata tag to every stored document.
+    pub fn tag_documents(&mut self, key: &str, value: &str) {
+        for file in self.data.files.values_mut() {
+            for document in file.documents.iter_mut() {
+                document.metadata.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+
+    pub fn document_tag(&self, key: &str) -> Option<&str> {
+        self.data
+            .files
+            .values()
+            .flat_map(|v| v.documents.iter())
+            .find_map(|v| v.metadata.get(key))
+            .map(|v| v.as_str())
+    }
+
     pub async fn refresh_document_paths(
         &mut self,
         document_paths: &[String],
@@ -299,15 +328,28 @@ impl Rag {
         rerank_model: Option<&str>,
         abort_signal: AbortSignal,
     ) -> Result<(String, Vec<DocumentId>)> {
-        let ret = abortable_run_with_spinner(
+        let hits = self
+            .search_hits(text, top_k, rerank_model, abort_signal)
+            .await?;
+        let (ids, documents): (Vec<_>, Vec<_>) =
+            hits.into_iter().map(|v| (v.id, v.content)).unzip();
+        let embeddings = documents.join("\n\n");
+        Ok((embeddings, ids))
+    }
+
+    pub async fn search_hits(
+        &self,
+        text: &str,
+        top_k: usize,
+        rerank_model: Option<&str>,
+        abort_signal: AbortSignal,
+    ) -> Result<Vec<RagSearchHit>> {
+        abortable_run_with_spinner(
             self.hybird_search(text, top_k, rerank_model),
             "Searching",
             abort_signal,
         )
-        .await;
-        let (ids, documents): (Vec<_>, Vec<_>) = ret?.into_iter().unzip();
-        let embeddings = documents.join("\n\n");
-        Ok((embeddings, ids))
+        .await
     }
 
     pub async fn sync_documents(
@@ -408,6 +450,7 @@ impl Rag {
             index += 1;
             println!("Load {local_path} [{index}/{total}]");
             match load_file(&loaders, &local_path).await {
+                Ok(v) if self.data.html_to_md => loaded_documents.push(v.html_to_md()),
                 Ok(v) => loaded_documents.push(v),
                 Err(err) => handle_error(err, &mut has_error),
             }
@@ -511,7 +554,7 @@ impl Rag {
         query: &str,
         top_k: usize,
         rerank_model: Option<&str>,
-    ) -> Result<Vec<(DocumentId, String)>> {
+    ) -> Result<Vec<RagSearchHit>> {
         let (vector_search_results, keyword_search_results) = tokio::join!(
             self.vector_search(query, top_k, 0.0),
             self.keyword_search(query, top_k, 0.0),
@@ -520,13 +563,14 @@ impl Rag {
         let vector_search_results = vector_search_results?;
         debug!("vector_search_results: {vector_search_results:?}",);
         let vector_search_ids: Vec<DocumentId> =
-            vector_search_results.into_iter().map(|(v, _)| v).collect();
+            vector_search_results.iter().map(|(v, _)| *v).collect();
 
         let keyword_search_results = keyword_search_results?;
         debug!("keyword_search_results: {keyword_search_results:?}",);
         let keyword_search_ids: Vec<DocumentId> =
-            keyword_search_results.into_iter().map(|(v, _)| v).collect();
+            keyword_search_results.iter().map(|(v, _)| *v).collect();
 
+        let mut rerank_scores: HashMap<DocumentId, f64> = HashMap::new();
         let ids = match rerank_model {
             Some(model_id) => {
                 let model =
@@ -549,7 +593,11 @@ impl Rag {
                 let ids: Vec<_> = list
                     .into_iter()
                     .take(top_k)
-                    .filter_map(|item| documents_ids.get(item.index).cloned())
+                    .filter_map(|item| {
+                        let id = documents_ids.get(item.index).cloned()?;
+                        rerank_scores.insert(id, item.relevance_score);
+                        Some(id)
+                    })
                     .collect();
                 debug!("rerank_ids: {ids:?}");
                 ids
@@ -564,11 +612,23 @@ impl Rag {
                 ids
             }
         };
+        let find_score = |list: &[(DocumentId, f32)], id: DocumentId| {
+            list.iter().find(|(v, _)| *v == id).map(|(_, score)| *score)
+        };
         let output = ids
             .into_iter()
             .filter_map(|id| {
                 let document = self.data.get(id)?;
-                Some((id, document.page_content.clone()))
+                let (file_index, _) = id.split();
+                Some(RagSearchHit {
+                    id,
+                    source: self.data.files.get(&file_index)?.path.clone(),
+                    heading: self.data.section_heading(id),
+                    content: document.page_content.clone(),
+                    vector_score: find_score(&vector_search_results, id),
+                    keyword_score: find_score(&keyword_search_results, id),
+                    rerank_score: rerank_scores.get(&id).copied(),
+                })
             })
             .collect();
         Ok(output)
@@ -633,7 +693,7 @@ impl Rag {
         &self,
         data: EmbeddingsData,
         spinner: Option<Spinner>,
-    ) -> Result<EmbeddingsOutput> {
+    ) -> Result<Vec<Vec<f32>>> {
         let embedding_client = init_client(&self.config, Some(self.embedding_model.clone()))?;
         let EmbeddingsData { texts, query } = data;
         let batch_size = self
@@ -667,6 +727,8 @@ impl Rag {
                 query,
             };
             let mut retry = 0;
+            // `Client::embeddings` adds the call to the usage ledger, unless it's answered from
+            // the response cache
             let chunk_output = loop {
                 retry += 1;
                 match embedding_client.embeddings(&chunk_data).await {
@@ -697,6 +759,8 @@ pub struct RagData {
     pub reranker_model: Option<String>,
     pub top_k: usize,
     pub batch_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub html_to_md: bool,
     pub next_file_id: FileId,
     pub document_paths: Vec<String>,
     pub files: IndexMap<FileId, RagFile>,
@@ -713,6 +777,7 @@ impl Debug for RagData {
             .field("reranker_model", &self.reranker_model)
             .field("top_k", &self.top_k)
             .field("batch_size", &self.batch_size)
+            .field("html_to_md", &self.html_to_md)
             .field("next_file_id", &self.next_file_id)
             .field("document_paths", &self.document_paths)
             .field("files", &self.files)
@@ -736,6 +801,7 @@ impl RagData {
             reranker_model,
             top_k,
             batch_size,
+            html_to_md: false,
             next_file_id: 0,
             document_paths: Default::default(),
             files: Default::default(),
@@ -750,6 +816,34 @@ impl RagData {
         Some(document)
     }
 
+    /// The closest markdown heading at or before the start of the document.
+    pub fn section_heading(&self, id: DocumentId) -> Option<String> {
+        let (file_index, document_index) = id.split();
+        let documents = &self.files.get(&file_index)?.documents;
+        let document = documents.get(document_index)?;
+        let parse_heading = |line: &str| {
+            let line = line.trim();
+            let heading = line.trim_start_matches('#');
+            if line.starts_with('#') && heading.starts_with(' ') {
+                Some(heading.trim().to_string())
+            } else {
+                None
+            }
+        };
+        let mut lines = document
+            .page_content
+            .lines()
+            .filter(|v| !v.trim().is_empty());
+        if let Some(heading) = lines.next().and_then(parse_heading) {
+            return Some(heading);
+        }
+        documents[..document_index]
+            .iter()
+            .rev()
+            .find_map(|v| v.page_content.lines().rev().find_map(parse_heading))
+            .or_else(|| document.page_content.lines().find_map(parse_heading))
+    }
+
     pub fn del(&mut self, file_ids: Vec<FileId>) {
         for file_id in file_ids {
             if let Some(file) = self.files.swap_remove(&file_id) {
@@ -766,7 +860,7 @@ impl RagData {
         next_file_id: FileId,
         files: Vec<(FileId, RagFile)>,
         document_ids: Vec<DocumentId>,
-        embeddings: EmbeddingsOutput,
+        embeddings: Vec<Vec<f32>>,
     ) {
         self.next_file_id = next_file_id;
         self.files.extend(files);
@@ -796,6 +890,18 @@ impl RagData {
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct RagSearchHit {
+    #[serde(skip)]
+    pub id: DocumentId,
+    pub source: String,
+    pub heading: Option<String>,
+    pub content: String,
+    pub vector_score: Option<f32>,
+    pub keyword_score: Option<f32>,
+    pub rerank_score: Option<f64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RagFile {
     hash: String,
@@ -994,10 +1100,7 @@ fn reciprocal_rank_fusion(
 ) -> Vec<DocumentId> {
     let rrf_k = top_k * 2;
     let mut map: IndexMap<DocumentId, f32> = IndexMap::new();
-    for (document_ids, weight) in list_of_document_ids
-        .into_iter()
-        .zip(list_of_weights.into_iter())
-    {
+    for (document_ids, weight) in list_of_document_ids.into_iter().zip(list_of_weights) {
         for (index, &item) in document_ids.iter().enumerate() {
             *map.entry(item).or_default() += (1.0 / ((rrf_k + index + 1) as f32)) * weight;
         }
@@ -1,6 +1,14 @@
+mod chat_api;
+
+use self::chat_api::{
+    convert_anthropic_messages, convert_anthropic_tools, convert_responses_input,
+    convert_responses_tools, ret_messages_err, ret_messages_non_stream, ret_responses_non_stream,
+    ChatApi, ChatStreamWriter,
+};
+
 use crate::{client::*, config::*, function::*, learning::*, rag::*, utils::*};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use bytes::Bytes;
 use chrono::{Timelike, Utc};
 use futures_util::StreamExt;
@@ -12,19 +20,22 @@ use hyper::{
 };
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use parking_lot::RwLock;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::{json, Value};
 use std::{
+    collections::HashMap,
     convert::Infallible,
     net::IpAddr,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Duration,
 };
 use tokio::{
     net::TcpListener,
     sync::{
+        broadcast,
         mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
@@ -51,16 +62,31 @@ pub async fn run(config: GlobalConfig, addr: Option<String>) -> Result<()> {
         }
         None => config.read().serve_addr(),
     };
-    let server = Arc::new(Server::new(&config));
+    let server = Arc::new(Server::new(&config)?);
+    let num_accounts = server.auth.read().store().len();
+    let api_keys = server.api_keys.clone();
+    let num_api_keys = api_keys.read().keys().len();
     let listener = TcpListener::bind(&addr).await?;
     let stop_server = server.run(listener).await?;
     println!("Chat Completions API: http://{addr}/v1/chat/completions");
+    println!("Responses API:        http://{addr}/v1/responses");
+    println!("Messages API:         http://{addr}/v1/messages");
     println!("Embeddings API:       http://{addr}/v1/embeddings");
     println!("Rerank API:           http://{addr}/v1/rerank");
     println!("LLM Playground:       http://{addr}/playground");
     println!("LLM Arena:            http://{addr}/arena?num=2");
+    println!("Learning App:         http://{addr}/learn");
+    if num_accounts > 0 {
+        println!("Authentication:       enabled ({num_accounts} accounts)");
+    } else {
+        println!("Authentication:       disabled, add an account with --add-account to enable it");
+    }
+    if num_api_keys > 0 {
+        println!("API Keys:             {num_api_keys} keys for /v1/*");
+    }
     shutdown_signal().await;
     let _ = stop_server.send(());
+    api_keys.write().flush(Utc::now().timestamp())?;
     Ok(())
 }
 
@@ -69,13 +95,21 @@ struct Server {
     models: Vec<Value>,
     roles: Vec<Role>,
     rags: Vec<String>,
-    lesson_manager: LessonManager,
-    quiz_manager: QuizManager,
+    lesson_manager: Arc<RwLock<LessonManager>>,
+    quiz_manager: Arc<RwLock<QuizManager>>,
+    content_changes: broadcast::Sender<ContentChange>,
+    course_index: Arc<RwLock<Option<Arc<CourseIndex>>>>,
+    _content_watcher: Option<ContentWatcher>,
     code_executor: RustCodeExecutor,
+    rust_docs_rag: RwLock<Option<Arc<Rag>>>,
+    auth: RwLock<Authenticator>,
+    api_keys: Arc<RwLock<ApiKeyGate>>,
+    activity: ActivityLog,
+    exams: RwLock<ExamStore>,
 }
 
 impl Server {
-    fn new(config: &GlobalConfig) -> Self {
+    fn new(config: &GlobalConfig) -> Result<Self> {
         let mut config = config.read().clone();
         config.functions = Functions::default();
         let mut models = list_all_models(&config);
@@ -101,7 +135,7 @@ impl Server {
                 value
             })
             .collect();
-        
+
         // Initialize learning components
         let global_config = Arc::new(RwLock::new(config.clone()));
         let lesson_manager = LessonManager::new(global_config.clone()).unwrap_or_else(|e| {
@@ -109,21 +143,52 @@ impl Server {
             // Create a minimal lesson manager as fallback - we'll handle this error differently
             panic!("Could not initialize lesson manager")
         });
-        let quiz_manager = QuizManager::new();
+        let lesson_manager = Arc::new(RwLock::new(lesson_manager));
+        let quiz_manager = Arc::new(RwLock::new(QuizManager::new()));
+        let (content_changes, _) = broadcast::channel(64);
+        let content_watcher = ContentWatcher::spawn(
+            lesson_manager.clone(),
+            quiz_manager.clone(),
+            content_changes.clone(),
+        )
+        .map_err(|err| warn!("Lessons will not reload on change, {err:#}"))
+        .ok();
+
+        // Rebuild the search index lazily after the course changes
+        let course_index: Arc<RwLock<Option<Arc<CourseIndex>>>> = Default::default();
+        let mut changes = content_changes.subscribe();
+        let index = course_index.clone();
+        tokio::spawn(async move {
+            while !matches!(
+                changes.recv().await,
+                Err(broadcast::error::RecvError::Closed)
+            ) {
+                *index.write() = None;
+            }
+        });
         let code_executor = RustCodeExecutor::new().unwrap_or_else(|_| {
             println!("Warning: Failed to initialize code executor");
             RustCodeExecutor::default()
         });
-        
-        Self {
+        let api_keys = ApiKeyGate::init(&config)?;
+
+        Ok(Self {
             config,
             models,
             roles: Config::all_roles(),
             rags: Config::list_rags(),
             lesson_manager,
             quiz_manager,
+            content_changes,
+            course_index,
+            _content_watcher: content_watcher,
             code_executor,
-        }
+            rust_docs_rag: Default::default(),
+            auth: RwLock::new(Authenticator::init()?),
+            api_keys: Arc::new(RwLock::new(api_keys)),
+            activity: ActivityLog::init(),
+            exams: RwLock::new(ExamStore::init()?),
+        })
     }
 
     async fn run(self: Arc<Self>, listener: TcpListener) -> Result<oneshot::Sender<()>> {
@@ -161,50 +226,146 @@ impl Server {
 
     async fn handle(
         self: Arc<Self>,
-        req: hyper::Request<Incoming>,
+        mut req: hyper::Request<Incoming>,
     ) -> std::result::Result<AppResponse, hyper::Error> {
         let method = req.method().clone();
         let uri = req.uri().clone();
         let path = uri.path();
+        let origin = header_value(&req, "origin").map(|v| v.to_string());
 
         if method == Method::OPTIONS {
             let mut res = Response::default();
             *res.status_mut() = StatusCode::NO_CONTENT;
-            set_cors_header(&mut res);
+            set_cors_header(&mut res, origin.as_deref(), &self.config.cors_origins);
             return Ok(res);
         }
 
         let mut status = StatusCode::OK;
-        let res = if path == "/v1/chat/completions" {
+        let res = if let Err(err_status) = self.authorize(&mut req) {
+            status = err_status;
+            Err(anyhow!(
+                "{}",
+                err_status.canonical_reason().unwrap_or_default()
+            ))
+        } else if path == "/v1/chat/completions" {
             self.chat_completions(req).await
+        } else if path == "/v1/responses" {
+            self.responses(req).await
+        } else if path == "/v1/messages" {
+            self.messages(req).await
         } else if path == "/v1/embeddings" {
             self.embeddings(req).await
         } else if path == "/v1/rerank" {
             self.rerank(req).await
         } else if path == "/v1/models" {
-            self.list_models()
+            self.list_models(&req)
         } else if path == "/v1/roles" {
             self.list_roles()
         } else if path == "/v1/rags" {
             self.list_rags()
         } else if path == "/v1/rags/search" {
             self.search_rag(req).await
+        } else if path == "/api/login" {
+            self.login(req).await
+        } else if path == "/api/logout" {
+            self.logout(req)
+        } else if path == "/api/me" {
+            self.me(req)
+        } else if path == "/api/accounts" {
+            self.list_accounts()
         } else if path == "/learn" || path == "/learn.html" {
             self.learning_page()
+        } else if path == "/api/lessons" && method == Method::POST {
+            self.create_lesson(req).await
         } else if path == "/api/lessons" {
-            self.list_lessons()
+            self.list_lessons(&req)
+        } else if path == "/api/lessons/preview" {
+            self.preview_lesson(req).await
+        } else if path == "/api/lessons/reload" {
+            self.reload_lessons()
+        } else if path.starts_with("/api/lessons/") {
+            let lesson_id = path.strip_prefix("/api/lessons/").unwrap().to_string();
+            match method {
+                Method::PUT => self.update_lesson(req, &lesson_id).await,
+                Method::DELETE => self.delete_lesson(&lesson_id),
+                _ => self.get_lesson(&lesson_id, None),
+            }
         } else if path.starts_with("/api/lesson/") {
             let lesson_id = path.strip_prefix("/api/lesson/").unwrap();
-            self.get_lesson(lesson_id)
+            self.view_lesson(&req, lesson_id)
+        } else if path == "/api/locale" {
+            self.locale_strings(&req)
         } else if path == "/api/quizzes" {
-            self.list_quizzes()
+            self.list_quizzes(&req)
+        } else if path.starts_with("/api/quiz/") && path.ends_with("/submit") {
+            let quiz_id = path
+                .strip_prefix("/api/quiz/")
+                .and_then(|v| v.strip_suffix("/submit"))
+                .unwrap_or_default()
+                .to_string();
+            self.submit_quiz(req, &quiz_id).await
         } else if path.starts_with("/api/quiz/") {
             let quiz_id = path.strip_prefix("/api/quiz/").unwrap();
-            self.get_quiz(quiz_id)
+            self.get_quiz(&req, quiz_id)
+        } else if path == "/api/exams" {
+            self.list_exams(&req)
+        } else if path.starts_with("/api/exam/") {
+            let exam_path = path.strip_prefix("/api/exam/").unwrap();
+            match exam_path.rsplit_once('/') {
+                Some((quiz_id, "start")) if method == Method::POST => {
+                    self.start_exam(&req, quiz_id)
+                }
+                Some((quiz_id, "submit")) if method == Method::POST => {
+                    let quiz_id = quiz_id.to_string();
+                    self.submit_exam(req, &quiz_id).await
+                }
+                _ => self.get_exam(&req, exam_path),
+            }
         } else if path == "/api/execute" {
             self.execute_code(req).await
+        } else if path == "/api/search" {
+            self.search_course(req).await
+        } else if path == "/api/recommendations" {
+            self.recommendations(&req)
+        } else if path == "/api/badges" {
+            self.list_badges(&req)
+        } else if path == "/api/certificates/verify" && method == Method::POST {
+            self.verify_certificate(req).await
+        } else if path.starts_with("/api/certificates/") {
+            let badge_id = path.strip_prefix("/api/certificates/").unwrap();
+            self.certificate_page(&req, badge_id)
+        } else if path == "/api/progress/export" {
+            self.export_progress(&req)
+        } else if path == "/api/progress/import" && method == Method::POST {
+            self.import_progress(req).await
         } else if path == "/api/search-rust-docs" {
             self.search_rust_docs(req).await
+        } else if path == "/api/events" {
+            self.content_events()
+        } else if path == "/api/tutor/chat" {
+            self.tutor_chat(req).await
+        } else if path == "/api/instructor/dashboard" {
+            self.instructor_dashboard(&req)
+        } else if path.starts_with("/api/instructor/exams/") {
+            let exam_path = path.strip_prefix("/api/instructor/exams/").unwrap();
+            match exam_path.strip_suffix("/release") {
+                Some(quiz_id) if method == Method::POST => self.release_exam(quiz_id),
+                _ => self.exam_report(exam_path),
+            }
+        } else if path.starts_with("/api/instructor/similarity/") {
+            let lesson_id = path.strip_prefix("/api/instructor/similarity/").unwrap();
+            self.code_similarity(&req, lesson_id).await
+        } else if path.starts_with("/api/instructor/") {
+            let section = path.strip_prefix("/api/instructor/").unwrap();
+            self.instructor_report(&req, section)
+        } else if path == "/execute" || path == "/evaluate.json" {
+            self.playground_execute(req, path == "/evaluate.json").await
+        } else if path == "/compile" {
+            self.playground_compile(req).await
+        } else if path == "/format" {
+            self.playground_format(req).await
+        } else if path == "/clippy" {
+            self.playground_clippy(req).await
         } else if path == "/playground" || path == "/playground.html" {
             self.playground_page()
         } else if path == "/arena" || path == "/arena.html" {
@@ -215,7 +376,7 @@ impl Server {
         };
         let mut res = match res {
             Ok(res) => {
-                info!("{method} {uri} {}", status.as_u16());
+                info!("{method} {uri} {}", res.status().as_u16());
                 res
             }
             Err(err) => {
@@ -223,11 +384,146 @@ impl Server {
                     status = StatusCode::BAD_REQUEST;
                 }
                 error!("{method} {uri} {} {err}", status.as_u16());
-                ret_err(err)
+                if path == "/v1/messages" {
+                    ret_messages_err(status, err)
+                } else {
+                    ret_err(err)
+                }
+            }
+        };
+        if status != StatusCode::OK {
+            *res.status_mut() = status;
+        }
+        set_cors_header(&mut res, origin.as_deref(), &self.config.cors_origins);
+        Ok(res)
+    }
+
+    /// Resolve the caller from a bearer token or session cookie and check it may access the path.
+    fn authorize(&self, req: &mut hyper::Request<Incoming>) -> std::result::Result<(), StatusCode> {
+        let is_provider_route = req.uri().path().starts_with("/v1/");
+        if is_provider_route {
+            let mut api_keys = self.api_keys.write();
+            // Anthropic's clients send their key in `x-api-key`
+            let key = bearer_token(req)
+                .or_else(|| header_value(req, "x-api-key"))
+                .and_then(|v| api_keys.verify(v))
+                .cloned();
+            if let Some(key) = key {
+                if let Err(err) = api_keys.admit(&key, Utc::now().timestamp()) {
+                    warn!("{err}");
+                    return Err(StatusCode::TOO_MANY_REQUESTS);
+                }
+                req.extensions_mut().insert(key);
+                return Ok(());
+            }
+        }
+        // Keys only limit anonymous callers, accounts use every model without a quota
+        let mut auth = self.auth.write();
+        auth.refresh();
+        if !auth.is_enabled() {
+            // Configured API keys guard the provider routes even without accounts
+            if is_provider_route && self.api_keys.read().is_enabled() {
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+            // Without accounts nobody can prove to be an instructor
+            if account_required(req.method(), req.uri().path()) {
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+            return Ok(());
+        }
+        let identity = match bearer_token(req) {
+            Some(token) => auth.verify_token(token),
+            None => session_cookie(req).and_then(|v| auth.verify_session(v)),
+        };
+        let required_role = required_role(req.method(), req.uri().path());
+        match (identity, required_role) {
+            (Some(identity), Some(role)) if identity.role < role => Err(StatusCode::FORBIDDEN),
+            (Some(identity), _) => {
+                req.extensions_mut().insert(identity);
+                Ok(())
             }
+            (None, Some(_)) => Err(StatusCode::UNAUTHORIZED),
+            (None, None) => Ok(()),
+        }
+    }
+
+    async fn login(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let req_body = req.collect().await?.to_bytes();
+        let LoginReqBody { username, password } = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        // Password hashing is slow by design, keep it off the lock and the async workers
+        let store = self.auth.read().store().clone();
+        let identity =
+            tokio::task::spawn_blocking(move || store.verify_password(&username, &password))
+                .await?;
+        let Some(identity) = identity else {
+            return Ok(ret_status_err(
+                StatusCode::UNAUTHORIZED,
+                "Invalid username or password",
+            ));
         };
-        *res.status_mut() = status;
-        set_cors_header(&mut res);
+        let session_id = self.auth.write().start_session(&identity);
+        info!("{} logged in as {:?}", identity.username, identity.role);
+
+        let data = json!({ "data": identity });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .header(
+                hyper::header::SET_COOKIE,
+                format!(
+                    "{SESSION_COOKIE_NAME}={session_id}; Path=/; HttpOnly; SameSite=Strict; Max-Age={SESSION_TTL_SECS}"
+                ),
+            )
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn logout(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        if let Some(session_id) = session_cookie(&req) {
+            self.auth.write().logout(session_id);
+        }
+        let data = json!({ "data": null });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .header(
+                hyper::header::SET_COOKIE,
+                format!("{SESSION_COOKIE_NAME}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0"),
+            )
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn me(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let identity = req.extensions().get::<Identity>();
+        let data = json!({
+            "data": identity,
+            "auth_enabled": self.auth.read().is_enabled(),
+        });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn list_accounts(&self) -> Result<AppResponse> {
+        let auth = self.auth.read();
+        let store = auth.store();
+        let accounts: Vec<_> = store
+            .accounts()
+            .map(|(username, account)| {
+                json!({
+                    "username": username,
+                    "role": account.role,
+                    "created_at": account.created_at,
+                    "tokens": store.tokens().iter().filter(|v| &v.username == username).count(),
+                })
+            })
+            .collect();
+        let data = json!({ "data": accounts });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
         Ok(res)
     }
 
@@ -245,8 +541,22 @@ impl Server {
         Ok(res)
     }
 
-    fn list_models(&self) -> Result<AppResponse> {
-        let data = json!({ "data": self.models });
+    fn list_models(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let models: Vec<&Value> = match req.extensions().get::<VirtualApiKey>() {
+            Some(key) => {
+                let default_model_id = self.config.model.id();
+                self.models
+                    .iter()
+                    .filter(|v| match v["id"].as_str() {
+                        Some(DEFAULT_MODEL_NAME) => key.allows_model(&default_model_id),
+                        Some(id) => key.allows_model(id),
+                        None => false,
+                    })
+                    .collect()
+            }
+            None => self.models.iter().collect(),
+        };
+        let data = json!({ "data": models });
         let res = Response::builder()
             .header("Content-Type", "application/json; charset=utf-8")
             .body(Full::new(Bytes::from(data.to_string())).boxed())?;
@@ -295,6 +605,7 @@ impl Server {
     }
 
     async fn chat_completions(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let api_key = req.extensions().get::<VirtualApiKey>().cloned();
         let req_body = req.collect().await?.to_bytes();
         let req_body: Value = serde_json::from_slice(&req_body)
             .map_err(|err| anyhow!("Invalid request json, {err}"))?;
@@ -310,14 +621,148 @@ impl Server {
             top_p,
             max_tokens,
             stream,
+            stream_options,
+            tools,
+        } = req_body;
+        let include_usage = stream_options.is_some_and(|v| v.include_usage);
+
+        let messages =
+            parse_messages(messages).map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        let functions = parse_tools(tools).map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        let chat = ChatRequest {
+            api: ChatApi::ChatCompletions { include_usage },
+            model,
+            messages,
+            temperature,
+            top_p,
+            max_tokens,
+            functions,
+            stream,
+        };
+        self.chat(api_key, chat).await
+    }
+
+    /// OpenAI's Responses API, translated onto the chat completions of the configured models.
+    /// Conversations are stateless, so `previous_response_id` isn't supported.
+    async fn responses(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let api_key = req.extensions().get::<VirtualApiKey>().cloned();
+        let req_body = req.collect().await?.to_bytes();
+        let req_body: Value = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+
+        debug!("responses request: {req_body}");
+        let req_body = serde_json::from_value(req_body)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        let ResponsesReqBody {
+            model,
+            input,
+            instructions,
+            temperature,
+            top_p,
+            max_output_tokens,
+            stream,
             tools,
+            previous_response_id,
         } = req_body;
+        if previous_response_id.is_some() {
+            bail!("'previous_response_id' isn't supported, send the whole conversation as 'input'");
+        }
 
-        let mut messages =
+        let mut messages = vec![];
+        if let Some(instructions) = instructions {
+            messages.push(json!({ "role": "system", "content": instructions }));
+        }
+        match input {
+            ResponsesReqBodyInput::Text(text) => {
+                messages.push(json!({ "role": "user", "content": text }))
+            }
+            ResponsesReqBodyInput::Items(items) => messages.extend(
+                convert_responses_input(items)
+                    .map_err(|err| anyhow!("Invalid request body, {err}"))?,
+            ),
+        }
+        let messages =
             parse_messages(messages).map_err(|err| anyhow!("Invalid request body, {err}"))?;
 
+        let tools = tools
+            .map(convert_responses_tools)
+            .transpose()
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+        let functions = parse_tools(tools).map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        let chat = ChatRequest {
+            api: ChatApi::Responses,
+            model,
+            messages,
+            temperature,
+            top_p,
+            max_tokens: max_output_tokens,
+            functions,
+            stream,
+        };
+        self.chat(api_key, chat).await
+    }
+
+    /// Anthropic's Messages API, translated onto the chat completions of the configured models.
+    async fn messages(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let api_key = req.extensions().get::<VirtualApiKey>().cloned();
+        let req_body = req.collect().await?.to_bytes();
+        let req_body: Value = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+
+        debug!("messages request: {req_body}");
+        let req_body = serde_json::from_value(req_body)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        let MessagesReqBody {
+            model,
+            system,
+            messages,
+            temperature,
+            top_p,
+            max_tokens,
+            stream,
+            tools,
+        } = req_body;
+
+        let messages = convert_anthropic_messages(system, messages)
+            .and_then(parse_messages)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        let tools = tools
+            .map(convert_anthropic_tools)
+            .transpose()
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
         let functions = parse_tools(tools).map_err(|err| anyhow!("Invalid request body, {err}"))?;
 
+        let chat = ChatRequest {
+            api: ChatApi::Messages,
+            model,
+            messages,
+            temperature,
+            top_p,
+            max_tokens,
+            functions,
+            stream,
+        };
+        self.chat(api_key, chat).await
+    }
+
+    async fn chat(&self, api_key: Option<VirtualApiKey>, chat: ChatRequest) -> Result<AppResponse> {
+        let ChatRequest {
+            api,
+            model,
+            mut messages,
+            temperature,
+            top_p,
+            max_tokens,
+            functions,
+            stream,
+        } = chat;
+
         let config = self.config.clone();
 
         let default_model = config.model.clone();
@@ -332,6 +777,9 @@ impl Server {
             (model, true)
         };
 
+        if let Some(err) = model_forbidden(api_key.as_ref(), &model_name) {
+            return Ok(api.ret_err(StatusCode::FORBIDDEN, err));
+        }
         if change {
             config.write().set_model(&model_name)?;
         }
@@ -340,12 +788,6 @@ impl Server {
         if max_tokens.is_some() {
             client.model_mut().set_max_tokens(max_tokens, true);
         }
-        let abort_signal = create_abort_signal();
-        let http_client = client.build_client()?;
-
-        let completion_id = generate_completion_id();
-        let created = Utc::now().timestamp();
-
         patch_messages(&mut messages, client.model());
 
         let data: ChatCompletionsData = ChatCompletionsData {
@@ -356,154 +798,25 @@ impl Server {
             stream,
         };
 
-        if stream {
-            let (tx, mut rx) = unbounded_channel();
-            tokio::spawn(async move {
-                let is_first = Arc::new(AtomicBool::new(true));
-                let (sse_tx, sse_rx) = unbounded_channel();
-                let mut handler = SseHandler::new(sse_tx, abort_signal);
-                async fn map_event(
-                    mut sse_rx: UnboundedReceiver<SseEvent>,
-                    tx: &UnboundedSender<ResEvent>,
-                    is_first: Arc<AtomicBool>,
-                ) {
-                    while let Some(reply_event) = sse_rx.recv().await {
-                        if is_first.load(Ordering::SeqCst) {
-                            let _ = tx.send(ResEvent::First(None));
-                            is_first.store(false, Ordering::SeqCst)
-                        }
-                        match reply_event {
-                            SseEvent::Text(text) => {
-                                let _ = tx.send(ResEvent::Text(text));
-                            }
-                            SseEvent::Done => {
-                                let _ = tx.send(ResEvent::Done);
-                                sse_rx.close();
-                            }
-                        }
-                    }
-                }
-                async fn chat_completions(
-                    client: &dyn Client,
-                    http_client: &reqwest::Client,
-                    handler: &mut SseHandler,
-                    mut data: ChatCompletionsData,
-                    tx: &UnboundedSender<ResEvent>,
-                    is_first: Arc<AtomicBool>,
-                ) {
-                    if client.model().no_stream() {
-                        data.stream = false;
-                        let ret = client.chat_completions_inner(http_client, data).await;
-                        match ret {
-                            Ok(output) => {
-                                let ChatCompletionsOutput {
-                                    text, tool_calls, ..
-                                } = output;
-                                let _ = tx.send(ResEvent::First(None));
-                                is_first.store(false, Ordering::SeqCst);
-                                let _ = tx.send(ResEvent::Text(text));
-                                if !tool_calls.is_empty() {
-                                    let _ = tx.send(ResEvent::ToolCalls(tool_calls));
-                                }
-                            }
-                            Err(err) => {
-                                let _ = tx.send(ResEvent::First(Some(format!("{err:?}"))));
-                                is_first.store(false, Ordering::SeqCst)
-                            }
-                        };
-                    } else {
-                        let ret = client
-                            .chat_completions_streaming_inner(http_client, handler, data)
-                            .await;
-                        let first = match ret {
-                            Ok(()) => None,
-                            Err(err) => Some(format!("{err:?}")),
-                        };
-                        if is_first.load(Ordering::SeqCst) {
-                            let _ = tx.send(ResEvent::First(first));
-                            is_first.store(false, Ordering::SeqCst)
-                        }
-                        let tool_calls = handler.tool_calls().to_vec();
-                        if !tool_calls.is_empty() {
-                            let _ = tx.send(ResEvent::ToolCalls(tool_calls));
-                        }
-                    }
-                    handler.done();
-                }
-                tokio::join!(
-                    map_event(sse_rx, &tx, is_first.clone()),
-                    chat_completions(
-                        client.as_ref(),
-                        &http_client,
-                        &mut handler,
-                        data,
-                        &tx,
-                        is_first
-                    ),
-                );
-            });
-
-            let first_event = rx.recv().await;
-
-            if let Some(ResEvent::First(Some(err))) = first_event {
-                bail!("{err}");
-            }
-
-            let shared: Arc<(String, String, i64, AtomicBool)> =
-                Arc::new((completion_id, model_name, created, AtomicBool::new(false)));
-            let stream = UnboundedReceiverStream::new(rx);
-            let stream = stream.filter_map(move |res_event| {
-                let shared = shared.clone();
-                async move {
-                    let (completion_id, model, created, has_tool_calls) = shared.as_ref();
-                    match res_event {
-                        ResEvent::Text(text) => {
-                            Some(Ok(create_text_frame(completion_id, model, *created, &text)))
-                        }
-                        ResEvent::ToolCalls(tool_calls) => {
-                            has_tool_calls.store(true, Ordering::SeqCst);
-                            Some(Ok(create_tool_calls_frame(
-                                completion_id,
-                                model,
-                                *created,
-                                &tool_calls,
-                            )))
-                        }
-                        ResEvent::Done => Some(Ok(create_done_frame(
-                            completion_id,
-                            model,
-                            *created,
-                            has_tool_calls.load(Ordering::SeqCst),
-                        ))),
-                        _ => None,
-                    }
+        let api_keys = self.api_keys.clone();
+        let api_key_name = api_key.as_ref().map(|v| v.name.clone());
+        chat_completions_response(
+            client,
+            data,
+            model_name,
+            api,
+            api_key_name,
+            move |_, usage, cost| {
+                if let Some(key) = api_key {
+                    record_api_key_usage(&api_keys, &key, usage, cost);
                 }
-            });
-            let res = Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", "text/event-stream")
-                .header("Cache-Control", "no-cache")
-                .header("Connection", "keep-alive")
-                .body(BodyExt::boxed(StreamBody::new(stream)))?;
-            Ok(res)
-        } else {
-            let output = client.chat_completions_inner(&http_client, data).await?;
-            let res = Response::builder()
-                .header("Content-Type", "application/json")
-                .body(
-                    Full::new(ret_non_stream(
-                        &completion_id,
-                        &model_name,
-                        created,
-                        &output,
-                    ))
-                    .boxed(),
-                )?;
-            Ok(res)
-        }
+            },
+        )
+        .await
     }
 
     async fn embeddings(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let api_key = req.extensions().get::<VirtualApiKey>().cloned();
         let req_body = req.collect().await?.to_bytes();
         let req_body: Value = serde_json::from_slice(&req_body)
             .map_err(|err| anyhow!("Invalid request json, {err}"))?;
@@ -521,19 +834,36 @@ impl Server {
 
         let embedding_model =
             Model::retrieve_model(&config.read(), &embedding_model_id, ModelType::Embedding)?;
+        if let Some(res) = forbid_model(api_key.as_ref(), &embedding_model.id()) {
+            return Ok(res);
+        }
 
         let texts = match input {
             EmbeddingsReqBodyInput::Single(v) => vec![v],
             EmbeddingsReqBodyInput::Multiple(v) => v,
         };
         let client = init_client(&config, Some(embedding_model))?;
-        let data = client
-            .embeddings(&EmbeddingsData {
-                query: false,
-                texts,
-            })
-            .await?;
-        let data: Vec<_> = data
+        // Called directly rather than through the response cache, as usage is recorded here
+        let output = client
+            .embeddings_inner(
+                &client.build_client()?,
+                &EmbeddingsData {
+                    query: false,
+                    texts: texts.clone(),
+                },
+            )
+            .await
+            .context("Failed to call embeddings api")?;
+        // Estimated for the embeddings APIs that don't report usage
+        let usage = TokenUsage {
+            input_tokens: output
+                .input_tokens
+                .unwrap_or_else(|| texts.iter().map(|v| estimate_token_length(v) as u64).sum()),
+            output_tokens: 0,
+            estimated: output.input_tokens.is_none(),
+        };
+        let data: Vec<_> = output
+            .embeddings
             .into_iter()
             .enumerate()
             .map(|(i, v)| {
@@ -544,13 +874,29 @@ impl Server {
                 })
             })
             .collect();
+        let cost = usage.cost(&client.answered_model());
+        if let Some(key) = &api_key {
+            record_api_key_usage(&self.api_keys, key, &usage, cost);
+        }
+        let entry = UsageEntry::new(
+            &config.read(),
+            UsageKind::Embeddings,
+            client.as_ref(),
+            &usage,
+        );
+        record_usage(
+            &config.read(),
+            &entry.with_api_key(api_key.as_ref().map(|v| v.name.as_str())),
+        );
         let output = json!({
             "object": "list",
             "data": data,
             "model": embedding_model_id,
             "usage": {
-                "prompt_tokens": 0,
-                "total_tokens": 0,
+                "prompt_tokens": usage.input_tokens,
+                "total_tokens": usage.total_tokens(),
+                "cost": cost,
+                "estimated": usage.estimated,
             }
         });
         let res = Response::builder()
@@ -560,6 +906,7 @@ impl Server {
     }
 
     async fn rerank(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let api_key = req.extensions().get::<VirtualApiKey>().cloned();
         let req_body = req.collect().await?.to_bytes();
         let req_body: Value = serde_json::from_slice(&req_body)
             .map_err(|err| anyhow!("Invalid request json, {err}"))?;
@@ -581,15 +928,44 @@ impl Server {
 
         let reranker_model =
             Model::retrieve_model(&config.read(), &reranker_model_id, ModelType::Reranker)?;
+        if let Some(res) = forbid_model(api_key.as_ref(), &reranker_model.id()) {
+            return Ok(res);
+        }
 
+        let input_tokens = std::iter::once(&query)
+            .chain(&documents)
+            .map(|v| estimate_token_length(v) as u64)
+            .sum();
+        let usage = TokenUsage {
+            input_tokens,
+            output_tokens: 0,
+            estimated: true,
+        };
         let client = init_client(&config, Some(reranker_model))?;
         let data = client
-            .rerank(&RerankData {
-                query,
-                documents: documents.clone(),
-                top_n,
-            })
-            .await?;
+            .rerank_inner(
+                &client.build_client()?,
+                &RerankData {
+                    query,
+                    documents: documents.clone(),
+                    top_n,
+                },
+            )
+            .await
+            .context("Failed to call rerank api")?;
+        if let Some(key) = &api_key {
+            record_api_key_usage(
+                &self.api_keys,
+                key,
+                &usage,
+                usage.cost(&client.answered_model()),
+            );
+        }
+        let entry = UsageEntry::new(&config.read(), UsageKind::Rerank, client.as_ref(), &usage);
+        record_usage(
+            &config.read(),
+            &entry.with_api_key(api_key.as_ref().map(|v| v.name.as_str())),
+        );
 
         let results: Vec<_> = data
             .into_iter()
@@ -620,24 +996,34 @@ impl Server {
         Ok(res)
     }
 
-    fn list_lessons(&self) -> Result<AppResponse> {
-        let lessons = self.lesson_manager.list_lessons();
+    fn list_lessons(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let locale = self.request_locale(req);
+        let lesson_manager = self.lesson_manager.read();
+        let lessons = lesson_manager.list_localized_lessons(&locale);
         let data = json!({ "data": lessons });
         let res = Response::builder()
             .header("Content-Type", "application/json; charset=utf-8")
+            .header("Content-Language", &locale)
             .body(Full::new(Bytes::from(data.to_string())).boxed())?;
         Ok(res)
     }
 
-    fn get_lesson(&self, lesson_id: &str) -> Result<AppResponse> {
-        if let Some(lesson) = self.lesson_manager.get_lesson(lesson_id) {
-            let html = self.lesson_manager.render_lesson_html(lesson);
-            let data = json!({ 
+    /// Without a locale the lesson is returned as written in its source file, for editing.
+    fn get_lesson(&self, lesson_id: &str, locale: Option<&str>) -> Result<AppResponse> {
+        let lesson_manager = self.lesson_manager.read();
+        let lesson = match locale {
+            Some(locale) => lesson_manager.get_localized_lesson(lesson_id, locale),
+            None => lesson_manager.get_lesson(lesson_id),
+        };
+        if let Some(lesson) = lesson {
+            let html = lesson_manager.render_lesson_html(lesson);
+            let data = json!({
                 "lesson": lesson,
                 "html": html
             });
             let res = Response::builder()
                 .header("Content-Type", "application/json; charset=utf-8")
+                .header("Content-Language", locale.unwrap_or(DEFAULT_LOCALE))
                 .body(Full::new(Bytes::from(data.to_string())).boxed())?;
             Ok(res)
         } else {
@@ -645,20 +1031,150 @@ impl Server {
         }
     }
 
-    fn list_quizzes(&self) -> Result<AppResponse> {
-        let quizzes = self.quiz_manager.list_quizzes();
+    /// Serve a lesson to a learner and note when they first opened it.
+    fn view_lesson(&self, req: &hyper::Request<Incoming>, lesson_id: &str) -> Result<AppResponse> {
+        let locale = self.request_locale(req);
+        let res = self.get_lesson(lesson_id, Some(&locale))?;
+        let identity = req.extensions().get::<Identity>().cloned();
+        let learner = query_param(req, "learner");
+        let identity = learner_identity(identity, learner.as_deref());
+        let event = ActivityEvent::new(
+            &identity,
+            ActivityKind::LessonView {
+                lesson_id: lesson_id.to_string(),
+            },
+        );
+        if let Err(err) = self.activity.record(&event) {
+            warn!("Failed to record lesson view, {err}");
+        }
+        Ok(res)
+    }
+
+    async fn create_lesson(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let lesson: Lesson = json_body(req).await?;
+        let lesson_id = lesson.id.clone();
+        self.lesson_manager.write().create_lesson(lesson)?;
+        let _ = self
+            .content_changes
+            .send(ContentChange::updated(ContentKind::Lesson, &lesson_id));
+        info!("Created lesson '{lesson_id}'");
+        self.get_lesson(&lesson_id, None)
+    }
+
+    async fn update_lesson(
+        &self,
+        req: hyper::Request<Incoming>,
+        lesson_id: &str,
+    ) -> Result<AppResponse> {
+        let lesson: Lesson = json_body(req).await?;
+        self.lesson_manager
+            .write()
+            .update_lesson(lesson_id, lesson)?;
+        let _ = self
+            .content_changes
+            .send(ContentChange::updated(ContentKind::Lesson, lesson_id));
+        info!("Updated lesson '{lesson_id}'");
+        self.get_lesson(lesson_id, None)
+    }
+
+    fn delete_lesson(&self, lesson_id: &str) -> Result<AppResponse> {
+        let lesson = self.lesson_manager.write().delete_lesson(lesson_id)?;
+        let _ = self
+            .content_changes
+            .send(ContentChange::removed(ContentKind::Lesson, lesson_id));
+        info!("Deleted lesson '{lesson_id}'");
+        let data = json!({ "lesson": lesson });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    /// Render a lesson without saving it, so authors can check their edits.
+    async fn preview_lesson(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let lesson: Lesson = json_body(req).await?;
+        validate_lesson(&lesson)?;
+        let html = self.lesson_manager.read().render_lesson_html(&lesson);
+        let data = json!({
+            "lesson": lesson,
+            "html": html
+        });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn reload_lessons(&self) -> Result<AppResponse> {
+        let mut lesson_manager = self.lesson_manager.write();
+        lesson_manager.load_lessons()?;
+        *self.course_index.write() = None;
+        let data = json!({ "data": lesson_manager.list_lessons() });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    /// Stream lesson and quiz changes to the browser, so it can refresh what it shows.
+    fn content_events(&self) -> Result<AppResponse> {
+        let mut changes = self.content_changes.subscribe();
+        let (tx, rx) = unbounded_channel();
+        tokio::spawn(async move {
+            let mut keep_alive = tokio::time::interval(Duration::from_secs(30));
+            loop {
+                let frame = tokio::select! {
+                    change = changes.recv() => match change {
+                        Ok(change) => Frame::data(Bytes::from(format!("data: {}\n\n", json!(change)))),
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = keep_alive.tick() => Frame::data(Bytes::from(": keep-alive\n\n")),
+                };
+                if tx.send(Ok::<_, Infallible>(frame)).is_err() {
+                    break;
+                }
+            }
+        });
+        let res = Response::builder()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .body(BodyExt::boxed(StreamBody::new(
+                UnboundedReceiverStream::new(rx),
+            )))?;
+        Ok(res)
+    }
+
+    fn list_quizzes(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let locale = self.request_locale(req);
+        let quiz_manager = self.quiz_manager.read();
+        let quizzes: Vec<&Quiz> = quiz_manager
+            .list_quizzes()
+            .into_iter()
+            .filter(|v| v.exam.is_none())
+            .filter_map(|v| quiz_manager.get_localized_quiz(&v.id, &locale))
+            .collect();
         let data = json!({ "data": quizzes });
         let res = Response::builder()
             .header("Content-Type", "application/json; charset=utf-8")
+            .header("Content-Language", &locale)
             .body(Full::new(Bytes::from(data.to_string())).boxed())?;
         Ok(res)
     }
 
-    fn get_quiz(&self, quiz_id: &str) -> Result<AppResponse> {
-        if let Some(quiz) = self.quiz_manager.get_quiz(quiz_id) {
+    fn get_quiz(&self, req: &hyper::Request<Incoming>, quiz_id: &str) -> Result<AppResponse> {
+        let locale = self.request_locale(req);
+        if let Some(quiz) = self
+            .quiz_manager
+            .read()
+            .get_localized_quiz(quiz_id, &locale)
+            .filter(|v| v.exam.is_none())
+        {
             let data = json!({ "quiz": quiz });
             let res = Response::builder()
                 .header("Content-Type", "application/json; charset=utf-8")
+                .header("Content-Language", &locale)
                 .body(Full::new(Bytes::from(data.to_string())).boxed())?;
             Ok(res)
         } else {
@@ -666,24 +1182,272 @@ impl Server {
         }
     }
 
-    async fn execute_code(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+    async fn submit_quiz(
+        &self,
+        req: hyper::Request<Incoming>,
+        quiz_id: &str,
+    ) -> Result<AppResponse> {
+        let identity = req.extensions().get::<Identity>().cloned();
         let req_body = req.collect().await?.to_bytes();
-        let req_body: Value = serde_json::from_slice(&req_body)
-            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+        let QuizSubmitReqBody { answers, learner } = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
 
-        debug!("execute code request: {req_body}");
-        
-        let code = req_body["code"].as_str()
-            .ok_or_else(|| anyhow!("Missing 'code' field"))?;
+        let mut attempt = QuizAttempt {
+            quiz_id: quiz_id.to_string(),
+            answers,
+            score: None,
+            completed_at: None,
+        };
+        let results = {
+            let quiz_manager = self.quiz_manager.read();
+            if quiz_manager
+                .get_quiz(quiz_id)
+                .is_some_and(|v| v.exam.is_some())
+            {
+                bail!("Quiz '{quiz_id}' is an exam, submit it through /api/exam/{quiz_id}/submit");
+            }
+            quiz_manager.grade_attempt(&mut attempt)?
+        };
 
-        // Safety check
-        if !is_safe_code(code) {
+        let identity = learner_identity(identity, learner.as_deref());
+        for result in &results {
+            let Some(answer) = attempt.answers.get(&result.question_id) else {
+                continue;
+            };
+            let event = ActivityEvent::new(
+                &identity,
+                ActivityKind::QuizAnswer {
+                    quiz_id: quiz_id.to_string(),
+                    question_id: result.question_id.clone(),
+                    answer: answer.clone(),
+                    correct: result.correct,
+                },
+            );
+            if let Err(err) = self.activity.record(&event) {
+                warn!("Failed to record quiz answer, {err}");
+            }
+        }
+
+        let data = json!({
+            "data": {
+                "score": attempt.score,
+                "completed_at": attempt.completed_at,
+                "results": results,
+            }
+        });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn list_exams(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let learner = target_learner(
+            req.extensions().get::<Identity>(),
+            query_param(req, "learner"),
+            DEFAULT_LEARNER,
+        );
+        let locale = self.request_locale(req);
+        let now = Utc::now().timestamp();
+        let quiz_manager = self.quiz_manager.read();
+        let exams = self.exams.read();
+        let mut quizzes: Vec<&Quiz> = quiz_manager
+            .list_quizzes()
+            .into_iter()
+            .filter(|v| v.exam.is_some())
+            .collect();
+        quizzes.sort_by(|a, b| a.id.cmp(&b.id));
+        let statuses: Vec<ExamStatus> = quizzes
+            .into_iter()
+            .filter_map(|quiz| {
+                let localized = quiz_manager.get_localized_quiz(&quiz.id, &locale)?;
+                let mut status = exams.status(quiz, localized, &learner, now);
+                status.questions.clear();
+                Some(status)
+            })
+            .collect();
+        let data = json!({ "learner": learner, "data": statuses });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .header("Content-Language", &locale)
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn get_exam(&self, req: &hyper::Request<Incoming>, quiz_id: &str) -> Result<AppResponse> {
+        let learner = target_learner(
+            req.extensions().get::<Identity>(),
+            query_param(req, "learner"),
+            DEFAULT_LEARNER,
+        );
+        self.exam_status(&self.request_locale(req), quiz_id, &learner)
+    }
+
+    fn start_exam(&self, req: &hyper::Request<Incoming>, quiz_id: &str) -> Result<AppResponse> {
+        let identity = learner_identity(
+            req.extensions().get::<Identity>().cloned(),
+            query_param(req, "learner").as_deref(),
+        );
+        let quiz = self
+            .quiz_manager
+            .read()
+            .get_quiz(quiz_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Quiz not found: {quiz_id}"))?;
+        self.exams
+            .write()
+            .start(&quiz, &identity, Utc::now().timestamp())?;
+        self.exam_status(&self.request_locale(req), quiz_id, &identity.username)
+    }
+
+    async fn submit_exam(
+        &self,
+        req: hyper::Request<Incoming>,
+        quiz_id: &str,
+    ) -> Result<AppResponse> {
+        let identity = req.extensions().get::<Identity>().cloned();
+        let locale = self.request_locale(&req);
+        let req_body = req.collect().await?.to_bytes();
+        let QuizSubmitReqBody { answers, learner } = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+        let identity = learner_identity(identity, learner.as_deref());
+
+        let events = {
+            let quiz_manager = self.quiz_manager.read();
+            let quiz = quiz_manager
+                .get_quiz(quiz_id)
+                .ok_or_else(|| anyhow!("Quiz not found: {quiz_id}"))?;
+            self.exams.write().submit(
+                &quiz_manager,
+                quiz,
+                &identity.username,
+                answers,
+                Utc::now().timestamp(),
+            )?
+        };
+        self.record_events(&events);
+        self.exam_status(&locale, quiz_id, &identity.username)
+    }
+
+    /// The learner's view of an exam, see [`ExamStatus`].
+    fn exam_status(&self, locale: &str, quiz_id: &str, learner: &str) -> Result<AppResponse> {
+        let quiz_manager = self.quiz_manager.read();
+        let quiz = quiz_manager
+            .get_quiz(quiz_id)
+            .filter(|v| v.exam.is_some())
+            .ok_or_else(|| anyhow!("Exam not found: {quiz_id}"))?;
+        let localized = quiz_manager
+            .get_localized_quiz(quiz_id, locale)
+            .unwrap_or(quiz);
+        let status = self
+            .exams
+            .read()
+            .status(quiz, localized, learner, Utc::now().timestamp());
+        let data = json!({ "data": status });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .header("Content-Language", locale)
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn exam_report(&self, quiz_id: &str) -> Result<AppResponse> {
+        let quiz_manager = self.quiz_manager.read();
+        let quiz = quiz_manager
+            .get_quiz(quiz_id)
+            .filter(|v| v.exam.is_some())
+            .ok_or_else(|| anyhow!("Exam not found: {quiz_id}"))?;
+        let report = self.exams.read().report(quiz, Utc::now().timestamp());
+        let data = json!({ "data": report });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn release_exam(&self, quiz_id: &str) -> Result<AppResponse> {
+        let quiz = self
+            .quiz_manager
+            .read()
+            .get_quiz(quiz_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Quiz not found: {quiz_id}"))?;
+        let events = self.exams.write().release(&quiz, Utc::now().timestamp())?;
+        self.record_events(&events);
+        self.exam_report(quiz_id)
+    }
+
+    fn record_events(&self, events: &[ActivityEvent]) {
+        for event in events {
+            if let Err(err) = self.activity.record(event) {
+                warn!("Failed to record quiz answer, {err}");
+            }
+        }
+    }
+
+    async fn execute_code(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let identity = req.extensions().get::<Identity>().cloned();
+        let req_body = req.collect().await?.to_bytes();
+        let req_body: Value = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+
+        debug!("execute code request: {req_body}");
+
+        let code = req_body["code"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Missing 'code' field"))?;
+
+        // Safety check
+        if !is_safe_code(code) {
             return Err(anyhow!("Code contains potentially unsafe operations"));
         }
 
-        let result = self.code_executor.execute_code(code).await
+        // Show what the compiler produces instead of running the code
+        if let Some(mode) = req_body.get("emit").filter(|v| !v.is_null()) {
+            let mode: EmitMode = serde_json::from_value(mode.clone())
+                .map_err(|err| anyhow!("Invalid emit mode, {err}"))?;
+            let result = self
+                .code_executor
+                .emit_code(
+                    code,
+                    mode,
+                    req_body["opt_level"].as_str().unwrap_or("0"),
+                    req_body["filter"].as_bool().unwrap_or(true),
+                )
+                .await?;
+            let data = json!({ "result": result });
+            let res = Response::builder()
+                .header("Content-Type", "application/json; charset=utf-8")
+                .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+            return Ok(res);
+        }
+
+        let result = self
+            .code_executor
+            .execute_code(code)
+            .await
             .map_err(|e| anyhow!("Execution failed: {}", e))?;
 
+        let identity = learner_identity(identity, req_body["learner"].as_str());
+        let lesson_id = req_body["lesson_id"].as_str();
+        let passed = result.exit_code == 0
+            && lesson_id
+                .and_then(|id| self.lesson_manager.read().get_lesson(id).cloned())
+                .is_some_and(|lesson| lesson.is_solved_by(&result.stdout));
+        let event = ActivityEvent::new(
+            &identity,
+            ActivityKind::CodeRun {
+                lesson_id: lesson_id.map(|v| v.to_string()),
+                exit_code: result.exit_code,
+                error_codes: extract_error_codes(&result.stderr),
+                code: (result.exit_code == 0).then(|| code.to_string()),
+                passed,
+            },
+        );
+        if let Err(err) = self.activity.record(&event) {
+            warn!("Failed to record code run, {err}");
+        }
+
         let data = json!({ "result": result });
         let res = Response::builder()
             .header("Content-Type", "application/json; charset=utf-8")
@@ -691,27 +1455,546 @@ impl Server {
         Ok(res)
     }
 
+    /// `/execute` and the legacy `/evaluate.json` of the Rust Playground.
+    async fn playground_execute(
+        &self,
+        req: hyper::Request<Incoming>,
+        evaluate: bool,
+    ) -> Result<AppResponse> {
+        let req_body = req.collect().await?.to_bytes();
+        let (code, options) = if evaluate {
+            let req_body: PlaygroundEvaluateRequest = serde_json::from_slice(&req_body)
+                .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+            let options = req_body.build_options();
+            (req_body.code, options)
+        } else {
+            let req_body: PlaygroundExecuteRequest = serde_json::from_slice(&req_body)
+                .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+            let options = req_body.build_options()?;
+            (req_body.code, options)
+        };
+
+        let data = if !is_safe_code(&code) {
+            match evaluate {
+                true => json!(PlaygroundEvaluateResponse::refused()),
+                false => json!(PlaygroundExecuteResponse::refused()),
+            }
+        } else {
+            let result = self
+                .code_executor
+                .execute_code_with_options(&code, None, &options)
+                .await?;
+            match evaluate {
+                true => json!(PlaygroundEvaluateResponse::from(result)),
+                false => json!(PlaygroundExecuteResponse::from(result)),
+            }
+        };
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    async fn playground_compile(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let req_body = req.collect().await?.to_bytes();
+        let req_body: PlaygroundCompileRequest = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+        let mode = req_body.emit_mode()?;
+        let build = req_body.build.build_options()?;
+
+        let data = if !is_safe_code(&req_body.build.code) {
+            json!(PlaygroundCompileResponse::refused())
+        } else {
+            let result = self
+                .code_executor
+                .emit_code_with_options(
+                    &req_body.build.code,
+                    mode,
+                    &build,
+                    &req_body.emit_options(),
+                )
+                .await?;
+            json!(PlaygroundCompileResponse::from(result))
+        };
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    async fn playground_format(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let req_body = req.collect().await?.to_bytes();
+        let req_body: PlaygroundFormatRequest = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+        let result = self
+            .code_executor
+            .format_code(&req_body.code, playground_edition(&req_body.edition))
+            .await?;
+        let data = json!(PlaygroundCompileResponse::from(result));
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    async fn playground_clippy(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let req_body = req.collect().await?.to_bytes();
+        let req_body: PlaygroundExecuteRequest = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+        let options = req_body.build_options()?;
+
+        // Clippy compiles the code, which is enough for `include_str!` to read server files
+        let data = if !is_safe_code(&req_body.code) {
+            json!(PlaygroundExecuteResponse::refused())
+        } else {
+            let result = self
+                .code_executor
+                .clippy_code(&req_body.code, &options)
+                .await?;
+            json!(PlaygroundExecuteResponse::from(result))
+        };
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    async fn search_course(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let req_body = req.collect().await?.to_bytes();
+        let SearchCourseReqBody {
+            query,
+            difficulty,
+            limit,
+        } = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+        let difficulty = difficulty
+            .filter(|v| !v.is_empty())
+            .map(|v| parse_difficulty(&v))
+            .transpose()?;
+
+        let hits = self
+            .course_index()
+            .search(&query, difficulty.as_ref(), limit.unwrap_or(20));
+
+        let data = json!({ "data": hits });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn course_index(&self) -> Arc<CourseIndex> {
+        if let Some(index) = self.course_index.read().as_ref() {
+            return index.clone();
+        }
+        let index = Arc::new(CourseIndex::build(
+            &self.lesson_manager.read(),
+            &self.quiz_manager.read(),
+        ));
+        *self.course_index.write() = Some(index.clone());
+        index
+    }
+
     async fn search_rust_docs(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
         let req_body = req.collect().await?.to_bytes();
         let req_body: Value = serde_json::from_slice(&req_body)
             .map_err(|err| anyhow!("Invalid request json, {err}"))?;
 
         debug!("search rust docs request: {req_body}");
-        
-        let query = req_body["query"].as_str()
+
+        let query = req_body["query"]
+            .as_str()
             .ok_or_else(|| anyhow!("Missing 'query' field"))?;
 
         let config = Arc::new(RwLock::new(self.config.clone()));
-        
-        let result = search_rust_docs(&config, query).await
-            .unwrap_or_else(|e| format!("Search failed: {}. Try running --setup-rust-docs first.", e));
 
-        let data = json!({ "result": result });
+        let hits = search_rust_docs(&config, query)
+            .await
+            .map_err(|e| anyhow!("Search failed: {e}. Try running --setup-rust-docs first."))?;
+
+        let data = json!({ "data": hits });
         let res = Response::builder()
             .header("Content-Type", "application/json; charset=utf-8")
             .body(Full::new(Bytes::from(data.to_string())).boxed())?;
         Ok(res)
     }
+
+    async fn tutor_chat(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let identity = req.extensions().get::<Identity>().cloned();
+        let req_body = req.collect().await?.to_bytes();
+        let req_body: Value = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
+
+        debug!("tutor chat request: {req_body}");
+        let TutorChatReqBody {
+            lesson_id,
+            message,
+            learner,
+            code,
+            errors,
+            model,
+            stream,
+        } = serde_json::from_value(req_body)
+            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
+
+        let lesson = self
+            .lesson_manager
+            .read()
+            .get_lesson(&lesson_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Lesson not found: {lesson_id}"))?;
+
+        let config = Arc::new(RwLock::new(self.config.clone()));
+        if let Some(model) = model.filter(|v| v != DEFAULT_MODEL_NAME) {
+            config.write().set_model(&model)?;
+        }
+
+        let learner = learner_identity(identity, learner.as_deref()).username;
+        let work = LearnerWork {
+            code: code.as_deref(),
+            errors: errors.as_deref(),
+        };
+        let session_name = use_tutor_session(&config, &learner, &lesson, &work)?;
+
+        let mut input = Input::from_str(&config, &message, None);
+        if let Some(rag) = self.rust_docs_rag(&config) {
+            match search_rust_docs_with(&rag, &message).await {
+                Ok(hits) => input.set_rag_context(rag.name(), &rust_docs_context(&hits)),
+                Err(err) => warn!("Failed to retrieve from the rust docs rag, {err}"),
+            }
+        }
+
+        let client = input.create_client()?;
+        let model_name = client.model().id();
+        let data = input.prepare_completion_data(client.model(), stream)?;
+
+        chat_completions_response(
+            client,
+            data,
+            model_name,
+            ChatApi::ChatCompletions {
+                include_usage: false,
+            },
+            None,
+            move |output, _, _| {
+                let ret = config
+                    .write()
+                    .after_chat_completion(&input, &output.text, &[]);
+                let ret = ret.and_then(|_| config.write().save_session(Some(&session_name)));
+                if let Err(err) = ret {
+                    error!("Failed to save tutor session '{session_name}', {err}");
+                }
+            },
+        )
+        .await
+    }
+
+    fn instructor_dashboard(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let report = self.cohort_report(req)?;
+        let data = json!({ "data": report });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn instructor_report(
+        &self,
+        req: &hyper::Request<Incoming>,
+        section: &str,
+    ) -> Result<AppResponse> {
+        let (section, is_csv) = match section.strip_suffix(".csv") {
+            Some(section) => (section, true),
+            None => (
+                section,
+                query_param(req, "format").as_deref() == Some("csv"),
+            ),
+        };
+        let section: ReportSection = section.parse()?;
+        let report = self.cohort_report(req)?;
+        let res = if is_csv {
+            Response::builder()
+                .header("Content-Type", "text/csv; charset=utf-8")
+                .header(
+                    hyper::header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.csv\"", section.as_str()),
+                )
+                .body(Full::new(Bytes::from(report.to_csv(section))).boxed())?
+        } else {
+            let data = match section {
+                ReportSection::Questions => json!({ "data": report.questions }),
+                ReportSection::Lessons => json!({ "data": report.lessons }),
+                ReportSection::Errors => json!({ "data": report.errors }),
+                ReportSection::Stuck => json!({ "data": report.stuck }),
+            };
+            Response::builder()
+                .header("Content-Type", "application/json; charset=utf-8")
+                .body(Full::new(Bytes::from(data.to_string())).boxed())?
+        };
+        Ok(res)
+    }
+
+    fn recommendations(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let learner = target_learner(
+            req.extensions().get::<Identity>(),
+            query_param(req, "learner"),
+            DEFAULT_LEARNER,
+        );
+        let events = self.activity.load(&ActivityFilter::default())?;
+
+        let lesson_manager = self.lesson_manager.read();
+        let quiz_manager = self.quiz_manager.read();
+        let recommender =
+            Recommender::new(lesson_manager.list_lessons(), quiz_manager.list_quizzes());
+        let progress = recommender.progress(&learner, &events);
+        let data = json!({
+            "learner": learner,
+            "data": recommender.recommend(&progress),
+        });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn locale_strings(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let locale = self.request_locale(req);
+        let locales = available_locales(&self.lesson_manager.read(), &self.quiz_manager.read());
+        let strings: Value = serde_json::from_str(ui_strings(&locale))?;
+        let data = json!({
+            "locale": locale,
+            "locales": locales,
+            "strings": strings,
+        });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .header("Content-Language", &locale)
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    /// The `locale` query param wins over the browser's `Accept-Language`.
+    fn request_locale<T>(&self, req: &hyper::Request<T>) -> String {
+        let locales = available_locales(&self.lesson_manager.read(), &self.quiz_manager.read());
+        let accept_language = req
+            .headers()
+            .get(hyper::header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok());
+        negotiate_locale(
+            query_param(req, "locale").as_deref(),
+            accept_language,
+            &locales,
+        )
+    }
+
+    fn list_badges(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let learner = target_learner(
+            req.extensions().get::<Identity>(),
+            query_param(req, "learner"),
+            DEFAULT_LEARNER,
+        );
+        let data = json!({
+            "learner": learner,
+            "data": self.badge_statuses(&learner)?,
+        });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn certificate_page(
+        &self,
+        req: &hyper::Request<Incoming>,
+        badge_id: &str,
+    ) -> Result<AppResponse> {
+        let learner = target_learner(
+            req.extensions().get::<Identity>(),
+            query_param(req, "learner"),
+            DEFAULT_LEARNER,
+        );
+        let status = self
+            .badge_statuses(&learner)?
+            .into_iter()
+            .find(|v| v.badge.id == badge_id)
+            .ok_or_else(|| anyhow!("Badge not found: {badge_id}"))?;
+        if status.earned_at.is_none() {
+            bail!("'{learner}' has not earned '{}' yet", status.badge.title);
+        }
+        let Some(record) = status.record else {
+            bail!("Certificates are only issued to signed-in learners");
+        };
+        let html = render_certificate_html(&record, &status.badge.description)?;
+        let res = Response::builder()
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(Full::new(Bytes::from(html)).boxed())?;
+        Ok(res)
+    }
+
+    async fn verify_certificate(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let req_body = req.collect().await?.to_bytes();
+        let record: CompletionRecord = serde_json::from_slice(&req_body)
+            .map_err(|err| anyhow!("Invalid completion record, {err}"))?;
+        // Anonymous requests must not create the key, without one nothing was ever issued
+        let status = if !Config::certificate_key_file().exists() {
+            "unverifiable"
+        } else if record.verify(&load_signing_key(false)?) {
+            "valid"
+        } else {
+            "invalid"
+        };
+        let data = json!({
+            "data": {
+                "valid": status == "valid",
+                "status": status,
+                "record": record,
+            }
+        });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn badge_statuses(&self, learner: &str) -> Result<Vec<BadgeStatus>> {
+        // Without accounts anyone can claim any name, so nothing is signed
+        let engine = BadgeEngine::init(self.auth.read().is_enabled())?;
+        let events = self.activity.load(&ActivityFilter::default())?;
+        let lesson_manager = self.lesson_manager.read();
+        let quiz_manager = self.quiz_manager.read();
+        let lessons = lesson_manager.list_lessons();
+        let quizzes = quiz_manager.list_quizzes();
+        let recommender = Recommender::new(lessons.clone(), quizzes.clone());
+        let progress = recommender.progress(learner, &events);
+        Ok(engine.evaluate(learner, &progress, &lessons, &quizzes))
+    }
+
+    fn export_progress(&self, req: &hyper::Request<Incoming>) -> Result<AppResponse> {
+        let learner = target_learner(
+            req.extensions().get::<Identity>(),
+            query_param(req, "learner"),
+            DEFAULT_LEARNER,
+        );
+        let lesson_ids: Vec<String> = self
+            .lesson_manager
+            .read()
+            .list_lessons()
+            .into_iter()
+            .map(|v| v.id.clone())
+            .collect();
+        let sessions_dir = self.config.sessions_dir();
+        let bundle = ProgressBundle::export(&learner, &self.activity, &sessions_dir, &lesson_ids)?;
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .header(
+                hyper::header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"progress-{}.json\"",
+                    sanitize_filename(&learner)
+                ),
+            )
+            .body(Full::new(Bytes::from(serde_json::to_string_pretty(&bundle)?)).boxed())?;
+        Ok(res)
+    }
+
+    async fn import_progress(&self, req: hyper::Request<Incoming>) -> Result<AppResponse> {
+        let learner = query_param(&req, "learner");
+        let req_body = req.collect().await?.to_bytes();
+        let bundle = ProgressBundle::parse(&req_body)?;
+
+        // Only instructors import, so the bundle's own learner is the default rather than them
+        let learner = learner.unwrap_or_else(|| bundle.learner.clone());
+        let cohort = self
+            .auth
+            .read()
+            .store()
+            .identity(&learner)
+            .and_then(|v| v.cohort);
+        let sessions_dir = self.config.sessions_dir();
+        let summary = bundle.import(&learner, cohort.as_deref(), &self.activity, &sessions_dir)?;
+        let data = json!({ "data": summary });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    /// Pairs of learners whose latest working code for the lesson looks alike. Like the other
+    /// `/api/instructor/*` routes it's closed unless an instructor account is signed in.
+    async fn code_similarity(
+        &self,
+        req: &hyper::Request<Incoming>,
+        lesson_id: &str,
+    ) -> Result<AppResponse> {
+        let method: SimilarityMethod = match query_param(req, "method") {
+            Some(value) => value.parse()?,
+            None => SimilarityMethod::default(),
+        };
+        let min_similarity = match query_param(req, "min_similarity") {
+            Some(value) => value
+                .parse()
+                .map_err(|_| anyhow!("Invalid min_similarity '{value}'"))?,
+            None => method.default_min_similarity(),
+        };
+        let boilerplate: Vec<String> = self
+            .lesson_manager
+            .read()
+            .get_lesson(lesson_id)
+            .ok_or_else(|| anyhow!("Lesson not found: {lesson_id}"))?
+            .code_examples
+            .iter()
+            .map(|v| v.code.clone())
+            .collect();
+        let filter = ActivityFilter::from_query(req.uri().query().unwrap_or_default())?;
+        let submissions = CodeSubmission::collect(&self.activity.load(&filter)?, lesson_id);
+        let embeddings = match method {
+            SimilarityMethod::Fingerprint => None,
+            SimilarityMethod::Embedding => {
+                let config = Arc::new(RwLock::new(self.config.clone()));
+                Some(embed_submissions(&config, &submissions).await?)
+            }
+        };
+        let boilerplate: Vec<&str> = boilerplate.iter().map(|v| v.as_str()).collect();
+        let report = SimilarityReport {
+            lesson_id: lesson_id.to_string(),
+            method,
+            min_similarity,
+            submissions: submissions.len(),
+            pairs: find_similar_submissions(
+                &submissions,
+                &boilerplate,
+                embeddings.as_deref(),
+                min_similarity,
+            ),
+        };
+        let data = json!({ "data": report });
+        let res = Response::builder()
+            .header("Content-Type", "application/json; charset=utf-8")
+            .body(Full::new(Bytes::from(data.to_string())).boxed())?;
+        Ok(res)
+    }
+
+    fn cohort_report(&self, req: &hyper::Request<Incoming>) -> Result<CohortReport> {
+        let filter = ActivityFilter::from_query(req.uri().query().unwrap_or_default())?;
+        let events = self.activity.load(&filter)?;
+        Ok(CohortReport::build(&events, &self.quiz_manager.read()))
+    }
+
+    fn rust_docs_rag(&self, config: &GlobalConfig) -> Option<Arc<Rag>> {
+        if let Some(rag) = self.rust_docs_rag.read().as_ref() {
+            return Some(rag.clone());
+        }
+        match load_rust_docs_rag(config) {
+            Ok(rag) => {
+                *self.rust_docs_rag.write() = rag.clone();
+                rag
+            }
+            Err(err) => {
+                warn!("Failed to load the rust docs rag, {err}");
+                None
+            }
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -729,9 +2012,93 @@ struct ChatCompletionsReqBody {
     max_tokens: Option<isize>,
     #[serde(default)]
     stream: bool,
+    stream_options: Option<StreamOptions>,
     tools: Option<Vec<Value>>,
 }
 
+#[derive(Debug, Deserialize)]
+struct ResponsesReqBody {
+    model: String,
+    input: ResponsesReqBodyInput,
+    instructions: Option<String>,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    max_output_tokens: Option<isize>,
+    #[serde(default)]
+    stream: bool,
+    tools: Option<Vec<Value>>,
+    previous_response_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ResponsesReqBodyInput {
+    Text(String),
+    Items(Vec<Value>),
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesReqBody {
+    model: String,
+    system: Option<Value>,
+    messages: Vec<Value>,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    max_tokens: Option<isize>,
+    #[serde(default)]
+    stream: bool,
+    tools: Option<Vec<Value>>,
+}
+
+/// A request from any of the `/v1` chat APIs, parsed into the shapes the clients take.
+struct ChatRequest {
+    api: ChatApi,
+    model: String,
+    messages: Vec<Message>,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    max_tokens: Option<isize>,
+    functions: Option<Vec<FunctionDeclaration>>,
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamOptions {
+    #[serde(default)]
+    include_usage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginReqBody {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchCourseReqBody {
+    query: String,
+    difficulty: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QuizSubmitReqBody {
+    answers: HashMap<String, UserAnswer>,
+    learner: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TutorChatReqBody {
+    lesson_id: String,
+    message: String,
+    learner: Option<String>,
+    code: Option<String>,
+    errors: Option<String>,
+    model: Option<String>,
+    #[serde(default)]
+    stream: bool,
+}
+
 #[derive(Debug, Deserialize)]
 struct EmbeddingsReqBody {
     input: EmbeddingsReqBodyInput,
@@ -758,7 +2125,8 @@ enum ResEvent {
     First(Option<String>),
     Text(String),
     ToolCalls(Vec<ToolCall>),
-    Done,
+    /// The usage chunk, when the request asked for one
+    Done(Option<Value>),
 }
 
 async fn shutdown_signal() {
@@ -767,15 +2135,162 @@ async fn shutdown_signal() {
         .expect("Failed to install CTRL+C signal handler")
 }
 
-fn generate_completion_id() -> String {
+fn generate_completion_id(prefix: &str) -> String {
     let random_id = chrono::Utc::now().nanosecond();
-    format!("chatcmpl-{random_id}")
+    format!("{prefix}{random_id}")
 }
 
-fn set_cors_header(res: &mut AppResponse) {
+fn required_role(method: &Method, path: &str) -> Option<AccountRole> {
+    match path {
+        // Employers checking a certificate don't have an account
+        "/api/login" | "/api/logout" | "/api/me" | "/api/certificates/verify" => None,
+        "/api/accounts" => Some(AccountRole::Admin),
+        // Bundles aren't signed, a learner could award themselves answers and certificates
+        "/api/lessons/preview" | "/api/lessons/reload" | "/api/progress/import" => {
+            Some(AccountRole::Instructor)
+        }
+        _ if path.starts_with("/api/lessons") && method != Method::GET => {
+            Some(AccountRole::Instructor)
+        }
+        _ if path.starts_with("/api/instructor/") => Some(AccountRole::Instructor),
+        _ if path.starts_with("/api/") || path.starts_with("/v1/") => Some(AccountRole::Learner),
+        // The play.rust-lang.org routes run code just like `/api/execute`
+        "/execute" | "/evaluate.json" | "/compile" | "/format" | "/clippy" => {
+            Some(AccountRole::Learner)
+        }
+        _ => None,
+    }
+}
+
+/// Routes that stay closed while authentication is disabled, as they change the course or
+/// show other learners' work. Exams too, a single attempt means nothing when anyone can
+/// pick another learner name.
+fn account_required(method: &Method, path: &str) -> bool {
+    path.starts_with("/api/exam/") || required_role(method, path) >= Some(AccountRole::Instructor)
+}
+
+/// The signed-in user, or the learner named in the request when authentication is disabled.
+fn learner_identity(identity: Option<Identity>, learner: Option<&str>) -> Identity {
+    identity.unwrap_or_else(|| Identity {
+        username: learner.unwrap_or(DEFAULT_LEARNER).to_string(),
+        role: AccountRole::Learner,
+        cohort: None,
+    })
+}
+
+/// The learner a progress request is about. Learners only get their own, instructors may
+/// name anyone with `?learner=`, as may anybody when authentication is disabled.
+fn target_learner(identity: Option<&Identity>, learner: Option<String>, fallback: &str) -> String {
+    match (identity, learner) {
+        (Some(identity), Some(learner)) if identity.role >= AccountRole::Instructor => learner,
+        (Some(identity), _) => identity.username.clone(),
+        (None, learner) => learner.unwrap_or_else(|| fallback.to_string()),
+    }
+}
+
+fn query_param<T>(req: &hyper::Request<T>, name: &str) -> Option<String> {
+    req.uri().query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+/// The 403 for a model the request's API key may not use.
+fn model_forbidden(api_key: Option<&VirtualApiKey>, model_id: &str) -> Option<String> {
+    let key = api_key.filter(|v| !v.allows_model(model_id))?;
+    Some(format!(
+        "API key '{}' may not use model '{model_id}'",
+        key.name
+    ))
+}
+
+fn forbid_model(api_key: Option<&VirtualApiKey>, model_id: &str) -> Option<AppResponse> {
+    model_forbidden(api_key, model_id).map(|err| ret_status_err(StatusCode::FORBIDDEN, err))
+}
+
+fn record_chat_usage(client: &dyn Client, usage: &TokenUsage, api_key_name: Option<&str>) {
+    let config = client.global_config().read();
+    let entry = UsageEntry::new(&config, UsageKind::Chat, client, usage);
+    record_usage(&config, &entry.with_api_key(api_key_name));
+}
+
+fn record_api_key_usage(
+    api_keys: &RwLock<ApiKeyGate>,
+    key: &VirtualApiKey,
+    usage: &TokenUsage,
+    cost: Option<f64>,
+) {
+    let now = Utc::now().timestamp();
+    if let Err(err) = api_keys.write().record(&key.name, usage, cost, now) {
+        warn!("Failed to record usage of API key '{}', {err}", key.name);
+    }
+}
+
+fn bearer_token<T>(req: &hyper::Request<T>) -> Option<&str> {
+    req.headers()
+        .get(hyper::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|v| v.trim())
+}
+
+/// Keep a name safe to put in a `Content-Disposition` header and on disk.
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Parse a JSON request body. Requiring the JSON content type makes browsers preflight
+/// cross-origin requests, so other sites can't submit it as a form or `text/plain`.
+async fn json_body<T: DeserializeOwned>(req: hyper::Request<Incoming>) -> Result<T> {
+    let is_json = header_value(&req, "content-type")
+        .is_some_and(|v| v.to_ascii_lowercase().starts_with("application/json"));
+    if !is_json {
+        bail!("Expected a request body with 'Content-Type: application/json'");
+    }
+    let req_body = req.collect().await?.to_bytes();
+    serde_json::from_slice(&req_body).map_err(|err| anyhow!("Invalid request body, {err}"))
+}
+
+fn header_value<'a, T>(req: &'a hyper::Request<T>, name: &str) -> Option<&'a str> {
+    req.headers().get(name)?.to_str().ok().map(|v| v.trim())
+}
+
+fn session_cookie<T>(req: &hyper::Request<T>) -> Option<&str> {
+    req.headers()
+        .get_all(hyper::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|v| {
+            let (name, value) = v.trim().split_once('=')?;
+            (name == SESSION_COOKIE_NAME).then_some(value)
+        })
+}
+
+/// Allow cross-origin calls from the configured `cors_origins` only, browsers keep other
+/// origins out because the headers are missing.
+fn set_cors_header(res: &mut AppResponse, origin: Option<&str>, allowed_origins: &[String]) {
+    let Some(origin) = origin.filter(|origin| {
+        allowed_origins
+            .iter()
+            .any(|v| v == "*" || v.trim_end_matches('/') == *origin)
+    }) else {
+        return;
+    };
+    let Ok(origin) = hyper::header::HeaderValue::from_str(origin) else {
+        return;
+    };
+    res.headers_mut()
+        .insert(hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
     res.headers_mut().insert(
-        hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        hyper::header::HeaderValue::from_static("*"),
+        hyper::header::VARY,
+        hyper::header::HeaderValue::from_static("Origin"),
     );
     res.headers_mut().insert(
         hyper::header::ACCESS_CONTROL_ALLOW_METHODS,
@@ -783,7 +2298,7 @@ fn set_cors_header(res: &mut AppResponse) {
     );
     res.headers_mut().insert(
         hyper::header::ACCESS_CONTROL_ALLOW_HEADERS,
-        hyper::header::HeaderValue::from_static("Content-Type,Authorization"),
+        hyper::header::HeaderValue::from_static("Content-Type,Authorization,x-api-key"),
     );
 }
 
@@ -856,7 +2371,13 @@ fn create_tool_calls_frame(
     Frame::data(Bytes::from(chunks))
 }
 
-fn create_done_frame(id: &str, model: &str, created: i64, has_tool_calls: bool) -> Frame<Bytes> {
+fn create_done_frame(
+    id: &str,
+    model: &str,
+    created: i64,
+    has_tool_calls: bool,
+    usage: Option<&Value>,
+) -> Frame<Bytes> {
     let finish_reason = if has_tool_calls { "tool_calls" } else { "stop" };
     let choice = json!({
         "index": 0,
@@ -864,7 +2385,20 @@ fn create_done_frame(id: &str, model: &str, created: i64, has_tool_calls: bool)
         "finish_reason": finish_reason,
     });
     let value = build_chat_completion_chunk_json(id, model, created, &choice);
-    Frame::data(Bytes::from(format!("data: {value}\n\ndata: [DONE]\n\n")))
+    let mut data = format!("data: {value}\n\n");
+    if let Some(usage) = usage {
+        let value = json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": [],
+            "usage": usage,
+        });
+        data.push_str(&format!("data: {value}\n\n"));
+    }
+    data.push_str("data: [DONE]\n\n");
+    Frame::data(Bytes::from(data))
 }
 
 fn build_chat_completion_chunk_json(id: &str, model: &str, created: i64, choice: &Value) -> Value {
@@ -877,11 +2411,172 @@ fn build_chat_completion_chunk_json(id: &str, model: &str, created: i64, choice:
     })
 }
 
-fn ret_non_stream(id: &str, model: &str, created: i64, output: &ChatCompletionsOutput) -> Bytes {
+async fn chat_completions_response<F>(
+    client: Box<dyn Client>,
+    data: ChatCompletionsData,
+    model_name: String,
+    api: ChatApi,
+    api_key_name: Option<String>,
+    on_complete: F,
+) -> Result<AppResponse>
+where
+    F: FnOnce(&ChatCompletionsOutput, &TokenUsage, Option<f64>) + Send + 'static,
+{
+    let abort_signal = create_abort_signal();
+    let http_client = client.build_client()?;
+    let estimated_input_tokens = client.model().total_tokens(&data.messages) as u64;
+
+    let completion_id = generate_completion_id(api.id_prefix());
+    let created = Utc::now().timestamp();
+
+    if data.stream {
+        let (tx, mut rx) = unbounded_channel();
+        tokio::spawn(async move {
+            let is_first = Arc::new(AtomicBool::new(true));
+            let (sse_tx, sse_rx) = unbounded_channel();
+            let handler = SseHandler::new(sse_tx, abort_signal);
+            async fn map_event(
+                mut sse_rx: UnboundedReceiver<SseEvent>,
+                tx: &UnboundedSender<ResEvent>,
+                is_first: Arc<AtomicBool>,
+            ) {
+                while let Some(reply_event) = sse_rx.recv().await {
+                    if is_first.load(Ordering::SeqCst) {
+                        let _ = tx.send(ResEvent::First(None));
+                        is_first.store(false, Ordering::SeqCst)
+                    }
+                    match reply_event {
+                        SseEvent::Text(text) => {
+                            let _ = tx.send(ResEvent::Text(text));
+                        }
+                        SseEvent::Done => {
+                            sse_rx.close();
+                        }
+                    }
+                }
+            }
+            async fn chat_completions(
+                client: &dyn Client,
+                http_client: &reqwest::Client,
+                mut handler: SseHandler,
+                mut data: ChatCompletionsData,
+                tx: &UnboundedSender<ResEvent>,
+                is_first: Arc<AtomicBool>,
+            ) -> Option<ChatCompletionsOutput> {
+                if client.model().no_stream() {
+                    data.stream = false;
+                    let ret = client.chat_completions_inner(http_client, data).await;
+                    let output = match ret {
+                        Ok(output) => {
+                            let _ = tx.send(ResEvent::First(None));
+                            is_first.store(false, Ordering::SeqCst);
+                            let _ = tx.send(ResEvent::Text(output.text.clone()));
+                            Some(output)
+                        }
+                        Err(err) => {
+                            let _ = tx.send(ResEvent::First(Some(format!("{err:?}"))));
+                            is_first.store(false, Ordering::SeqCst);
+                            None
+                        }
+                    };
+                    handler.done();
+                    output
+                } else {
+                    let ret = client
+                        .chat_completions_streaming_inner(http_client, &mut handler, data)
+                        .await;
+                    let first = match &ret {
+                        Ok(()) => None,
+                        Err(err) => Some(format!("{err:?}")),
+                    };
+                    if is_first.load(Ordering::SeqCst) {
+                        let _ = tx.send(ResEvent::First(first));
+                        is_first.store(false, Ordering::SeqCst)
+                    }
+                    handler.done();
+                    let (input_tokens, output_tokens) = handler.usage();
+                    let (text, tool_calls) = handler.take();
+                    ret.ok().map(|_| ChatCompletionsOutput {
+                        text,
+                        tool_calls,
+                        input_tokens,
+                        output_tokens,
+                        ..Default::default()
+                    })
+                }
+            }
+            let (_, output) = tokio::join!(
+                map_event(sse_rx, &tx, is_first.clone()),
+                chat_completions(client.as_ref(), &http_client, handler, data, &tx, is_first),
+            );
+            let usage = output
+                .as_ref()
+                .map(|output| output.usage(estimated_input_tokens));
+            // Sent after all text, which `map_event` has forwarded by now
+            if let Some(output) = output.as_ref().filter(|v| !v.tool_calls.is_empty()) {
+                let _ = tx.send(ResEvent::ToolCalls(output.tool_calls.clone()));
+            }
+            let usage_chunk = usage
+                .filter(|_| api.streams_usage())
+                .map(|usage| api.usage_json(&usage, &client.answered_model()));
+            let _ = tx.send(ResEvent::Done(usage_chunk));
+            if let (Some(output), Some(usage)) = (output, usage) {
+                record_chat_usage(client.as_ref(), &usage, api_key_name.as_deref());
+                on_complete(&output, &usage, usage.cost(&client.answered_model()));
+            }
+        });
+
+        let first_event = rx.recv().await;
+
+        if let Some(ResEvent::First(Some(err))) = first_event {
+            bail!("{err}");
+        }
+
+        let mut writer = ChatStreamWriter::new(api, completion_id, model_name, created);
+        let stream = UnboundedReceiverStream::new(rx);
+        let stream = stream.filter_map(move |res_event| {
+            let frame = writer.write(res_event).map(Ok);
+            async move { frame }
+        });
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .body(BodyExt::boxed(StreamBody::new(stream)))?;
+        Ok(res)
+    } else {
+        let output = client.chat_completions_inner(&http_client, data).await?;
+        let usage = output.usage(estimated_input_tokens);
+        record_chat_usage(client.as_ref(), &usage, api_key_name.as_deref());
+        on_complete(&output, &usage, usage.cost(&client.answered_model()));
+        let usage = api.usage_json(&usage, &client.answered_model());
+        let res_body = match api {
+            ChatApi::ChatCompletions { .. } => {
+                ret_non_stream(&completion_id, &model_name, created, &output, usage)
+            }
+            ChatApi::Responses => {
+                ret_responses_non_stream(&completion_id, &model_name, created, &output, usage)
+            }
+            ChatApi::Messages => {
+                ret_messages_non_stream(&completion_id, &model_name, &output, usage)
+            }
+        };
+        let res = Response::builder()
+            .header("Content-Type", "application/json")
+            .body(Full::new(res_body).boxed())?;
+        Ok(res)
+    }
+}
+
+fn ret_non_stream(
+    id: &str,
+    model: &str,
+    created: i64,
+    output: &ChatCompletionsOutput,
+    usage: Value,
+) -> Bytes {
     let id = output.id.as_deref().unwrap_or(id);
-    let input_tokens = output.input_tokens.unwrap_or_default();
-    let output_tokens = output.output_tokens.unwrap_or_default();
-    let total_tokens = input_tokens + output_tokens;
     let choice = if output.tool_calls.is_empty() {
         json!({
             "index": 0,
@@ -929,15 +2624,28 @@ fn ret_non_stream(id: &str, model: &str, created: i64, output: &ChatCompletionsO
         "created": created,
         "model": model,
         "choices": [choice],
-        "usage": {
-            "prompt_tokens": input_tokens,
-            "completion_tokens": output_tokens,
-            "total_tokens": total_tokens,
-        },
+        "usage": usage,
     });
     Bytes::from(res_body.to_string())
 }
 
+/// OpenAI's usage object, with the cost and whether the counts are estimated added.
+fn usage_json(usage: &TokenUsage, model: &Model) -> Value {
+    json!({
+        "prompt_tokens": usage.input_tokens,
+        "completion_tokens": usage.output_tokens,
+        "total_tokens": usage.total_tokens(),
+        "cost": usage.cost(model),
+        "estimated": usage.estimated,
+    })
+}
+
+fn ret_status_err<T: std::fmt::Display>(status: StatusCode, err: T) -> AppResponse {
+    let mut res = ret_err(err);
+    *res.status_mut() = status;
+    res
+}
+
 fn ret_err<T: std::fmt::Display>(err: T) -> AppResponse {
     let data = json!({
         "error": {
@@ -1020,7 +2728,7 @@ fn parse_messages(message: Vec<Value>) -> Result<Vec<Message>> {
                     if tool_calls.len() == tool_values.len() {
                         let mut list = vec![];
                         for ((id, name, arguments), (value, tool_call_id)) in
-                            tool_calls.into_iter().zip(tool_values.into_iter())
+                            tool_calls.into_iter().zip(tool_values)
                         {
                             if id != tool_call_id {
                                 return Err(err());
@@ -1071,3 +2779,34 @@ fn parse_tools(tools: Option<Vec<Value>>) -> Result<Option<Vec<FunctionDeclarati
     }
     Ok(Some(functions))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("alice.b-c_1"), "alice.b-c_1");
+        assert_eq!(sanitize_filename("a\"; x=\r\n/é"), "a___x_____");
+    }
+
+    #[test]
+    fn test_account_required() {
+        assert!(account_required(&Method::POST, "/api/lessons"));
+        assert!(account_required(&Method::DELETE, "/api/lessons/01-intro"));
+        assert!(account_required(&Method::POST, "/api/lessons/reload"));
+        assert!(account_required(&Method::GET, "/api/instructor/dashboard"));
+        assert!(account_required(&Method::POST, "/api/progress/import"));
+        assert!(account_required(&Method::GET, "/api/accounts"));
+        assert!(account_required(
+            &Method::POST,
+            "/api/exam/quiz-01-intro/start"
+        ));
+        assert!(account_required(
+            &Method::POST,
+            "/api/instructor/exams/quiz-01-intro/release"
+        ));
+        assert!(!account_required(&Method::GET, "/api/lessons"));
+        assert!(!account_required(&Method::POST, "/api/execute"));
+    }
+}
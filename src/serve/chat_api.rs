@@ -0,0 +1,946 @@
+use super::*;
+
+/// The `/v1` API a chat request came in through, which answers in its own shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ChatApi {
+    ChatCompletions { include_usage: bool },
+    Responses,
+    Messages,
+}
+
+impl ChatApi {
+    pub(super) fn id_prefix(&self) -> &'static str {
+        match self {
+            ChatApi::ChatCompletions { .. } => "chatcmpl-",
+            ChatApi::Responses => "resp_",
+            ChatApi::Messages => "msg_",
+        }
+    }
+
+    /// Chat completions only stream usage when asked to, the others always do.
+    pub(super) fn streams_usage(&self) -> bool {
+        match self {
+            ChatApi::ChatCompletions { include_usage } => *include_usage,
+            ChatApi::Responses | ChatApi::Messages => true,
+        }
+    }
+
+    pub(super) fn usage_json(&self, usage: &TokenUsage, model: &Model) -> Value {
+        match self {
+            ChatApi::ChatCompletions { .. } => usage_json(usage, model),
+            ChatApi::Responses => json!({
+                "input_tokens": usage.input_tokens,
+                "output_tokens": usage.output_tokens,
+                "total_tokens": usage.total_tokens(),
+                "cost": usage.cost(model),
+                "estimated": usage.estimated,
+            }),
+            ChatApi::Messages => json!({
+                "input_tokens": usage.input_tokens,
+                "output_tokens": usage.output_tokens,
+                "cost": usage.cost(model),
+                "estimated": usage.estimated,
+            }),
+        }
+    }
+
+    pub(super) fn ret_err<T: std::fmt::Display>(&self, status: StatusCode, err: T) -> AppResponse {
+        match self {
+            ChatApi::Messages => ret_messages_err(status, err),
+            _ => ret_status_err(status, err),
+        }
+    }
+}
+
+/// Turns the events of a streaming chat into the server-sent events of the API the request
+/// came in through. The Responses and Messages APIs open and close an output item around
+/// the text and each tool call, so the writer keeps track of what is open.
+pub(super) struct ChatStreamWriter {
+    api: ChatApi,
+    id: String,
+    model: String,
+    created: i64,
+    started: bool,
+    /// The text of the open output item
+    text: Option<String>,
+    /// The finished output items, as they go in the final response
+    items: Vec<Value>,
+    has_tool_calls: bool,
+    sequence_number: u64,
+}
+
+impl ChatStreamWriter {
+    pub(super) fn new(api: ChatApi, id: String, model: String, created: i64) -> Self {
+        Self {
+            api,
+            id,
+            model,
+            created,
+            started: false,
+            text: None,
+            items: vec![],
+            has_tool_calls: false,
+            sequence_number: 0,
+        }
+    }
+
+    pub(super) fn write(&mut self, event: ResEvent) -> Option<Frame<Bytes>> {
+        if let ChatApi::ChatCompletions { .. } = self.api {
+            return self.write_chat_completions(event);
+        }
+        let mut events = vec![];
+        if !self.started {
+            self.started = true;
+            events.push(self.start_event());
+        }
+        match event {
+            ResEvent::First(_) => {}
+            ResEvent::Text(text) => self.push_text(&text, &mut events),
+            ResEvent::ToolCalls(tool_calls) => {
+                self.close_text(&mut events);
+                self.has_tool_calls = true;
+                for tool_call in &tool_calls {
+                    self.push_tool_call(tool_call, &mut events);
+                }
+            }
+            ResEvent::Done(usage) => {
+                self.close_text(&mut events);
+                self.push_done(usage, &mut events);
+            }
+        }
+        if events.is_empty() {
+            return None;
+        }
+        let mut data = String::new();
+        for (event, mut value) in events {
+            value["type"] = event.into();
+            if self.api == ChatApi::Responses {
+                value["sequence_number"] = self.sequence_number.into();
+                self.sequence_number += 1;
+            }
+            data.push_str(&format!("event: {event}\ndata: {value}\n\n"));
+        }
+        Some(Frame::data(Bytes::from(data)))
+    }
+
+    fn write_chat_completions(&mut self, event: ResEvent) -> Option<Frame<Bytes>> {
+        let (id, model, created) = (&self.id, &self.model, self.created);
+        match event {
+            ResEvent::Text(text) => Some(create_text_frame(id, model, created, &text)),
+            ResEvent::ToolCalls(tool_calls) => {
+                self.has_tool_calls = true;
+                Some(create_tool_calls_frame(id, model, created, &tool_calls))
+            }
+            ResEvent::Done(usage) => Some(create_done_frame(
+                id,
+                model,
+                created,
+                self.has_tool_calls,
+                usage.as_ref(),
+            )),
+            ResEvent::First(_) => None,
+        }
+    }
+
+    fn start_event(&self) -> (&'static str, Value) {
+        match self.api {
+            ChatApi::Responses => (
+                "response.created",
+                json!({ "response": self.response_json("in_progress", Value::Null) }),
+            ),
+            _ => (
+                "message_start",
+                json!({
+                    "message": {
+                        "id": self.id,
+                        "type": "message",
+                        "role": "assistant",
+                        "model": self.model,
+                        "content": [],
+                        "stop_reason": null,
+                        "stop_sequence": null,
+                        "usage": { "input_tokens": 0, "output_tokens": 0 },
+                    }
+                }),
+            ),
+        }
+    }
+
+    fn push_text(&mut self, text: &str, events: &mut Vec<(&'static str, Value)>) {
+        if text.is_empty() {
+            return;
+        }
+        let index = self.items.len();
+        let item_id = self.item_id("msg");
+        if self.text.is_none() {
+            self.text = Some(String::new());
+            match self.api {
+                ChatApi::Responses => {
+                    events.push((
+                        "response.output_item.added",
+                        json!({
+                            "output_index": index,
+                            "item": {
+                                "id": item_id,
+                                "type": "message",
+                                "status": "in_progress",
+                                "role": "assistant",
+                                "content": [],
+                            },
+                        }),
+                    ));
+                    events.push((
+                        "response.content_part.added",
+                        json!({
+                            "item_id": item_id,
+                            "output_index": index,
+                            "content_index": 0,
+                            "part": { "type": "output_text", "text": "", "annotations": [] },
+                        }),
+                    ));
+                }
+                _ => events.push((
+                    "content_block_start",
+                    json!({
+                        "index": index,
+                        "content_block": { "type": "text", "text": "" },
+                    }),
+                )),
+            }
+        }
+        if let Some(open_text) = self.text.as_mut() {
+            open_text.push_str(text);
+        }
+        match self.api {
+            ChatApi::Responses => events.push((
+                "response.output_text.delta",
+                json!({
+                    "item_id": item_id,
+                    "output_index": index,
+                    "content_index": 0,
+                    "delta": text,
+                }),
+            )),
+            _ => events.push((
+                "content_block_delta",
+                json!({
+                    "index": index,
+                    "delta": { "type": "text_delta", "text": text },
+                }),
+            )),
+        }
+    }
+
+    fn close_text(&mut self, events: &mut Vec<(&'static str, Value)>) {
+        let Some(text) = self.text.take() else {
+            return;
+        };
+        let index = self.items.len();
+        let item = match self.api {
+            ChatApi::Responses => {
+                let item_id = self.item_id("msg");
+                let part = json!({ "type": "output_text", "text": text, "annotations": [] });
+                events.push((
+                    "response.output_text.done",
+                    json!({
+                        "item_id": item_id,
+                        "output_index": index,
+                        "content_index": 0,
+                        "text": text,
+                    }),
+                ));
+                events.push((
+                    "response.content_part.done",
+                    json!({
+                        "item_id": item_id,
+                        "output_index": index,
+                        "content_index": 0,
+                        "part": part,
+                    }),
+                ));
+                let item = json!({
+                    "id": item_id,
+                    "type": "message",
+                    "status": "completed",
+                    "role": "assistant",
+                    "content": [part],
+                });
+                events.push((
+                    "response.output_item.done",
+                    json!({ "output_index": index, "item": item }),
+                ));
+                item
+            }
+            _ => {
+                events.push(("content_block_stop", json!({ "index": index })));
+                json!({ "type": "text", "text": text })
+            }
+        };
+        self.items.push(item);
+    }
+
+    fn push_tool_call(&mut self, tool_call: &ToolCall, events: &mut Vec<(&'static str, Value)>) {
+        let index = self.items.len();
+        let call_id = tool_call_id(tool_call, index);
+        let arguments = tool_call.arguments.to_string();
+        let item = match self.api {
+            ChatApi::Responses => {
+                let item_id = self.item_id("fc");
+                let item = json!({
+                    "id": item_id,
+                    "type": "function_call",
+                    "status": "completed",
+                    "call_id": call_id,
+                    "name": tool_call.name,
+                    "arguments": arguments,
+                });
+                let mut added = item.clone();
+                added["status"] = "in_progress".into();
+                added["arguments"] = "".into();
+                events.push((
+                    "response.output_item.added",
+                    json!({ "output_index": index, "item": added }),
+                ));
+                events.push((
+                    "response.function_call_arguments.delta",
+                    json!({ "item_id": item_id, "output_index": index, "delta": arguments }),
+                ));
+                events.push((
+                    "response.function_call_arguments.done",
+                    json!({ "item_id": item_id, "output_index": index, "arguments": arguments }),
+                ));
+                events.push((
+                    "response.output_item.done",
+                    json!({ "output_index": index, "item": item }),
+                ));
+                item
+            }
+            _ => {
+                events.push((
+                    "content_block_start",
+                    json!({
+                        "index": index,
+                        "content_block": {
+                            "type": "tool_use",
+                            "id": call_id,
+                            "name": tool_call.name,
+                            "input": {},
+                        },
+                    }),
+                ));
+                events.push((
+                    "content_block_delta",
+                    json!({
+                        "index": index,
+                        "delta": { "type": "input_json_delta", "partial_json": arguments },
+                    }),
+                ));
+                events.push(("content_block_stop", json!({ "index": index })));
+                json!({
+                    "type": "tool_use",
+                    "id": call_id,
+                    "name": tool_call.name,
+                    "input": tool_call.arguments,
+                })
+            }
+        };
+        self.items.push(item);
+    }
+
+    /// End the response. Without usage the model call failed, which these APIs report with
+    /// an error event instead of a normal end.
+    fn push_done(&mut self, usage: Option<Value>, events: &mut Vec<(&'static str, Value)>) {
+        const FAILED: &str = "The model failed to complete the response";
+        match (self.api, usage) {
+            (ChatApi::Responses, Some(usage)) => events.push((
+                "response.completed",
+                json!({ "response": self.response_json("completed", usage) }),
+            )),
+            (ChatApi::Responses, None) => {
+                let mut response = self.response_json("failed", Value::Null);
+                response["error"] = json!({ "code": "server_error", "message": FAILED });
+                events.push(("response.failed", json!({ "response": response })));
+            }
+            (_, Some(usage)) => {
+                let stop_reason = if self.has_tool_calls {
+                    "tool_use"
+                } else {
+                    "end_turn"
+                };
+                events.push((
+                    "message_delta",
+                    json!({
+                        "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+                        "usage": usage,
+                    }),
+                ));
+                events.push(("message_stop", json!({})));
+            }
+            (_, None) => events.push((
+                "error",
+                json!({ "error": { "type": "api_error", "message": FAILED } }),
+            )),
+        }
+    }
+
+    fn response_json(&self, status: &str, usage: Value) -> Value {
+        json!({
+            "id": self.id,
+            "object": "response",
+            "created_at": self.created,
+            "status": status,
+            "model": self.model,
+            "output": self.items,
+            "usage": usage,
+        })
+    }
+
+    fn item_id(&self, prefix: &str) -> String {
+        format!("{prefix}_{}_{}", self.id, self.items.len())
+    }
+}
+
+pub(super) fn ret_responses_non_stream(
+    id: &str,
+    model: &str,
+    created: i64,
+    output: &ChatCompletionsOutput,
+    usage: Value,
+) -> Bytes {
+    let mut items = vec![];
+    if !output.text.is_empty() {
+        items.push(json!({
+            "id": format!("msg_{id}_0"),
+            "type": "message",
+            "status": "completed",
+            "role": "assistant",
+            "content": [{ "type": "output_text", "text": output.text, "annotations": [] }],
+        }));
+    }
+    for call in &output.tool_calls {
+        let index = items.len();
+        items.push(json!({
+            "id": format!("fc_{id}_{index}"),
+            "type": "function_call",
+            "status": "completed",
+            "call_id": tool_call_id(call, index),
+            "name": call.name,
+            "arguments": call.arguments.to_string(),
+        }));
+    }
+    let res_body = json!({
+        "id": id,
+        "object": "response",
+        "created_at": created,
+        "status": "completed",
+        "model": model,
+        "output": items,
+        "usage": usage,
+    });
+    Bytes::from(res_body.to_string())
+}
+
+pub(super) fn ret_messages_non_stream(
+    id: &str,
+    model: &str,
+    output: &ChatCompletionsOutput,
+    usage: Value,
+) -> Bytes {
+    let mut content = vec![];
+    if !output.text.is_empty() {
+        content.push(json!({ "type": "text", "text": output.text }));
+    }
+    for call in &output.tool_calls {
+        content.push(json!({
+            "type": "tool_use",
+            "id": tool_call_id(call, content.len()),
+            "name": call.name,
+            "input": call.arguments,
+        }));
+    }
+    let stop_reason = if output.tool_calls.is_empty() {
+        "end_turn"
+    } else {
+        "tool_use"
+    };
+    let res_body = json!({
+        "id": id,
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop_reason,
+        "stop_sequence": null,
+        "usage": usage,
+    });
+    Bytes::from(res_body.to_string())
+}
+
+/// Not every provider gives tool calls an id, but the Responses and Messages APIs need one
+/// to match the results to the calls.
+fn tool_call_id(call: &ToolCall, index: usize) -> String {
+    call.id.clone().unwrap_or_else(|| format!("call_{index}"))
+}
+
+/// An error in the shape of Anthropic's API, for `/v1/messages`.
+pub(super) fn ret_messages_err<T: std::fmt::Display>(status: StatusCode, err: T) -> AppResponse {
+    let error_type = match status {
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        status if status.is_server_error() => "api_error",
+        _ => "invalid_request_error",
+    };
+    let data = json!({
+        "type": "error",
+        "error": {
+            "type": error_type,
+            "message": err.to_string(),
+        },
+    });
+    let mut res = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(data.to_string())).boxed())
+        .unwrap();
+    *res.status_mut() = status;
+    res
+}
+
+/// Rewrite the `input` items of a Responses request as chat completions messages.
+pub(super) fn convert_responses_input(items: Vec<Value>) -> Result<Vec<Value>> {
+    let mut messages: Vec<Value> = vec![];
+    for (i, item) in items.into_iter().enumerate() {
+        let err = || anyhow!("Failed to parse '.input[{i}]'");
+        match item["type"].as_str().unwrap_or("message") {
+            "message" => {
+                let role = match item["role"].as_str() {
+                    Some("system" | "developer") => "system",
+                    Some(role @ ("user" | "assistant")) => role,
+                    _ => return Err(err()),
+                };
+                let content = convert_responses_content(&item["content"]).ok_or_else(err)?;
+                messages.push(json!({ "role": role, "content": content }));
+            }
+            "function_call" => {
+                let (Some(call_id), Some(name), Some(arguments)) = (
+                    item["call_id"].as_str(),
+                    item["name"].as_str(),
+                    item["arguments"].as_str(),
+                ) else {
+                    return Err(err());
+                };
+                let tool_call = json!({
+                    "id": call_id,
+                    "type": "function",
+                    "function": { "name": name, "arguments": arguments },
+                });
+                // The calls of a turn follow its message, if the model said anything
+                match messages.last_mut() {
+                    Some(message) if message["role"] == "assistant" => {
+                        match message["tool_calls"].as_array_mut() {
+                            Some(tool_calls) => tool_calls.push(tool_call),
+                            None => message["tool_calls"] = json!([tool_call]),
+                        }
+                    }
+                    _ => messages.push(json!({
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [tool_call],
+                    })),
+                }
+            }
+            "function_call_output" => {
+                let output = match &item["output"] {
+                    Value::String(output) => output.clone(),
+                    output => output.to_string(),
+                };
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": item["call_id"],
+                    "content": output,
+                }));
+            }
+            "reasoning" => {}
+            _ => return Err(err()),
+        }
+    }
+    Ok(messages)
+}
+
+fn convert_responses_content(content: &Value) -> Option<Value> {
+    if content.is_string() {
+        return Some(content.clone());
+    }
+    let mut parts = vec![];
+    for part in content.as_array()? {
+        match part["type"].as_str()? {
+            "input_text" | "output_text" | "text" => {
+                parts.push(json!({ "type": "text", "text": part["text"].as_str()? }))
+            }
+            "refusal" => parts.push(json!({ "type": "text", "text": part["refusal"].as_str()? })),
+            "input_image" => parts.push(json!({
+                "type": "image_url",
+                "image_url": { "url": part["image_url"].as_str()? },
+            })),
+            _ => return None,
+        }
+    }
+    Some(parts.into())
+}
+
+/// Rewrite the function tools of a Responses request as chat completions tools. The hosted
+/// tools, like web search, have no equivalent.
+pub(super) fn convert_responses_tools(tools: Vec<Value>) -> Result<Vec<Value>> {
+    tools
+        .into_iter()
+        .enumerate()
+        .map(
+            |(i, tool)| match (tool["type"].as_str(), tool["name"].as_str()) {
+                (Some("function"), Some(name)) => Ok(function_tool(
+                    name,
+                    &tool["description"],
+                    &tool["parameters"],
+                )),
+                (Some(tool_type), _) => {
+                    bail!("Unsupported tool type '{tool_type}' in '.tools[{i}]'")
+                }
+                _ => bail!("Failed to parse '.tools[{i}]'"),
+            },
+        )
+        .collect()
+}
+
+/// Rewrite the system prompt and messages of an Anthropic Messages request as chat
+/// completions messages. Tool results in a user message become tool messages.
+pub(super) fn convert_anthropic_messages(
+    system: Option<Value>,
+    messages: Vec<Value>,
+) -> Result<Vec<Value>> {
+    let mut output = vec![];
+    match system {
+        Some(Value::String(text)) => output.push(json!({ "role": "system", "content": text })),
+        Some(Value::Array(blocks)) => {
+            let text = blocks
+                .iter()
+                .map(|v| {
+                    v["text"]
+                        .as_str()
+                        .ok_or_else(|| anyhow!("Failed to parse '.system'"))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join("\n\n");
+            output.push(json!({ "role": "system", "content": text }));
+        }
+        Some(Value::Null) | None => {}
+        Some(_) => bail!("Failed to parse '.system'"),
+    }
+    for (i, message) in messages.into_iter().enumerate() {
+        let err = || anyhow!("Failed to parse '.messages[{i}]'");
+        let role = match message["role"].as_str() {
+            Some(role @ ("user" | "assistant")) => role,
+            _ => return Err(err()),
+        };
+        let blocks = match &message["content"] {
+            Value::String(text) => {
+                output.push(json!({ "role": role, "content": text }));
+                continue;
+            }
+            Value::Array(blocks) => blocks,
+            _ => return Err(err()),
+        };
+        let mut parts = vec![];
+        let mut tool_calls = vec![];
+        for block in blocks {
+            match (role, block["type"].as_str()) {
+                (_, Some("text")) => {
+                    parts.push(json!({ "type": "text", "text": block["text"] }));
+                }
+                (_, Some("image")) => {
+                    let source = &block["source"];
+                    let url = match source["type"].as_str() {
+                        Some("base64") => format!(
+                            "data:{};base64,{}",
+                            source["media_type"].as_str().ok_or_else(err)?,
+                            source["data"].as_str().ok_or_else(err)?
+                        ),
+                        Some("url") => source["url"].as_str().ok_or_else(err)?.to_string(),
+                        _ => return Err(err()),
+                    };
+                    parts.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+                }
+                ("assistant", Some("tool_use")) => tool_calls.push(json!({
+                    "id": block["id"],
+                    "type": "function",
+                    "function": {
+                        "name": block["name"],
+                        "arguments": block["input"].to_string(),
+                    },
+                })),
+                ("user", Some("tool_result")) => {
+                    let content = match &block["content"] {
+                        Value::String(text) => text.clone(),
+                        Value::Array(blocks) => blocks
+                            .iter()
+                            .filter_map(|v| v["text"].as_str())
+                            .collect::<Vec<_>>()
+                            .join("\n\n"),
+                        _ => String::new(),
+                    };
+                    output.push(json!({
+                        "role": "tool",
+                        "tool_call_id": block["tool_use_id"],
+                        "content": content,
+                    }));
+                }
+                (_, Some("thinking" | "redacted_thinking")) => {}
+                _ => return Err(err()),
+            }
+        }
+        if !tool_calls.is_empty() {
+            output.push(json!({ "role": role, "content": parts, "tool_calls": tool_calls }));
+        } else if !parts.is_empty() {
+            output.push(json!({ "role": role, "content": parts }));
+        }
+    }
+    Ok(output)
+}
+
+/// Rewrite the tools of an Anthropic Messages request as chat completions tools. The
+/// server tools, like web search, have no equivalent.
+pub(super) fn convert_anthropic_tools(tools: Vec<Value>) -> Result<Vec<Value>> {
+    tools
+        .into_iter()
+        .enumerate()
+        .map(
+            |(i, tool)| match (tool["type"].as_str(), tool["name"].as_str()) {
+                (None | Some("custom"), Some(name)) => Ok(function_tool(
+                    name,
+                    &tool["description"],
+                    &tool["input_schema"],
+                )),
+                (Some(tool_type), _) => {
+                    bail!("Unsupported tool type '{tool_type}' in '.tools[{i}]'")
+                }
+                _ => bail!("Failed to parse '.tools[{i}]'"),
+            },
+        )
+        .collect()
+}
+
+fn function_tool(name: &str, description: &Value, parameters: &Value) -> Value {
+    let parameters = if parameters.is_object() {
+        parameters.clone()
+    } else {
+        json!({ "type": "object", "properties": {} })
+    };
+    json!({
+        "type": "function",
+        "function": {
+            "name": name,
+            "description": description.as_str().unwrap_or_default(),
+            "parameters": parameters,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write the events and return the server-sent events, as names and data.
+    fn stream(api: ChatApi, events: Vec<ResEvent>) -> Vec<(String, Value)> {
+        let mut writer = ChatStreamWriter::new(api, "resp_1".into(), "test".into(), 0);
+        let mut output = vec![];
+        for event in events {
+            let Some(frame) = writer.write(event) else {
+                continue;
+            };
+            let data = frame.into_data().unwrap();
+            for chunk in String::from_utf8_lossy(&data).split_terminator("\n\n") {
+                let (name, data) = match chunk.split_once('\n') {
+                    Some((event, data)) => (event.trim_start_matches("event: "), data),
+                    None => ("", chunk),
+                };
+                let data = data.trim_start_matches("data: ");
+                let data = serde_json::from_str(data).unwrap_or_else(|_| data.into());
+                output.push((name.to_string(), data));
+            }
+        }
+        output
+    }
+
+    fn events() -> Vec<ResEvent> {
+        let tool_call = ToolCall::new(
+            "get_weather".into(),
+            json!({"city": "Paris"}),
+            Some("call_1".into()),
+        );
+        vec![
+            ResEvent::First(None),
+            ResEvent::Text("Let me ".into()),
+            ResEvent::Text("check".into()),
+            ResEvent::ToolCalls(vec![tool_call]),
+            ResEvent::Done(Some(json!({"input_tokens": 9, "output_tokens": 4}))),
+        ]
+    }
+
+    fn names(output: &[(String, Value)]) -> Vec<&str> {
+        output.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_stream_responses() {
+        let output = stream(ChatApi::Responses, events());
+        assert_eq!(
+            names(&output),
+            [
+                "response.created",
+                "response.output_item.added",
+                "response.content_part.added",
+                "response.output_text.delta",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.content_part.done",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                "response.function_call_arguments.done",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+        for (i, (name, data)) in output.iter().enumerate() {
+            assert_eq!(data["type"], name.as_str());
+            assert_eq!(data["sequence_number"], i);
+        }
+        // The text is the first output item, the tool call the second
+        assert_eq!(output[3].1["output_index"], 0);
+        assert_eq!(output[5].1["text"], "Let me check");
+        assert_eq!(output[8].1["output_index"], 1);
+        assert_eq!(output[8].1["item"]["call_id"], "call_1");
+        let response = &output[12].1["response"];
+        assert_eq!(response["status"], "completed");
+        assert_eq!(response["output"].as_array().unwrap().len(), 2);
+        assert_eq!(response["usage"]["input_tokens"], 9);
+
+        // Without usage the call failed
+        let output = stream(
+            ChatApi::Responses,
+            vec![ResEvent::Text("Hi".into()), ResEvent::Done(None)],
+        );
+        let (name, data) = output.last().unwrap();
+        assert_eq!(name, "response.failed");
+        assert_eq!(data["response"]["status"], "failed");
+        assert_eq!(data["response"]["output"][0]["content"][0]["text"], "Hi");
+    }
+
+    #[test]
+    fn test_stream_messages() {
+        let output = stream(ChatApi::Messages, events());
+        assert_eq!(
+            names(&output),
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        let indices: Vec<_> = output[1..8].iter().map(|(_, v)| &v["index"]).collect();
+        assert_eq!(indices, [0, 0, 0, 0, 1, 1, 1]);
+        assert!(output
+            .iter()
+            .all(|(_, v)| v.get("sequence_number").is_none()));
+        assert_eq!(output[5].1["content_block"]["id"], "call_1");
+        assert_eq!(output[6].1["delta"]["partial_json"], r#"{"city":"Paris"}"#);
+        assert_eq!(output[8].1["delta"]["stop_reason"], "tool_use");
+        assert_eq!(output[8].1["usage"]["output_tokens"], 4);
+
+        let output = stream(
+            ChatApi::Messages,
+            vec![ResEvent::Text("Hi".into()), ResEvent::Done(None)],
+        );
+        assert_eq!(
+            names(&output),
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "error",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_chat_completions() {
+        let api = ChatApi::ChatCompletions {
+            include_usage: true,
+        };
+        let output = stream(api, events());
+        assert!(names(&output).iter().all(|v| v.is_empty()));
+        let deltas: Vec<_> = output
+            .iter()
+            .map(|(_, v)| &v["choices"][0]["delta"])
+            .collect();
+        assert_eq!(deltas[0]["content"], "Let me ");
+        assert_eq!(deltas[2]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(output[4].1["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(output[5].1["usage"]["input_tokens"], 9);
+        assert_eq!(output[6].1, "[DONE]");
+
+        let output = stream(api, vec![ResEvent::Done(None)]);
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[1].1, "[DONE]");
+    }
+
+    #[test]
+    fn test_convert_chat_apis() {
+        let input = serde_json::from_str(
+            r#"[{"role":"developer","content":"Be brief"},
+            {"role":"user","content":[{"type":"input_text","text":"weather?"}]},
+            {"type":"message","role":"assistant","content":[{"type":"output_text","text":"Checking"}]},
+            {"type":"function_call","call_id":"call_1","name":"get_weather","arguments":"{\"city\":\"Paris\"}"},
+            {"type":"function_call_output","call_id":"call_1","output":"sunny"}]"#,
+        )
+        .unwrap();
+        let messages = parse_messages(convert_responses_input(input).unwrap()).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[2].content, MessageContent::ToolCalls(_)));
+
+        let messages = serde_json::from_str(
+            r#"[{"role":"user","content":"weather?"},
+            {"role":"assistant","content":[{"type":"text","text":"Checking"},
+                {"type":"tool_use","id":"toolu_1","name":"get_weather","input":{"city":"Paris"}}]},
+            {"role":"user","content":[{"type":"tool_result","tool_use_id":"toolu_1","content":"sunny"},
+                {"type":"text","text":"And tomorrow?"}]}]"#,
+        )
+        .unwrap();
+        let messages =
+            convert_anthropic_messages(Some(json!("Be brief")), messages).and_then(parse_messages);
+        let roles: Vec<_> = messages.unwrap().iter().map(|v| v.role).collect();
+        assert_eq!(
+            roles,
+            [
+                MessageRole::System,
+                MessageRole::User,
+                MessageRole::Assistant,
+                MessageRole::User
+            ]
+        );
+
+        let tools = convert_anthropic_tools(vec![json!({"name": "get_weather"})]).unwrap();
+        assert_eq!(
+            parse_tools(Some(tools)).unwrap().unwrap()[0].name,
+            "get_weather"
+        );
+        assert!(convert_responses_tools(vec![json!({"type": "web_search"})]).is_err());
+    }
+}
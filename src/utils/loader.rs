@@ -25,6 +25,16 @@ impl LoadedDocument {
             metadata,
         }
     }
+
+    /// Convert an HTML document to markdown, leaving any other document as it is.
+    pub fn html_to_md(mut self) -> Self {
+        let extension = self.metadata.get(EXTENSION_METADATA).map(|v| v.as_str());
+        if matches!(extension, Some("htm" | "html")) {
+            self.contents = html_to_md(&self.contents);
+            self.metadata.insert(EXTENSION_METADATA.into(), "md".into());
+        }
+        self
+    }
 }
 
 pub async fn load_recursive_url(
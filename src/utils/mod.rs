@@ -86,7 +86,7 @@ pub fn estimate_token_length(text: &str) -> usize {
     output.ceil() as usize
 }
 
-pub fn strip_think_tag(text: &str) -> Cow<str> {
+pub fn strip_think_tag(text: &str) -> Cow<'_, str> {
     THINK_TAG_RE.replace_all(text, "")
 }
 
@@ -118,7 +118,7 @@ where
             Some((v, score))
         })
         .collect();
-    list.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+    list.sort_unstable_by_key(|v| std::cmp::Reverse(v.1));
     list.into_iter().map(|(v, _)| v).collect()
 }
 